@@ -66,6 +66,10 @@ impl<S: SignedPrimitive, T> AllocationCount<S, T> {
             return Ok(());
         }
         let new_layout = Self::layout_of(new_capacity)?;
+        if testing_allocation_fails() {
+            cold();
+            return ContainerError::OutOfMemory.err();
+        }
         let new_ptr = unsafe {
             if old_capacity.is_positive() {
                 alloc::realloc(
@@ -95,6 +99,27 @@ impl<S: SignedPrimitive, T> AllocationCount<S, T> {
         }
     }
 
+    /// Moves the first `count` (initialized) elements into `other`, frees our memory,
+    /// and uses `other`'s memory from then on.  Panics if `other` can't hold `count`
+    /// elements.  Useful for changing capacity without `realloc`, e.g., to allocate
+    /// a few buffers before committing to any of them.
+    pub fn replace_with(&mut self, other: Self, count: Count<S>) {
+        assert!(
+            count <= other.capacity(),
+            "other should have room for all elements"
+        );
+        let mut other = other;
+        if count.is_positive() {
+            unsafe {
+                std::ptr::copy_nonoverlapping(self.as_ptr(), other.as_ptr_mut(), count.to_usize());
+            }
+        }
+        // The moved elements are now considered uninitialized here.
+        self.set_capacity(Count::<S>::default())
+            .expect("freeing should not fail");
+        *self = other;
+    }
+
     /// Writes to an offset that should *not* be initialized until *after* this call.
     /// I.e., it is uninitialized before calling this.
     pub fn write_initializing(&mut self, offset: Offset<S>, value: T) -> Containered {
@@ -154,7 +179,7 @@ impl<S: SignedPrimitive, T> AllocationCount<S, T> {
         self.ptr
     }
 
-    fn as_ptr_mut(&mut self) -> *mut T {
+    pub(crate) fn as_ptr_mut(&mut self) -> *mut T {
         self.ptr
     }
 }
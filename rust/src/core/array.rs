@@ -3,7 +3,9 @@ use crate::core::moot::*;
 pub use crate::core::container::*;
 pub use crate::core::count::*;
 pub use crate::core::signed::*;
-pub use crate::core::traits::{Few, GetCount, SetCount, TryClone, TypeMarker};
+pub use crate::core::traits::{
+    Few, GetCount, SetCount, TryClone, TryCloneWithCapacity, TypeMarker,
+};
 
 #[derive(Eq, PartialEq, Copy, Clone, Debug, Default, Hash)]
 pub enum Clear {
@@ -0,0 +1,528 @@
+use crate::core::allocation::*;
+use crate::core::array::Clear;
+
+pub use crate::core::container::*;
+pub use crate::core::count::*;
+pub use crate::core::signed::*;
+pub use crate::core::traits::{GetCount, SetCount, TryClone};
+
+pub type BitArray64 = BitArray<i64>;
+pub type BitArray32 = BitArray<i32>;
+pub type BitArray16 = BitArray<i16>;
+pub type BitArray8 = BitArray<i8>;
+
+const WORD_BITS: usize = 64;
+
+/// Compact array of `count()` bools, stored as bits in `u64` words.
+/// Bits past `count()` in the last word are always kept as zero,
+/// so that we can count/compare whole words at a time.
+/// Use `BitArray256` for a fixed-size set of bytes (e.g., a character class).
+#[repr(C, align(8))]
+pub struct BitArray<S: SignedPrimitive> {
+    allocation: AllocationCount<S, u64>,
+    /// Number of bits (not words).
+    count: Count<S>,
+}
+
+impl<S: SignedPrimitive> Default for BitArray<S> {
+    fn default() -> Self {
+        Self {
+            allocation: AllocationCount::<S, u64>::default(),
+            count: Count::<S>::default(),
+        }
+    }
+}
+
+impl<S: SignedPrimitive> BitArray<S> {
+    pub fn len(&self) -> usize {
+        self.count.to_usize()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        !self.count.is_positive()
+    }
+
+    /// Capacity in bits.
+    pub fn capacity(&self) -> Count<S> {
+        let bits = self.allocation.capacity().to_usize() * WORD_BITS;
+        // The capacity is in words, so there may be more bits than we can count.
+        Count::<S>::of(bits).unwrap_or(Count::<S>::MAX)
+    }
+
+    pub fn get(&self, at: usize) -> ContainerResult<bool> {
+        if at >= self.len() {
+            return Err(ContainerError::InvalidAt);
+        }
+        Ok(words_get(self.words(), at))
+    }
+
+    pub fn set(&mut self, at: usize, value: bool) -> Containered {
+        if at >= self.len() {
+            return ContainerError::InvalidAt.err();
+        }
+        words_set(self.words_mut(), at, value);
+        Ok(())
+    }
+
+    /// Returns the new value of the bit.
+    pub fn flip(&mut self, at: usize) -> ContainerResult<bool> {
+        if at >= self.len() {
+            return Err(ContainerError::InvalidAt);
+        }
+        Ok(words_flip(self.words_mut(), at))
+    }
+
+    pub fn count_ones(&self) -> Count<S> {
+        Count::<S>::of(words_count_ones(self.words())).expect("at most `count` ones")
+    }
+
+    pub fn first_set(&self) -> Option<usize> {
+        words_first_set(self.words())
+    }
+
+    pub fn first_unset(&self) -> Option<usize> {
+        words_first_unset(self.words(), self.len())
+    }
+
+    /// Iterates over the offsets of all set bits, in increasing order.
+    pub fn iter_set(&self) -> impl Iterator<Item = usize> + '_ {
+        WordsIterSet::new(self.words())
+    }
+
+    /// Sets bits which are set in either `self` or `other`.
+    /// Grows `self` to `other.len()` if necessary.
+    pub fn union_with(&mut self, other: &Self) -> Containered {
+        if other.count > self.count {
+            self.set_count(other.count)?;
+        }
+        let other = other.words();
+        for (word, other) in self.words_mut().iter_mut().zip(other) {
+            *word |= *other;
+        }
+        Ok(())
+    }
+
+    /// Keeps only bits which are set in both `self` and `other`.
+    pub fn intersect_with(&mut self, other: &Self) {
+        let other = other.words();
+        for (i, word) in self.words_mut().iter_mut().enumerate() {
+            *word &= other.get(i).copied().unwrap_or(0);
+        }
+    }
+
+    /// Unsets bits which are set in `other`.
+    pub fn difference_with(&mut self, other: &Self) {
+        let other = other.words();
+        for (word, other) in self.words_mut().iter_mut().zip(other) {
+            *word &= !*other;
+        }
+    }
+
+    pub fn clear(&mut self, options: Clear) {
+        match options {
+            Clear::KeepingCapacity => self.count = Count::<S>::default(),
+            Clear::DroppingCapacity => self
+                .set_capacity(Count::<S>::default())
+                .expect("clearing should not alloc"),
+        }
+    }
+
+    /// Will reallocate to hold exactly this many bits (rounded up to a whole word).
+    /// Will delete bits if `new_capacity < self.count()`.
+    pub fn set_capacity(&mut self, new_capacity: Count<S>) -> Containered {
+        if new_capacity < self.count {
+            self.truncate(new_capacity);
+        }
+        let word_count = Count::<S>::of(word_count(new_capacity.to_usize())).expect("fewer words");
+        self.allocation.set_capacity(word_count)
+    }
+
+    fn truncate(&mut self, new_count: Count<S>) {
+        self.count = new_count;
+        let bits = self.len();
+        let words = self.words_mut();
+        if !bits.is_multiple_of(WORD_BITS) {
+            words[bits / WORD_BITS] &= (1u64 << (bits % WORD_BITS)) - 1;
+        }
+    }
+
+    fn grow_to_at_least(&mut self, required_capacity: Count<S>) -> Containered {
+        let desired_capacity = self
+            .capacity()
+            .double_or_at_least(required_capacity)
+            .map_err(|_| ContainerError::OutOfMemory)?;
+        self.set_capacity(desired_capacity)
+    }
+
+    fn words(&self) -> &[u64] {
+        &self.allocation[0..word_count(self.len())]
+    }
+
+    fn words_mut(&mut self) -> &mut [u64] {
+        let word_count = word_count(self.len());
+        &mut self.allocation[0..word_count]
+    }
+}
+
+impl<S: SignedPrimitive> GetCount<S> for BitArray<S> {
+    fn count(&self) -> Count<S> {
+        self.count
+    }
+}
+
+impl<S: SignedPrimitive> SetCount<S> for BitArray<S> {
+    type Error = ContainerError;
+
+    /// New bits are unset.
+    fn set_count(&mut self, new_count: Count<S>) -> Containered {
+        if new_count.is_null() {
+            return ContainerError::OutOfMemory.err();
+        }
+        if new_count <= self.count {
+            self.truncate(new_count);
+            return Ok(());
+        }
+        if new_count > self.capacity() {
+            self.grow_to_at_least(new_count)?;
+        }
+        // Trailing bits in the last word are already zero; zero any new words.
+        let old_word_count = word_count(self.len());
+        self.count = new_count;
+        for word in self.words_mut()[old_word_count..].iter_mut() {
+            *word = 0;
+        }
+        Ok(())
+    }
+}
+
+impl<S: SignedPrimitive> PartialEq<Self> for BitArray<S> {
+    fn eq(&self, other: &Self) -> bool {
+        self.count == other.count && self.words() == other.words()
+    }
+}
+
+impl<S: SignedPrimitive> Eq for BitArray<S> {}
+
+impl<S: SignedPrimitive> TryClone for BitArray<S> {
+    type Error = ContainerError;
+
+    fn try_clone(&self) -> Result<Self, ContainerError> {
+        let mut result = Self::default();
+        // Only need to clone up to `count`, not the full `capacity`.
+        result.set_capacity(self.count)?;
+        result.count = self.count;
+        result.words_mut().copy_from_slice(self.words());
+        Ok(result)
+    }
+}
+
+impl<S: SignedPrimitive> std::fmt::Debug for BitArray<S> {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "BitArray{}::from(\"", S::BITS)?;
+        for i in 0..self.len() {
+            write!(f, "{}", words_get(self.words(), i) as u8)?;
+        }
+        write!(f, "\")")
+    }
+}
+
+impl<S: SignedPrimitive> Drop for BitArray<S> {
+    fn drop(&mut self) {
+        self.clear(Clear::DroppingCapacity);
+    }
+}
+
+/// A fixed set of 256 bits, e.g., to check if a byte is in a character class
+/// like `needs_escape: set_{u8_}` in `core/file/tag.oh`.
+#[derive(Eq, PartialEq, Copy, Clone, Default, Hash)]
+pub struct BitArray256 {
+    words: [u64; 4],
+}
+
+impl BitArray256 {
+    pub const fn of(bytes: &[u8]) -> Self {
+        let mut result = Self { words: [0; 4] };
+        let mut i = 0;
+        while i < bytes.len() {
+            let byte = bytes[i] as usize;
+            result.words[byte / WORD_BITS] |= 1u64 << (byte % WORD_BITS);
+            i += 1;
+        }
+        result
+    }
+
+    pub const fn get(&self, at: u8) -> bool {
+        let at = at as usize;
+        self.words[at / WORD_BITS] & (1u64 << (at % WORD_BITS)) != 0
+    }
+
+    pub fn set(&mut self, at: u8, value: bool) {
+        words_set(&mut self.words, at as usize, value)
+    }
+
+    /// Returns the new value of the bit.
+    pub fn flip(&mut self, at: u8) -> bool {
+        words_flip(&mut self.words, at as usize)
+    }
+
+    pub fn count_ones(&self) -> usize {
+        words_count_ones(&self.words)
+    }
+
+    pub fn first_set(&self) -> Option<u8> {
+        words_first_set(&self.words).map(|at| at as u8)
+    }
+
+    pub fn first_unset(&self) -> Option<u8> {
+        words_first_unset(&self.words, 256).map(|at| at as u8)
+    }
+
+    /// Iterates over all set bytes, in increasing order.
+    pub fn iter_set(&self) -> impl Iterator<Item = u8> + '_ {
+        WordsIterSet::new(&self.words).map(|at| at as u8)
+    }
+
+    pub const fn union(self, other: Self) -> Self {
+        let mut words = self.words;
+        let mut i = 0;
+        while i < 4 {
+            words[i] |= other.words[i];
+            i += 1;
+        }
+        Self { words }
+    }
+
+    pub const fn intersection(self, other: Self) -> Self {
+        let mut words = self.words;
+        let mut i = 0;
+        while i < 4 {
+            words[i] &= other.words[i];
+            i += 1;
+        }
+        Self { words }
+    }
+
+    pub const fn difference(self, other: Self) -> Self {
+        let mut words = self.words;
+        let mut i = 0;
+        while i < 4 {
+            words[i] &= !other.words[i];
+            i += 1;
+        }
+        Self { words }
+    }
+}
+
+impl std::fmt::Debug for BitArray256 {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "BitArray256::of(b\"")?;
+        for byte in self.iter_set() {
+            write!(f, "{}", std::ascii::escape_default(byte))?;
+        }
+        write!(f, "\")")
+    }
+}
+
+#[inline]
+fn word_count(bits: usize) -> usize {
+    bits.div_ceil(WORD_BITS)
+}
+
+#[inline]
+fn words_get(words: &[u64], at: usize) -> bool {
+    words[at / WORD_BITS] & (1u64 << (at % WORD_BITS)) != 0
+}
+
+#[inline]
+fn words_set(words: &mut [u64], at: usize, value: bool) {
+    let mask = 1u64 << (at % WORD_BITS);
+    if value {
+        words[at / WORD_BITS] |= mask;
+    } else {
+        words[at / WORD_BITS] &= !mask;
+    }
+}
+
+#[inline]
+fn words_flip(words: &mut [u64], at: usize) -> bool {
+    let mask = 1u64 << (at % WORD_BITS);
+    let word = &mut words[at / WORD_BITS];
+    *word ^= mask;
+    *word & mask != 0
+}
+
+fn words_count_ones(words: &[u64]) -> usize {
+    words.iter().map(|word| word.count_ones() as usize).sum()
+}
+
+fn words_first_set(words: &[u64]) -> Option<usize> {
+    for (i, word) in words.iter().enumerate() {
+        if *word != 0 {
+            return Some(i * WORD_BITS + word.trailing_zeros() as usize);
+        }
+    }
+    None
+}
+
+/// `bits` is needed because trailing bits in the last word are zero (i.e., unset)
+/// but aren't part of the array.
+fn words_first_unset(words: &[u64], bits: usize) -> Option<usize> {
+    for (i, word) in words.iter().enumerate() {
+        if *word != u64::MAX {
+            let at = i * WORD_BITS + word.trailing_ones() as usize;
+            return if at < bits { Some(at) } else { None };
+        }
+    }
+    None
+}
+
+struct WordsIterSet<'a> {
+    words: &'a [u64],
+    word_index: usize,
+    /// Bits of `words[word_index]` that we haven't returned yet.
+    remaining: u64,
+}
+
+impl<'a> WordsIterSet<'a> {
+    fn new(words: &'a [u64]) -> Self {
+        Self {
+            words,
+            word_index: 0,
+            remaining: words.first().copied().unwrap_or(0),
+        }
+    }
+}
+
+impl Iterator for WordsIterSet<'_> {
+    type Item = usize;
+
+    fn next(&mut self) -> Option<usize> {
+        while self.remaining == 0 {
+            self.word_index += 1;
+            if self.word_index >= self.words.len() {
+                return None;
+            }
+            self.remaining = self.words[self.word_index];
+        }
+        let bit = self.remaining.trailing_zeros() as usize;
+        // Clear the lowest set bit.
+        self.remaining &= self.remaining - 1;
+        Some(self.word_index * WORD_BITS + bit)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::core::testing::*;
+
+    #[test]
+    fn set_count_zeroes_new_bits() {
+        let mut bits = BitArray16::default();
+        bits.set_count(Count::of(70).expect("ok")).expect("ok");
+        assert_eq!(bits.count_ones(), Count::of(0).expect("ok"));
+        assert_eq!(bits.capacity(), Count::of(128).expect("ok"));
+        testing_unprint(vec![Vec::from(b"create(A: 2)")]);
+
+        bits.set(69, true).expect("ok");
+        bits.set(3, true).expect("ok");
+        // Truncating and regrowing shouldn't bring back old bits.
+        bits.set_count(Count::of(65).expect("ok")).expect("ok");
+        bits.set_count(Count::of(100).expect("ok")).expect("ok");
+        assert_eq!(bits.iter_set().collect::<Vec<_>>(), vec![3]);
+        testing_unprint(vec![]);
+    }
+
+    #[test]
+    fn get_set_and_flip() {
+        let mut bits = BitArray8::default();
+        bits.set_count(Count::of(10).expect("ok")).expect("ok");
+        assert_eq!(bits.get(4), Ok(false));
+        bits.set(4, true).expect("ok");
+        assert_eq!(bits.get(4), Ok(true));
+        assert_eq!(bits.flip(4), Ok(false));
+        assert_eq!(bits.flip(9), Ok(true));
+        assert_eq!(bits.get(9), Ok(true));
+        assert_eq!(bits.get(10), Err(ContainerError::InvalidAt));
+        assert_eq!(bits.set(10, true), Err(ContainerError::InvalidAt));
+        assert_eq!(bits.flip(128), Err(ContainerError::InvalidAt));
+        assert_eq!(format!("{:?}", bits), "BitArray8::from(\"0000000001\")");
+    }
+
+    #[test]
+    fn first_set_and_unset() {
+        let mut bits = BitArray32::default();
+        assert_eq!(bits.first_set(), None);
+        assert_eq!(bits.first_unset(), None);
+        bits.set_count(Count::of(130).expect("ok")).expect("ok");
+        assert_eq!(bits.first_set(), None);
+        assert_eq!(bits.first_unset(), Some(0));
+        bits.set(100, true).expect("ok");
+        assert_eq!(bits.first_set(), Some(100));
+        for i in 0..130 {
+            bits.set(i, true).expect("ok");
+        }
+        assert_eq!(bits.first_unset(), None);
+        assert_eq!(bits.count_ones(), Count::of(130).expect("ok"));
+        bits.set(128, false).expect("ok");
+        assert_eq!(bits.first_unset(), Some(128));
+    }
+
+    #[test]
+    fn set_operations() {
+        let mut a = BitArray64::default();
+        a.set_count(Count::of(8).expect("ok")).expect("ok");
+        for i in [1, 2, 3] {
+            a.set(i, true).expect("ok");
+        }
+        let mut b = BitArray64::default();
+        b.set_count(Count::of(80).expect("ok")).expect("ok");
+        for i in [3, 4, 77] {
+            b.set(i, true).expect("ok");
+        }
+
+        let mut union = a.try_clone().expect("ok");
+        union.union_with(&b).expect("ok");
+        assert_eq!(union.count(), Count::of(80).expect("ok"));
+        assert_eq!(union.iter_set().collect::<Vec<_>>(), vec![1, 2, 3, 4, 77]);
+
+        let mut intersection = b.try_clone().expect("ok");
+        intersection.intersect_with(&a);
+        assert_eq!(intersection.iter_set().collect::<Vec<_>>(), vec![3]);
+
+        let mut difference = b.try_clone().expect("ok");
+        difference.difference_with(&a);
+        assert_eq!(difference.iter_set().collect::<Vec<_>>(), vec![4, 77]);
+        assert_ne!(difference, b);
+    }
+
+    #[test]
+    fn bit_array_256_works_for_bytes() {
+        let needs_escape = BitArray256::of(b"$_");
+        assert!(needs_escape.get(b'$'));
+        assert!(needs_escape.get(b'_'));
+        assert!(!needs_escape.get(b'a'));
+        assert_eq!(needs_escape.count_ones(), 2);
+        assert_eq!(needs_escape.first_set(), Some(b'$'));
+        assert_eq!(needs_escape.first_unset(), Some(0));
+        assert_eq!(format!("{:?}", needs_escape), "BitArray256::of(b\"$_\")");
+
+        let mut all = BitArray256::default();
+        for byte in 0..=255u8 {
+            all.set(byte, true);
+        }
+        assert_eq!(all.first_unset(), None);
+        assert!(!all.flip(255));
+        assert_eq!(all.first_unset(), Some(255));
+        assert_eq!(all.iter_set().count(), 255);
+
+        let digits = BitArray256::of(b"0123456789");
+        let hex = digits.union(BitArray256::of(b"abcdef"));
+        assert_eq!(hex.count_ones(), 16);
+        assert_eq!(hex.intersection(digits), digits);
+        assert_eq!(
+            hex.difference(digits).iter_set().collect::<Vec<_>>(),
+            b"abcdef".to_vec()
+        );
+    }
+}
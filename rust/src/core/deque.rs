@@ -0,0 +1,510 @@
+use crate::core::allocation::*;
+use crate::core::array::Clear;
+use crate::core::offset::*;
+
+pub use crate::core::container::*;
+pub use crate::core::count::*;
+pub use crate::core::signed::*;
+pub use crate::core::traits::{GetCount, TryClone};
+
+pub type Deque64<T> = Deque<i64, T>;
+pub type Deque32<T> = Deque<i32, T>;
+pub type Deque16<T> = Deque<i16, T>;
+pub type Deque8<T> = Deque<i8, T>;
+
+pub enum DequeInsert<T> {
+    AtStart(T),
+    AtEnd(T),
+}
+
+#[derive(Eq, PartialEq, Copy, Clone, Debug, Hash)]
+pub enum DequeRemove {
+    First,
+    Last,
+}
+
+/// Double-ended queue that can store up to `Count::<S>::MAX` elements,
+/// stored as a ring inside of an `AllocationCount`.  Elements start at
+/// `head` and wrap around to the start of the allocation if necessary,
+/// so they are not necessarily contiguous; use `as_slices` if you need
+/// direct access, or index into the deque with `deque[i]`.
+/// Like `NonLocalArrayCount`, changing `S` allows for more compact indices.
+#[repr(C, align(8))]
+pub struct Deque<S: SignedPrimitive, T> {
+    allocation: AllocationCount<S, T>,
+    /// Offset into `allocation` of the first element.  Always less than
+    /// `capacity` unless the capacity is zero.
+    head: Offset<S>,
+    count: Count<S>,
+}
+
+impl<S: SignedPrimitive, T> Default for Deque<S, T> {
+    fn default() -> Self {
+        Self {
+            allocation: AllocationCount::<S, T>::default(),
+            head: Offset::<S>::of(S::ZERO),
+            count: Count::<S>::default(),
+        }
+    }
+}
+
+impl<S: SignedPrimitive, T> Deque<S, T> {
+    /// Looking for `fn push_front(t)` or `fn push_back(t)`?
+    /// use `insert(DequeInsert::AtStart(t))` or `insert(DequeInsert::AtEnd(t))`.
+    pub fn insert(&mut self, insert: DequeInsert<T>) -> Containered {
+        match insert {
+            DequeInsert::AtStart(t) => self.insert_at_start(t),
+            DequeInsert::AtEnd(t) => self.insert_at_end(t),
+        }
+    }
+
+    /// Looking for `fn pop_front()` or `fn pop_back()`?
+    /// use `remove(DequeRemove::First)` or `remove(DequeRemove::Last)`.
+    pub fn remove(&mut self, remove: DequeRemove) -> Option<T> {
+        match remove {
+            DequeRemove::First => self.remove_first(),
+            DequeRemove::Last => self.remove_last(),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.count.to_usize()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        !self.count.is_positive()
+    }
+
+    pub fn get(&self, index: usize) -> Option<&T> {
+        if index >= self.len() {
+            return None;
+        }
+        let offset = self.wrapped_offset(index);
+        Some(&self.allocation[offset])
+    }
+
+    pub fn get_mut(&mut self, index: usize) -> Option<&mut T> {
+        if index >= self.len() {
+            return None;
+        }
+        let offset = self.wrapped_offset(index);
+        Some(&mut self.allocation[offset])
+    }
+
+    /// Returns the elements in order as two slices; the second slice
+    /// is non-empty only if the elements wrap around the allocation.
+    pub fn as_slices(&self) -> (&[T], &[T]) {
+        let (first, second) = self.ranges();
+        (&self.allocation[first], &self.allocation[second])
+    }
+
+    pub fn as_mut_slices(&mut self) -> (&mut [T], &mut [T]) {
+        let (first, second) = self.ranges();
+        let (start, end) = self.allocation.split_at_mut(first.start);
+        (&mut end[0..first.len()], &mut start[second])
+    }
+
+    pub fn iter(&self) -> impl DoubleEndedIterator<Item = &T> {
+        let (first, second) = self.as_slices();
+        first.iter().chain(second.iter())
+    }
+
+    pub fn clear(&mut self, options: Clear) {
+        match options {
+            Clear::KeepingCapacity => {
+                // We could optimize this but we do need Rust to drop each individual
+                // element (if necessary), so we can't just dealloc the `ptr` itself.
+                while self.remove_last().is_some() {}
+            }
+            Clear::DroppingCapacity => self
+                .set_capacity(Count::<S>::default())
+                .expect("clearing should not alloc"),
+        }
+        assert!(self.count == Count::<S>::default());
+        self.head = Offset::<S>::of(S::ZERO);
+    }
+
+    #[inline]
+    pub fn capacity(&self) -> Count<S> {
+        self.allocation.capacity()
+    }
+
+    /// Will reallocate to exactly this capacity, unwrapping the ring so that
+    /// the first element ends up at the start of the new allocation.
+    /// Will delete items from the end if `new_capacity < self.count()`.
+    pub fn set_capacity(&mut self, new_capacity: Count<S>) -> Containered {
+        if new_capacity == self.capacity() {
+            return Ok(());
+        }
+        while self.count > new_capacity {
+            // Could happen if new_capacity < 0
+            if self.remove_last().is_none() {
+                break;
+            }
+        }
+        if !new_capacity.is_positive() {
+            self.head = Offset::<S>::of(S::ZERO);
+            return self.allocation.set_capacity(new_capacity);
+        }
+        // We can't just `realloc` since we'd need to shuffle the wrapped elements
+        // around afterwards anyway; copy both halves into a fresh allocation instead.
+        let mut new_allocation = AllocationCount::<S, T>::default();
+        new_allocation.set_capacity(new_capacity)?;
+        let (first, second) = self.ranges();
+        unsafe {
+            let from = self.allocation.as_ptr_mut();
+            let to = new_allocation.as_ptr_mut();
+            std::ptr::copy_nonoverlapping(from.add(first.start), to, first.len());
+            std::ptr::copy_nonoverlapping(
+                from.add(second.start),
+                to.add(first.len()),
+                second.len(),
+            );
+        }
+        // The elements now live in `new_allocation`; we only need to free the memory.
+        std::mem::swap(&mut self.allocation, &mut new_allocation);
+        new_allocation
+            .set_capacity(Count::<S>::default())
+            .expect("freeing should not fail");
+        self.head = Offset::<S>::of(S::ZERO);
+        Ok(())
+    }
+
+    fn insert_at_start(&mut self, t: T) -> Containered {
+        let new_count = self.count + S::ONE;
+        if new_count.is_null() {
+            return ContainerError::OutOfMemory.err();
+        }
+        if new_count > self.capacity() {
+            self.grow_to_at_least(new_count)?;
+        }
+        let capacity = self.capacity().to_usize();
+        let head = (self.head_usize() + capacity - 1) % capacity;
+        self.allocation
+            .write_initializing(Self::offset_of(head), t)
+            .expect("should be in bounds");
+        self.head = Self::offset_of(head);
+        self.count = new_count;
+        Ok(())
+    }
+
+    fn insert_at_end(&mut self, t: T) -> Containered {
+        let new_count = self.count + S::ONE;
+        if new_count.is_null() {
+            return ContainerError::OutOfMemory.err();
+        }
+        if new_count > self.capacity() {
+            self.grow_to_at_least(new_count)?;
+        }
+        let offset = self.wrapped_offset(self.len());
+        self.allocation
+            .write_initializing(Self::offset_of(offset), t)
+            .expect("should be in bounds");
+        self.count = new_count;
+        Ok(())
+    }
+
+    fn remove_first(&mut self) -> Option<T> {
+        if !self.count.is_positive() {
+            return None;
+        }
+        let result = self
+            .allocation
+            .read_destructively(self.head)
+            .expect("should be in bounds");
+        let capacity = self.capacity().to_usize();
+        self.head = Self::offset_of((self.head_usize() + 1) % capacity);
+        self.count -= S::ONE;
+        debug_assert!(self.count.is_not_null());
+        Some(result)
+    }
+
+    fn remove_last(&mut self) -> Option<T> {
+        if !self.count.is_positive() {
+            return None;
+        }
+        let offset = self.wrapped_offset(self.len() - 1);
+        let result = self
+            .allocation
+            .read_destructively(Self::offset_of(offset))
+            .expect("should be in bounds");
+        self.count -= S::ONE;
+        debug_assert!(self.count.is_not_null());
+        Some(result)
+    }
+
+    fn grow_to_at_least(&mut self, required_capacity: Count<S>) -> Containered {
+        let desired_capacity = self
+            .capacity()
+            .double_or_at_least(required_capacity)
+            .map_err(|_| ContainerError::OutOfMemory)?;
+        self.set_capacity(desired_capacity)
+    }
+
+    /// Ranges (into `allocation`) of the elements before and after wrapping around.
+    fn ranges(&self) -> (std::ops::Range<usize>, std::ops::Range<usize>) {
+        let head = self.head_usize();
+        let count = self.len();
+        let capacity = self.capacity().to_usize();
+        if head + count <= capacity {
+            (head..head + count, 0..0)
+        } else {
+            (head..capacity, 0..head + count - capacity)
+        }
+    }
+
+    /// Caller must ensure that `capacity > 0`.
+    #[inline]
+    fn wrapped_offset(&self, index: usize) -> usize {
+        (self.head_usize() + index) % self.capacity().to_usize()
+    }
+
+    #[inline]
+    fn head_usize(&self) -> usize {
+        self.head.to_inner().as_() as usize
+    }
+
+    #[inline]
+    fn offset_of(offset: usize) -> Offset<S> {
+        Offset::<S>::of(S::from(offset).expect("offsets are less than capacity"))
+    }
+}
+
+impl<S: SignedPrimitive, T> GetCount<S> for Deque<S, T> {
+    fn count(&self) -> Count<S> {
+        self.count
+    }
+}
+
+impl<S: SignedPrimitive, T> std::ops::Index<usize> for Deque<S, T> {
+    type Output = T;
+
+    fn index(&self, index: usize) -> &T {
+        self.get(index).expect("index should be in bounds")
+    }
+}
+
+impl<S: SignedPrimitive, T> std::ops::IndexMut<usize> for Deque<S, T> {
+    fn index_mut(&mut self, index: usize) -> &mut T {
+        self.get_mut(index).expect("index should be in bounds")
+    }
+}
+
+impl<S: SignedPrimitive, T: std::cmp::PartialEq> PartialEq<Self> for Deque<S, T> {
+    fn eq(&self, other: &Self) -> bool {
+        if self.count != other.count {
+            return false;
+        }
+        self.iter().eq(other.iter())
+    }
+}
+
+impl<S: SignedPrimitive, T: std::cmp::Eq> Eq for Deque<S, T> {}
+
+impl<S: SignedPrimitive, T: TryClone> TryClone for Deque<S, T> {
+    // TODO: this should probably be one_of(ContainerError, <T as TryClone>::Error)
+    type Error = ContainerError;
+
+    fn try_clone(&self) -> Result<Self, ContainerError> {
+        let mut result = Self::default();
+        // Only need to clone up to `count`, not the full `capacity`.
+        result.set_capacity(self.count)?;
+        for value in self.iter() {
+            let clone = value.try_clone().map_err(|_| ContainerError::Unknown)?;
+            result
+                .insert_at_end(clone)
+                .expect("already at necessary capacity");
+        }
+        Ok(result)
+    }
+}
+
+impl<S: SignedPrimitive, T: std::fmt::Debug> std::fmt::Debug for Deque<S, T> {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "Deque{}::<_>::from([", S::BITS)?;
+        for value in self.iter() {
+            write!(f, "{:?}, ", value)?;
+        }
+        write!(f, "])")
+    }
+}
+
+impl<S: SignedPrimitive, T> Drop for Deque<S, T> {
+    fn drop(&mut self) {
+        self.clear(Clear::DroppingCapacity);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::core::testing::*;
+
+    #[test]
+    fn deque_is_compact() {
+        assert_eq!(std::mem::size_of::<Deque8<u8>>(), 16);
+        assert_eq!(std::mem::size_of::<Deque32<u8>>(), 24);
+        assert_eq!(std::mem::size_of::<Deque64<u8>>(), 32);
+    }
+
+    #[test]
+    fn insert_and_remove_at_both_ends() {
+        let mut deque = Deque16::<u32>::default();
+        deque.insert(DequeInsert::AtEnd(3)).expect("ok");
+        deque.insert(DequeInsert::AtStart(2)).expect("ok");
+        deque.insert(DequeInsert::AtEnd(4)).expect("ok");
+        deque.insert(DequeInsert::AtStart(1)).expect("ok");
+        assert_eq!(deque.count(), Count::of(4).expect("ok"));
+        assert_eq!(deque.iter().copied().collect::<Vec<_>>(), vec![1, 2, 3, 4]);
+        assert_eq!(deque[0], 1);
+        assert_eq!(deque[3], 4);
+        assert_eq!(deque.get(4), None);
+
+        deque[1] = 20;
+        assert_eq!(deque.remove(DequeRemove::First), Some(1));
+        assert_eq!(deque.remove(DequeRemove::Last), Some(4));
+        assert_eq!(deque.remove(DequeRemove::First), Some(20));
+        assert_eq!(deque.remove(DequeRemove::Last), Some(3));
+        assert_eq!(deque.remove(DequeRemove::First), None);
+        assert_eq!(deque.remove(DequeRemove::Last), None);
+        assert!(deque.is_empty());
+    }
+
+    #[test]
+    fn wraps_around_the_allocation() {
+        let mut deque = Deque8::<u8>::default();
+        deque.set_capacity(Count::of(4).expect("ok")).expect("ok");
+        deque.insert(DequeInsert::AtEnd(1)).expect("ok");
+        deque.insert(DequeInsert::AtEnd(2)).expect("ok");
+        deque.insert(DequeInsert::AtStart(0)).expect("ok");
+        assert_eq!(deque.as_slices(), (&[0u8][..], &[1u8, 2][..]));
+
+        deque.insert(DequeInsert::AtEnd(3)).expect("ok");
+        assert_eq!(deque.capacity(), Count::of(4).expect("ok"));
+        assert_eq!(deque.as_slices(), (&[0u8][..], &[1u8, 2, 3][..]));
+        {
+            let (first, second) = deque.as_mut_slices();
+            first[0] = 10;
+            second[2] = 13;
+        }
+        assert_eq!(
+            deque.iter().rev().copied().collect::<Vec<_>>(),
+            vec![13, 2, 1, 10]
+        );
+    }
+
+    #[test]
+    fn growth_unwraps_the_ring() {
+        let mut deque = Deque32::<TestingNoisy>::default();
+        deque.set_capacity(Count::of(3).expect("ok")).expect("ok");
+        deque
+            .insert(DequeInsert::AtEnd(TestingNoisy::new(2)))
+            .expect("ok");
+        deque
+            .insert(DequeInsert::AtStart(TestingNoisy::new(1)))
+            .expect("ok");
+        deque
+            .insert(DequeInsert::AtEnd(TestingNoisy::new(3)))
+            .expect("ok");
+        testing_unprint(vec![
+            Vec::from(b"create(A: 3)"),
+            Vec::from(b"noisy_new(2)"),
+            Vec::from(b"noisy_new(1)"),
+            Vec::from(b"noisy_new(3)"),
+        ]);
+        assert_eq!(deque.as_slices().1.len(), 2);
+
+        deque
+            .insert(DequeInsert::AtEnd(TestingNoisy::new(4)))
+            .expect("ok");
+        // Moving elements into the new allocation doesn't clone or drop them.
+        testing_unprint(vec![
+            Vec::from(b"noisy_new(4)"),
+            Vec::from(b"create(B: 6)"),
+            Vec::from(b"delete(A)"),
+        ]);
+        assert_eq!(deque.capacity(), Count::of(6).expect("ok"));
+        let (first, second) = deque.as_slices();
+        assert_eq!(second.len(), 0);
+        assert_eq!(
+            first.iter().map(|n| n.value()).collect::<Vec<_>>(),
+            vec![1, 2, 3, 4]
+        );
+    }
+
+    #[test]
+    fn set_capacity_drops_from_the_end() {
+        let mut deque = Deque8::<TestingNoisy>::default();
+        deque.set_capacity(Count::of(4).expect("ok")).expect("ok");
+        for i in 1..=4 {
+            deque
+                .insert(DequeInsert::AtStart(TestingNoisy::new(i)))
+                .expect("ok");
+        }
+        _ = testing_prints();
+
+        deque.set_capacity(Count::of(2).expect("ok")).expect("ok");
+        testing_unprint(vec![
+            Vec::from(b"noisy_drop(1)"),
+            Vec::from(b"noisy_drop(2)"),
+            Vec::from(b"create(B: 2)"),
+            Vec::from(b"delete(A)"),
+        ]);
+        assert_eq!(
+            deque.iter().map(|n| n.value()).collect::<Vec<_>>(),
+            vec![4, 3]
+        );
+    }
+
+    #[test]
+    fn drop_frees_everything() {
+        {
+            let mut deque = Deque16::<TestingNoisy>::default();
+            deque.set_capacity(Count::of(3).expect("ok")).expect("ok");
+            deque
+                .insert(DequeInsert::AtEnd(TestingNoisy::new(5)))
+                .expect("ok");
+            deque
+                .insert(DequeInsert::AtStart(TestingNoisy::new(4)))
+                .expect("ok");
+            deque
+                .insert(DequeInsert::AtEnd(TestingNoisy::new(6)))
+                .expect("ok");
+            assert_eq!(deque.remove(DequeRemove::First).map(|n| n.value()), Some(4));
+            testing_unprint(vec![
+                Vec::from(b"create(A: 3)"),
+                Vec::from(b"noisy_new(5)"),
+                Vec::from(b"noisy_new(4)"),
+                Vec::from(b"noisy_new(6)"),
+                Vec::from(b"noisy_drop(4)"),
+            ]);
+        }
+        testing_unprint(vec![
+            Vec::from(b"noisy_drop(6)"),
+            Vec::from(b"noisy_drop(5)"),
+            Vec::from(b"delete(A)"),
+        ]);
+    }
+
+    #[test]
+    fn clone_keeps_order() {
+        let mut deque = Deque32::<TestingNoisy>::default();
+        deque.set_capacity(Count::of(4).expect("ok")).expect("ok");
+        deque
+            .insert(DequeInsert::AtStart(TestingNoisy::new(2)))
+            .expect("ok");
+        deque
+            .insert(DequeInsert::AtStart(TestingNoisy::new(1)))
+            .expect("ok");
+        _ = testing_prints();
+
+        let clone = deque.try_clone().expect("ok");
+        assert_eq!(clone.capacity(), Count::of(2).expect("ok"));
+        assert_eq!(clone, deque);
+        testing_unprint(vec![
+            Vec::from(b"create(B: 2)"),
+            Vec::from(b"noisy_clone(1)"),
+            Vec::from(b"noisy_clone(2)"),
+        ]);
+    }
+}
@@ -0,0 +1,259 @@
+use crate::core::file_error::*;
+use crate::core::file_path::*;
+use crate::core::filesystem::*;
+use crate::core::moot::*;
+use crate::core::non_local_array::*;
+
+pub use crate::core::array::*;
+
+use std::collections::BTreeSet;
+
+/// Entries of a directory, sorted by path.
+pub type DirectoryEntries = NonLocalArrayCount32<ValidFilePath>;
+
+/// What to skip when walking a directory tree, e.g., for `find_oh_files`.
+#[derive(Copy, Clone, Debug)]
+pub struct DirectoryWalk<'a> {
+    /// Skip files and directories whose names start with `.`, e.g., `.git/`.
+    pub skip_hidden: bool,
+    /// Skip compiler outputs, i.e., `.generated/` directories and files like
+    /// `.main.generated.c`, even if we're not skipping hidden files.
+    pub skip_generated: bool,
+    /// Skip files and directories with these exact names, e.g., `target`.
+    pub skip_names: &'a [&'a str],
+}
+
+impl Default for DirectoryWalk<'_> {
+    fn default() -> Self {
+        Self {
+            skip_hidden: true,
+            skip_generated: true,
+            skip_names: &[],
+        }
+    }
+}
+
+impl DirectoryWalk<'_> {
+    pub fn skips(&self, name: &[u8]) -> bool {
+        (self.skip_hidden && name.starts_with(b"."))
+            || (self.skip_generated
+                && (name == b".generated" || name.windows(11).any(|w| w == b".generated.")))
+            || self.skip_names.iter().any(|skip| skip.as_bytes() == name)
+    }
+}
+
+impl DirectoryPath {
+    /// Lists this directory's entries (without `.` and `..`), sorted by path.
+    /// Entries are typed by checking the file system; symbolic links are
+    /// followed, and broken ones are listed as regular files.
+    pub fn entries(&self) -> FileResult<DirectoryEntries> {
+        DiskFilesystem.list(self)
+    }
+
+    /// Finds `.oh` files in this directory and its subdirectories, like `recurse_`
+    /// in `oh/main.oh`: `each` gets this directory's files first, then each
+    /// subdirectory's, all in sorted order.  Returns the number of `.oh` files.
+    /// Symbolic links are followed, but each directory is only walked once, so
+    /// that link cycles (e.g., `a/loop -> ..`) don't make us walk forever.
+    pub fn find_oh_files(
+        &self,
+        walk: &DirectoryWalk<'_>,
+        each: impl FnMut(RegularFilePath) -> Filed,
+    ) -> FileResult<usize> {
+        self.find_oh_files_in(&DiskFilesystem, walk, each)
+    }
+
+    /// Like `find_oh_files`, but walks `filesystem` instead of the disk.
+    pub fn find_oh_files_in(
+        &self,
+        filesystem: &dyn Filesystem,
+        walk: &DirectoryWalk<'_>,
+        mut each: impl FnMut(RegularFilePath) -> Filed,
+    ) -> FileResult<usize> {
+        self.find_oh_files_with(filesystem, walk, &mut BTreeSet::new(), &mut each)
+    }
+
+    fn find_oh_files_with(
+        &self,
+        filesystem: &dyn Filesystem,
+        walk: &DirectoryWalk<'_>,
+        visited: &mut BTreeSet<FileIdentity>,
+        each: &mut impl FnMut(RegularFilePath) -> Filed,
+    ) -> FileResult<usize> {
+        if let Some(FileStat {
+            identity: Some(identity),
+            ..
+        }) = filesystem.stat(self)?
+            && !visited.insert(identity)
+        {
+            // Already walked, e.g., we came back around through a symbolic link.
+            return Ok(0);
+        }
+        let mut entries = filesystem.list(self)?;
+        let mut oh_file_count = 0;
+        for entry in entries.iter_mut() {
+            if let ValidFilePath::RegularFile(path) = entry
+                && path.extension() == b"oh"
+                && !walk.skips(path.file_name())
+                && let ValidFilePath::RegularFile(path) = moot(entry)
+            {
+                each(path)?;
+                oh_file_count += 1;
+            }
+        }
+        for entry in entries.iter() {
+            // Files we moved out above are left as empty paths (the current directory).
+            if let ValidFilePath::Directory(path) = entry
+                && !path.is_empty()
+                && !walk.skips(path.name())
+            {
+                oh_file_count += path.find_oh_files_with(filesystem, walk, visited, each)?;
+            }
+        }
+        Ok(oh_file_count)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::core::testing::*;
+
+    fn directory_path(directory: &TestingDirectory) -> DirectoryPath {
+        DirectoryPath::try_from(FilePath::try_from(&**directory).expect("ok")).expect("ok")
+    }
+
+    fn relative(directory: &DirectoryPath, path: &FilePath) -> String {
+        path.to_str().expect("ok")[directory.len()..].to_string()
+    }
+
+    #[test]
+    fn lists_typed_entries_in_order() {
+        let temporary = TestingDirectory::new("directory-entries");
+        temporary.create_files(&["b.txt", "a/", "c/d.oh", ".e"]);
+        let directory = directory_path(&temporary);
+        let entries = directory.entries().expect("ok");
+        let names: Vec<String> = entries
+            .iter()
+            .map(|entry| relative(&directory, entry))
+            .collect();
+        assert_eq!(names, vec![".e", "a/", "b.txt", "c/"]);
+        assert!(matches!(entries[0], ValidFilePath::RegularFile(_)));
+        assert!(matches!(entries[1], ValidFilePath::Directory(_)));
+        assert!(matches!(entries[2], ValidFilePath::RegularFile(_)));
+        assert!(matches!(entries[3], ValidFilePath::Directory(_)));
+
+        let missing = DirectoryPath::try_from(
+            directory
+                .join(&FilePath::try_from("missing").expect("ok"))
+                .expect("ok"),
+        )
+        .expect("ok");
+        match missing.entries() {
+            Err(FileError::Missing(details)) => {
+                assert_eq!(details.operation, FileOperation::List);
+                assert_eq!(&details.path, &*missing);
+            }
+            _ => panic!("should be missing"),
+        }
+    }
+
+    #[test]
+    fn finds_oh_files_recursively() {
+        let temporary = TestingDirectory::new("directory-walk");
+        temporary.create_files(&[
+            "z.oh",
+            "main.oh",
+            "readme.md",
+            "b/x.oh",
+            "a/deep/er/y.oh",
+            "a/w.oh",
+            ".git/hidden.oh",
+            ".generated/g.oh",
+            "target/t.oh",
+            "main.generated.oh",
+            "empty/",
+        ]);
+        let directory = directory_path(&temporary);
+        let mut found = Vec::new();
+        let walk = DirectoryWalk {
+            skip_names: &["target"],
+            ..DirectoryWalk::default()
+        };
+        let count = directory
+            .find_oh_files(&walk, |path| {
+                found.push(relative(&directory, &path));
+                Ok(())
+            })
+            .expect("ok");
+        assert_eq!(
+            found,
+            vec!["main.oh", "z.oh", "a/w.oh", "a/deep/er/y.oh", "b/x.oh"]
+        );
+        assert_eq!(count, 5);
+
+        let mut found = Vec::new();
+        let walk = DirectoryWalk {
+            skip_hidden: false,
+            skip_generated: false,
+            skip_names: &[],
+        };
+        directory
+            .find_oh_files(&walk, |path| {
+                found.push(relative(&directory, &path));
+                Ok(())
+            })
+            .expect("ok");
+        assert_eq!(
+            found,
+            vec![
+                "main.generated.oh",
+                "main.oh",
+                "z.oh",
+                ".generated/g.oh",
+                ".git/hidden.oh",
+                "a/w.oh",
+                "a/deep/er/y.oh",
+                "b/x.oh",
+                "target/t.oh",
+            ]
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn finding_follows_symbolic_link_cycles_once() {
+        let temporary = TestingDirectory::new("directory-cycle");
+        temporary.create_files(&["top.oh", "a/b.oh", "c/d.oh"]);
+        let directory = directory_path(&temporary);
+        let root = temporary.to_path_buf();
+        // Two links back up make a naive walk exponential.
+        std::os::unix::fs::symlink("..", root.join("a/loop")).expect("ok");
+        std::os::unix::fs::symlink("..", root.join("a/loop2")).expect("ok");
+        // Links to a directory outside of the cycle are still followed (once).
+        std::os::unix::fs::symlink("../c", root.join("a/link")).expect("ok");
+        let mut found = Vec::new();
+        let count = directory
+            .find_oh_files(&DirectoryWalk::default(), |path| {
+                found.push(relative(&directory, &path));
+                Ok(())
+            })
+            .expect("ok");
+        assert_eq!(found, vec!["top.oh", "a/b.oh", "a/link/d.oh"]);
+        assert_eq!(count, 3);
+    }
+
+    #[test]
+    fn finding_stops_on_errors() {
+        let temporary = TestingDirectory::new("directory-errors");
+        temporary.create_files(&["a.oh", "b.oh"]);
+        let directory = directory_path(&temporary);
+        let mut calls = 0;
+        let result = directory.find_oh_files(&DirectoryWalk::default(), |_| {
+            calls += 1;
+            Err(FileError::Unknown)
+        });
+        assert_eq!(result, Err(FileError::Unknown));
+        assert_eq!(calls, 1);
+    }
+}
@@ -0,0 +1,193 @@
+use crate::core::non_local_array::*;
+
+pub use crate::core::array::*;
+
+/// Starts a file to mark it as UTF-8; `InMemoryFile` strips it from `lines`.
+pub const UTF8_BYTE_ORDER_MARK: &[u8] = b"\xef\xbb\xbf";
+
+/// Whether `bytes` (the start of a file) look like UTF-16, i.e., start with a
+/// UTF-16 byte order mark, or with an ASCII character next to a zero byte.
+pub fn looks_like_utf16(bytes: &[u8]) -> bool {
+    match bytes {
+        [0xff, 0xfe, ..] | [0xfe, 0xff, ..] => true,
+        [0, ascii, ..] | [ascii, 0, ..] => *ascii != 0 && ascii.is_ascii(),
+        _ => false,
+    }
+}
+
+/// Where something is in a file, for diagnostics like `3:14: tab`.
+/// Both start at 1, and `column` counts code points, where each byte of
+/// invalid UTF-8 counts as one.
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq, Hash, Ord, PartialOrd)]
+pub struct FilePosition {
+    pub line: u32,
+    pub column: u32,
+}
+
+impl std::fmt::Display for FilePosition {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "{}:{}", self.line, self.column)
+    }
+}
+
+/// Something in a source file which isn't plain UTF-8 with spaces, e.g.,
+/// a tab, which we care about for oh's Horstmann-style indentation.
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq, Hash)]
+pub struct EncodingIssue {
+    pub position: FilePosition,
+    pub kind: EncodingIssueKind,
+}
+
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq, Hash)]
+pub enum EncodingIssueKind {
+    /// A `\t`.
+    #[default]
+    Tab,
+    /// Whitespace other than ` `, `\t`, and line endings, e.g., a lone `\r`,
+    /// a no-break space (U+00A0), or a zero-width space (U+200B).
+    NonStandardWhitespace(char),
+    /// Bytes which aren't valid UTF-8, e.g., a truncated sequence.
+    InvalidUtf8(InvalidUtf8Bytes),
+}
+
+/// Up to 3 bytes of invalid UTF-8, i.e., `Utf8Chunk::invalid`.
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq, Hash)]
+pub struct InvalidUtf8Bytes {
+    bytes: [u8; 3],
+    count: u8,
+}
+
+impl InvalidUtf8Bytes {
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.bytes[0..self.count as usize]
+    }
+}
+
+/// E.g., `3:14: invalid UTF-8 (bytes e2 82)`.
+impl std::fmt::Display for EncodingIssue {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "{}: ", self.position)?;
+        match self.kind {
+            EncodingIssueKind::Tab => write!(f, "tab"),
+            EncodingIssueKind::NonStandardWhitespace(rune) => {
+                write!(f, "non-standard whitespace U+{:04X}", rune as u32)
+            }
+            EncodingIssueKind::InvalidUtf8(invalid) => {
+                write!(f, "invalid UTF-8 (bytes")?;
+                for byte in invalid.as_bytes() {
+                    write!(f, " {:02x}", byte)?;
+                }
+                write!(f, ")")
+            }
+        }
+    }
+}
+
+/// Encoding issues in order of position.
+pub type EncodingIssues = NonLocalArrayCount32<EncodingIssue>;
+
+impl EncodingIssue {
+    /// Finds issues in `lines` (without their line endings), in order.
+    pub fn find<'a>(lines: impl IntoIterator<Item = &'a [u8]>) -> ContainerResult<EncodingIssues> {
+        let mut issues = EncodingIssues::default();
+        for (index, line) in lines.into_iter().enumerate() {
+            Self::find_in_line(line, index as u32 + 1, &mut issues)?;
+        }
+        Ok(issues)
+    }
+
+    fn find_in_line(line: &[u8], line_number: u32, issues: &mut EncodingIssues) -> Containered {
+        let mut column = 1;
+        for chunk in line.utf8_chunks() {
+            for rune in chunk.valid().chars() {
+                let kind = match rune {
+                    ' ' => None,
+                    '\t' => Some(EncodingIssueKind::Tab),
+                    // Zero-width characters which `is_whitespace` doesn't include.
+                    '\u{200b}' | '\u{2060}' | '\u{feff}' => {
+                        Some(EncodingIssueKind::NonStandardWhitespace(rune))
+                    }
+                    rune if rune.is_whitespace() => {
+                        Some(EncodingIssueKind::NonStandardWhitespace(rune))
+                    }
+                    _ => None,
+                };
+                if let Some(kind) = kind {
+                    issues.insert(OrderedInsert::AtEnd(EncodingIssue {
+                        position: FilePosition {
+                            line: line_number,
+                            column,
+                        },
+                        kind,
+                    }))?;
+                }
+                column += 1;
+            }
+            let invalid = chunk.invalid();
+            if !invalid.is_empty() {
+                let mut bytes = InvalidUtf8Bytes::default();
+                bytes.bytes[0..invalid.len()].copy_from_slice(invalid);
+                bytes.count = invalid.len() as u8;
+                issues.insert(OrderedInsert::AtEnd(EncodingIssue {
+                    position: FilePosition {
+                        line: line_number,
+                        column,
+                    },
+                    kind: EncodingIssueKind::InvalidUtf8(bytes),
+                }))?;
+                column += invalid.len() as u32;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn issues(lines: &[&[u8]]) -> Vec<String> {
+        EncodingIssue::find(lines.iter().copied())
+            .expect("ok")
+            .iter()
+            .map(|issue| issue.to_string())
+            .collect()
+    }
+
+    #[test]
+    fn finds_tabs_whitespace_and_invalid_utf8_by_position() {
+        assert_eq!(
+            issues(&[b"fine(): null", b"    indented", b""]),
+            Vec::<String>::new()
+        );
+        assert_eq!(
+            issues(&[
+                b"\tx: 1",
+                "caf\u{e9}\u{a0}=".as_bytes(),
+                b"a\xe2\x82 \xffb\r",
+                "\u{200b}\u{3000}".as_bytes(),
+            ]),
+            vec![
+                "1:1: tab",
+                "2:5: non-standard whitespace U+00A0",
+                "3:2: invalid UTF-8 (bytes e2 82)",
+                "3:5: invalid UTF-8 (bytes ff)",
+                "3:7: non-standard whitespace U+000D",
+                "4:1: non-standard whitespace U+200B",
+                "4:2: non-standard whitespace U+3000",
+            ]
+        );
+    }
+
+    #[test]
+    fn detects_utf16() {
+        assert!(looks_like_utf16(b"\xff\xfeh\x00"));
+        assert!(looks_like_utf16(b"\xfe\xff\x00h"));
+        assert!(looks_like_utf16(b"h\x00i\x00"));
+        assert!(looks_like_utf16(b"\x00h\x00i"));
+        assert!(!looks_like_utf16(b"hi"));
+        assert!(!looks_like_utf16(b"\xef\xbb\xbfhi"));
+        assert!(!looks_like_utf16(b"\x00\x00"));
+        assert!(!looks_like_utf16(b""));
+    }
+}
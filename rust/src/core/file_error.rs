@@ -0,0 +1,302 @@
+use crate::core::file_path::*;
+
+pub use crate::core::array::*;
+
+/// Ways that working with files can fail.  Anything involving the file system
+/// has `FileErrorDetails` about where it failed, so that we can print a useful
+/// message (see `Display`).
+#[derive(Debug, Eq, PartialEq)]
+pub enum FileError {
+    /// Program ran out of memory.
+    OutOfMemory,
+    /// Nothing exists at the path.  Use `MissingFile::Empty` to treat a missing
+    /// file as empty instead.
+    Missing(FileErrorDetails),
+    /// Not allowed to access the file (or its directory).
+    Permission(FileErrorDetails),
+    /// The disk (or quota) is full.
+    NoSpace(FileErrorDetails),
+    /// Part of the path which should be a directory is a regular file.
+    NotADirectory(FileErrorDetails),
+    /// The file changed on disk since we read it, so we didn't write it.
+    Conflict(FileErrorDetails),
+    /// The file isn't UTF-8, e.g., it looks like UTF-16.
+    Encoding(FileErrorDetails),
+    /// Opening, listing, checking, or watching a path failed for another reason.
+    Open(FileErrorDetails),
+    /// Reading failed for another reason.
+    Read(FileErrorDetails),
+    /// Writing (or renaming) failed for another reason.
+    Write(FileErrorDetails),
+    Unknown,
+}
+
+pub type FileResult<T> = Result<T, FileError>;
+
+pub type Filed = FileResult<()>;
+
+impl FileError {
+    /// Classifies `error` by its kind (e.g., `NotFound` is `Missing`), falling
+    /// back to `operation` (e.g., `Read`) for other kinds.
+    pub fn io(operation: FileOperation, path: &FilePath, error: &std::io::Error) -> Self {
+        match FileErrorDetails::new(operation, path, Some(OsError::from(error))) {
+            Ok(details) => Self::classify(details, error.kind()),
+            Err(error) => error,
+        }
+    }
+
+    /// Like `io` for an OS path, e.g., a temporary file.
+    pub fn io_at(operation: FileOperation, path: &std::path::Path, error: &std::io::Error) -> Self {
+        match FileErrorDetails::at(operation, path, Some(OsError::from(error))) {
+            Ok(details) => Self::classify(details, error.kind()),
+            Err(error) => error,
+        }
+    }
+
+    /// Where (and how) this failed, if it involved the file system.
+    pub fn details(&self) -> Option<&FileErrorDetails> {
+        match self {
+            FileError::Missing(details)
+            | FileError::Permission(details)
+            | FileError::NoSpace(details)
+            | FileError::NotADirectory(details)
+            | FileError::Conflict(details)
+            | FileError::Encoding(details)
+            | FileError::Open(details)
+            | FileError::Read(details)
+            | FileError::Write(details) => Some(details),
+            FileError::OutOfMemory | FileError::Unknown => None,
+        }
+    }
+
+    fn classify(details: FileErrorDetails, kind: std::io::ErrorKind) -> Self {
+        match kind {
+            std::io::ErrorKind::NotFound => FileError::Missing(details),
+            std::io::ErrorKind::PermissionDenied | std::io::ErrorKind::ReadOnlyFilesystem => {
+                FileError::Permission(details)
+            }
+            std::io::ErrorKind::StorageFull | std::io::ErrorKind::QuotaExceeded => {
+                FileError::NoSpace(details)
+            }
+            std::io::ErrorKind::NotADirectory => FileError::NotADirectory(details),
+            _ => match details.operation {
+                FileOperation::Open
+                | FileOperation::List
+                | FileOperation::Check
+                | FileOperation::Watch => FileError::Open(details),
+                FileOperation::Read => FileError::Read(details),
+                FileOperation::Write | FileOperation::Rename => FileError::Write(details),
+            },
+        }
+    }
+}
+
+impl From<ContainerError> for FileError {
+    fn from(_error: ContainerError) -> Self {
+        FileError::OutOfMemory
+    }
+}
+
+/// E.g., "couldn't read `a.txt`: Permission denied (os error 13)".
+impl std::fmt::Display for FileError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        let (details, reason) = match self {
+            FileError::OutOfMemory => return write!(f, "out of memory"),
+            FileError::Unknown => return write!(f, "unknown file error"),
+            FileError::Missing(details) => (details, "it doesn't exist"),
+            FileError::Permission(details) => (details, "permission denied"),
+            FileError::NoSpace(details) => (details, "no space left"),
+            FileError::NotADirectory(details) => (details, "part of the path isn't a directory"),
+            FileError::Conflict(details) => (details, "it changed since it was read"),
+            FileError::Encoding(details) => (details, "it isn't UTF-8"),
+            FileError::Open(details) | FileError::Read(details) | FileError::Write(details) => {
+                (details, "unknown error")
+            }
+        };
+        write!(f, "couldn't {} `{}`: ", details.operation, details.path)?;
+        match details.os_error {
+            Some(os_error) => write!(f, "{}", os_error),
+            None => f.write_str(reason),
+        }
+    }
+}
+
+/// Where a file operation failed, and what the OS said about it.
+#[derive(Debug, Eq, PartialEq)]
+pub struct FileErrorDetails {
+    pub operation: FileOperation,
+    pub path: FilePath,
+    /// `None` if we found the problem ourselves, e.g., for a `Conflict`.
+    pub os_error: Option<OsError>,
+}
+
+impl FileErrorDetails {
+    pub fn new(
+        operation: FileOperation,
+        path: &FilePath,
+        os_error: Option<OsError>,
+    ) -> FileResult<Self> {
+        Ok(Self {
+            operation,
+            path: path.try_clone()?,
+            os_error,
+        })
+    }
+
+    pub fn at(
+        operation: FileOperation,
+        path: &std::path::Path,
+        os_error: Option<OsError>,
+    ) -> FileResult<Self> {
+        Ok(Self {
+            operation,
+            path: FilePath::try_from(path)?,
+            os_error,
+        })
+    }
+}
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
+pub enum FileOperation {
+    Open,
+    Read,
+    Write,
+    Rename,
+    /// Listing a directory's entries.
+    List,
+    /// Getting metadata, e.g., to see if a path exists.
+    Check,
+    /// Watching for changes, e.g., with a `FileWatcher`.
+    Watch,
+}
+
+impl std::fmt::Display for FileOperation {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.write_str(match self {
+            FileOperation::Open => "open",
+            FileOperation::Read => "read",
+            FileOperation::Write => "write",
+            FileOperation::Rename => "rename",
+            FileOperation::List => "list",
+            FileOperation::Check => "check",
+            FileOperation::Watch => "watch",
+        })
+    }
+}
+
+/// What the OS reported, from a `std::io::Error`.
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
+pub struct OsError {
+    pub kind: std::io::ErrorKind,
+    /// E.g., `ENOENT` on Unix-like systems; `None` if the error didn't come
+    /// directly from the OS.
+    pub errno: Option<i32>,
+}
+
+impl From<&std::io::Error> for OsError {
+    fn from(error: &std::io::Error) -> Self {
+        Self {
+            kind: error.kind(),
+            errno: error.raw_os_error(),
+        }
+    }
+}
+
+/// Uses the OS's message if we have an `errno`, e.g., "No such file or directory (os error 2)".
+impl std::fmt::Display for OsError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self.errno {
+            Some(errno) => write!(f, "{}", std::io::Error::from_raw_os_error(errno)),
+            None => write!(f, "{}", self.kind),
+        }
+    }
+}
+
+/// What to do when reading a file which doesn't exist.
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq, Hash)]
+pub enum MissingFile {
+    /// Return `FileError::Missing`.
+    #[default]
+    Error,
+    /// Treat it like an empty file (or directory), e.g., for a file we're creating.
+    Empty,
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    /// `ENOENT` on Unix-like systems and `ERROR_FILE_NOT_FOUND` on Windows.
+    fn not_found() -> std::io::Error {
+        std::io::Error::from_raw_os_error(2)
+    }
+
+    #[test]
+    fn classifies_by_kind_then_operation() {
+        let path = FilePath::try_from("a/b.txt").expect("ok");
+        let error = FileError::io(FileOperation::Read, &path, &not_found());
+        let details = error.details().expect("has details");
+        assert!(matches!(error, FileError::Missing(_)));
+        assert_eq!(details.operation, FileOperation::Read);
+        assert_eq!(details.path, path);
+        assert_eq!(
+            details.os_error,
+            Some(OsError {
+                kind: std::io::ErrorKind::NotFound,
+                errno: Some(2)
+            })
+        );
+
+        for (kind, operation, expected) in [
+            (
+                std::io::ErrorKind::PermissionDenied,
+                FileOperation::Open,
+                "Permission",
+            ),
+            (
+                std::io::ErrorKind::StorageFull,
+                FileOperation::Write,
+                "NoSpace",
+            ),
+            (
+                std::io::ErrorKind::NotADirectory,
+                FileOperation::List,
+                "NotADirectory",
+            ),
+            (std::io::ErrorKind::Other, FileOperation::Check, "Open"),
+            (std::io::ErrorKind::Interrupted, FileOperation::Read, "Read"),
+            (std::io::ErrorKind::Other, FileOperation::Rename, "Write"),
+        ] {
+            let error = FileError::io(operation, &path, &std::io::Error::from(kind));
+            assert!(format!("{:?}", error).starts_with(expected), "{:?}", error);
+            assert_eq!(
+                error.details().expect("has details").os_error,
+                Some(OsError { kind, errno: None })
+            );
+        }
+    }
+
+    #[test]
+    fn displays_operation_path_and_os_error() {
+        let path = FilePath::try_from("a/b.txt").expect("ok");
+        assert_eq!(
+            FileError::io(FileOperation::Open, &path, &not_found()).to_string(),
+            format!("couldn't open `a/b.txt`: {}", not_found())
+        );
+        assert_eq!(
+            FileError::io(
+                FileOperation::Write,
+                &path,
+                &std::io::Error::from(std::io::ErrorKind::PermissionDenied)
+            )
+            .to_string(),
+            "couldn't write `a/b.txt`: permission denied"
+        );
+        let details = FileErrorDetails::new(FileOperation::Write, &path, None).expect("ok");
+        assert_eq!(
+            FileError::Conflict(details).to_string(),
+            "couldn't write `a/b.txt`: it changed since it was read"
+        );
+        assert_eq!(FileError::OutOfMemory.to_string(), "out of memory");
+    }
+}
@@ -0,0 +1,270 @@
+use crate::core::encoding::*;
+use crate::core::file_error::*;
+use crate::core::file_path::*;
+use crate::core::filesystem::*;
+use crate::core::in_memory_file::*;
+use crate::core::non_local_array::*;
+use crate::core::offset::*;
+
+pub use crate::core::array::*;
+
+/// Lines of a file, without their line endings (like `InMemoryFile::lines`).
+/// Starts out as the whole file in one buffer with the offset of each line,
+/// so reading doesn't allocate per line, and becomes a `FileLine` per line
+/// (`InMemoryFileLines`) the first time a line is modified.
+pub struct FileLines {
+    storage: FileLinesStorage,
+}
+
+enum FileLinesStorage {
+    Contiguous {
+        /// The file as is, including line endings.
+        bytes: NonLocalArrayCount32<u8>,
+        /// Offset of the start of each line in `bytes`.
+        line_starts: NonLocalArrayCount32<Offset32>,
+    },
+    Editable(InMemoryFileLines),
+}
+
+impl Default for FileLines {
+    fn default() -> Self {
+        Self {
+            storage: FileLinesStorage::Editable(InMemoryFileLines::default()),
+        }
+    }
+}
+
+impl FileLines {
+    /// Reads the whole file into one buffer.  Returns `FileError::Missing`
+    /// if nothing exists at `path`.
+    pub fn read(path: &FilePath) -> FileResult<Self> {
+        Self::read_with(path, MissingFile::Error)
+    }
+
+    /// Like `read`, but a missing file has no lines.
+    pub fn read_or_empty(path: &FilePath) -> FileResult<Self> {
+        Self::read_with(path, MissingFile::Empty)
+    }
+
+    pub fn read_with(path: &FilePath, missing_file: MissingFile) -> FileResult<Self> {
+        Self::read_in(&DiskFilesystem, path, missing_file)
+    }
+
+    /// Like `read_with`, but reads via `filesystem` instead of the disk.
+    pub fn read_in(
+        filesystem: &dyn Filesystem,
+        path: &FilePath,
+        missing_file: MissingFile,
+    ) -> FileResult<Self> {
+        match filesystem.read(path) {
+            Ok(bytes) => Ok(Self::from_bytes(bytes)?),
+            Err(FileError::Missing(_)) if missing_file == MissingFile::Empty => Ok(Self::default()),
+            Err(error) => Err(error),
+        }
+    }
+
+    /// Takes ownership of a file's `bytes` and finds where each line starts.
+    /// Like `InMemoryFile`, skips a UTF-8 byte order mark.
+    pub fn from_bytes(bytes: NonLocalArrayCount32<u8>) -> ContainerResult<Self> {
+        let mut line_starts = NonLocalArrayCount32::<Offset32>::default();
+        if !bytes.is_empty() {
+            let start = if bytes.starts_with(UTF8_BYTE_ORDER_MARK) {
+                UTF8_BYTE_ORDER_MARK.len()
+            } else {
+                0
+            };
+            line_starts.insert(OrderedInsert::AtEnd(Offset32::of(start as i32)))?;
+        }
+        for (offset, byte) in bytes.iter().enumerate() {
+            // A trailing `\n` ends the last line rather than starting another.
+            if *byte == b'\n' && offset + 1 < bytes.len() {
+                line_starts.insert(OrderedInsert::AtEnd(Offset32::of(offset as i32 + 1)))?;
+            }
+        }
+        Ok(Self {
+            storage: FileLinesStorage::Contiguous { bytes, line_starts },
+        })
+    }
+
+    pub fn len(&self) -> usize {
+        match &self.storage {
+            FileLinesStorage::Contiguous { line_starts, .. } => line_starts.len(),
+            FileLinesStorage::Editable(lines) => lines.len(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// True once a line has been modified, i.e., each line has its own `FileLine`.
+    pub fn is_editable(&self) -> bool {
+        matches!(self.storage, FileLinesStorage::Editable(_))
+    }
+
+    pub fn line(&self, index: usize) -> Option<&[u8]> {
+        match &self.storage {
+            FileLinesStorage::Contiguous { bytes, line_starts } => {
+                let start = **line_starts.get(index)? as usize;
+                let mut end = match line_starts.get(index + 1) {
+                    Some(next_start) => **next_start as usize,
+                    None => bytes.len(),
+                };
+                if bytes[start..end].ends_with(b"\n") {
+                    end -= 1;
+                    if bytes[start..end].ends_with(b"\r") {
+                        end -= 1;
+                    }
+                }
+                Some(&bytes[start..end])
+            }
+            FileLinesStorage::Editable(lines) => lines.get(index).map(|line| &line[..]),
+        }
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &[u8]> + '_ {
+        (0..self.len()).map(|index| self.line(index).expect("in bounds"))
+    }
+
+    /// Converts to one `FileLine` per line if necessary; `None` if out of bounds.
+    pub fn line_mut(&mut self, index: usize) -> ContainerResult<Option<&mut FileLine>> {
+        Ok(self.editable()?.get_mut(index))
+    }
+
+    /// Converts to one `FileLine` per line if necessary, e.g., to add or remove lines.
+    pub fn editable(&mut self) -> ContainerResult<&mut InMemoryFileLines> {
+        if !self.is_editable() {
+            let mut lines = InMemoryFileLines::default();
+            lines.set_capacity(Count::of(self.len()).map_err(|_| ContainerError::OutOfMemory)?)?;
+            for line in self.iter() {
+                let mut file_line = FileLine::default();
+                file_line.insert_few(OrderedInsertFew::AtEnd(line, TypeMarker, TypeMarker))?;
+                lines.insert(OrderedInsert::AtEnd(file_line))?;
+            }
+            self.storage = FileLinesStorage::Editable(lines);
+        }
+        match &mut self.storage {
+            FileLinesStorage::Editable(lines) => Ok(lines),
+            FileLinesStorage::Contiguous { .. } => unreachable!("converted above"),
+        }
+    }
+
+    pub fn into_editable(mut self) -> ContainerResult<InMemoryFileLines> {
+        self.editable()?;
+        match self.storage {
+            FileLinesStorage::Editable(lines) => Ok(lines),
+            FileLinesStorage::Contiguous { .. } => unreachable!("converted above"),
+        }
+    }
+}
+
+impl std::ops::Index<usize> for FileLines {
+    type Output = [u8];
+
+    fn index(&self, index: usize) -> &[u8] {
+        self.line(index).expect("line index should be in bounds")
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn lines(bytes: &[u8]) -> FileLines {
+        let mut array = NonLocalArrayCount32::<u8>::default();
+        array
+            .insert_few(OrderedInsertFew::AtEnd(bytes, TypeMarker, TypeMarker))
+            .expect("ok");
+        FileLines::from_bytes(array).expect("ok")
+    }
+
+    #[test]
+    fn borrows_lines_without_line_endings() {
+        let file_lines = lines(b"one\r\ntwo\n\nthree\r\n");
+        assert!(!file_lines.is_editable());
+        assert_eq!(file_lines.len(), 4);
+        assert_eq!(
+            file_lines.iter().collect::<Vec<_>>(),
+            vec![&b"one"[..], b"two", b"", b"three"]
+        );
+        assert_eq!(&file_lines[1], b"two");
+        assert_eq!(file_lines.line(4), None);
+
+        let file_lines = lines(b"no\rfinal newline\r");
+        assert_eq!(
+            file_lines.iter().collect::<Vec<_>>(),
+            vec![&b"no\rfinal newline\r"[..]]
+        );
+
+        assert!(lines(b"").is_empty());
+        assert_eq!(lines(b"\n").iter().collect::<Vec<_>>(), vec![&b""[..]]);
+        assert_eq!(
+            lines(b"\n\n").iter().collect::<Vec<_>>(),
+            vec![&b""[..], b""]
+        );
+    }
+
+    #[test]
+    fn becomes_editable_when_modified() {
+        let mut file_lines = lines(b"first line is long enough to need the heap\nsecond\n");
+        let line = file_lines.line_mut(1).expect("ok").expect("in bounds");
+        *line = FileLine::try_from("2nd").expect("ok");
+        assert!(file_lines.is_editable());
+        assert_eq!(
+            file_lines.iter().collect::<Vec<_>>(),
+            vec![&b"first line is long enough to need the heap"[..], b"2nd"]
+        );
+        assert!(file_lines.line_mut(2).expect("ok").is_none());
+
+        file_lines
+            .editable()
+            .expect("ok")
+            .insert(OrderedInsert::AtEnd(
+                FileLine::try_from("third").expect("ok"),
+            ))
+            .expect("ok");
+        let editable = file_lines.into_editable().expect("ok");
+        assert_eq!(
+            &editable[..],
+            ["first line is long enough to need the heap", "2nd", "third"]
+        );
+    }
+
+    #[test]
+    fn missing_files_are_errors_unless_empty() {
+        let path = FilePath::try_from("tests/missing-file.txt").expect("ok");
+        match FileLines::read(&path) {
+            Err(FileError::Missing(details)) => assert_eq!(details.path, path),
+            _ => panic!("should be missing"),
+        }
+        assert!(FileLines::read_or_empty(&path).expect("ok").is_empty());
+    }
+
+    #[test]
+    fn matches_in_memory_file() {
+        for fixture in [
+            "tests/sample-file.txt",
+            "tests/empty-file.txt",
+            "tests/non-empty-file.txt",
+            "tests/crlf-file.txt",
+            "tests/mixed-line-endings-file.txt",
+            "tests/lone-carriage-return-file.txt",
+            "tests/byte-order-mark-file.txt",
+            "tests/missing-file.txt",
+        ] {
+            let path = FilePath::try_from(fixture).expect("ok");
+            let file_lines = FileLines::read_or_empty(&path).expect("ok");
+            let in_memory_file = InMemoryFile::open_or_empty(path).expect("ok");
+            assert_eq!(
+                file_lines.iter().collect::<Vec<_>>(),
+                in_memory_file
+                    .lines
+                    .iter()
+                    .map(|line| &line[..])
+                    .collect::<Vec<_>>(),
+                "{}",
+                fixture
+            );
+        }
+    }
+}
@@ -0,0 +1,1100 @@
+use crate::core::file_error::*;
+use crate::core::shtick::*;
+
+pub use crate::core::array::*;
+
+use std::ffi::{OsStr, OsString};
+
+/// A file path stored as [WTF-8](https://simonsapin.github.io/wtf-8/), i.e., UTF-8
+/// which also allows unpaired surrogates, so that we can represent any OS path
+/// without loss (see `core/file/path.oh`).
+///
+/// * On Windows, paths are UTF-16 which might have unpaired surrogates;
+///   these are kept as is (see `from_wide`).
+/// * On Unix-like systems, paths are bytes which might not be UTF-8; each byte
+///   which isn't part of valid UTF-8 becomes an unpaired surrogate in
+///   U+DC80..=U+DCFF (like Python's "surrogateescape", see `from_unix_bytes`).
+///
+/// Valid UTF-8 paths are stored as the same UTF-8 bytes on all systems.
+#[derive(Default, Eq, PartialEq)]
+pub struct FilePath {
+    wtf8: ShtickOptimized8,
+}
+
+impl FilePath {
+    pub fn from_os_str(os_str: &OsStr) -> ContainerResult<Self> {
+        #[cfg(unix)]
+        {
+            use std::os::unix::ffi::OsStrExt;
+            Self::from_unix_bytes(os_str.as_bytes())
+        }
+        #[cfg(windows)]
+        {
+            use std::os::windows::ffi::OsStrExt;
+            Self::from_wide(os_str.encode_wide())
+        }
+        #[cfg(not(any(unix, windows)))]
+        {
+            // Other platforms store `OsStr`s as UTF-8.
+            Self::from_unix_bytes(os_str.as_encoded_bytes())
+        }
+    }
+
+    pub fn to_os_string(&self) -> OsString {
+        #[cfg(unix)]
+        {
+            use std::os::unix::ffi::OsStringExt;
+            OsString::from_vec(self.unix_bytes().collect())
+        }
+        #[cfg(windows)]
+        {
+            use std::os::windows::ffi::OsStringExt;
+            OsString::from_wide(&self.encode_wide().collect::<Vec<u16>>())
+        }
+        #[cfg(not(any(unix, windows)))]
+        {
+            OsString::from(
+                String::from_utf8_lossy(&self.unix_bytes().collect::<Vec<u8>>()).into_owned(),
+            )
+        }
+    }
+
+    /// Converts Unix path bytes, where bytes which aren't valid UTF-8 become
+    /// unpaired surrogates, so that `unix_bytes` can recover them.
+    pub fn from_unix_bytes(bytes: &[u8]) -> ContainerResult<Self> {
+        let mut result = Self::default();
+        for chunk in bytes.utf8_chunks() {
+            result.wtf8.append(chunk.valid().as_bytes())?;
+            for byte in chunk.invalid() {
+                // Invalid bytes are never ASCII, so they're in 0x80..=0xff.
+                result.push_code_point(0xdc00 + *byte as u32)?;
+            }
+        }
+        Ok(result)
+    }
+
+    /// Inverse of `from_unix_bytes`.  Unpaired surrogates which aren't from
+    /// `from_unix_bytes` (e.g., from `from_wide`) can't be represented on Unix,
+    /// so we leave them as their WTF-8 bytes.
+    pub fn unix_bytes(&self) -> impl Iterator<Item = u8> + '_ {
+        Wtf8CodePoints::new(&self.wtf8).flat_map(|(code_point, bytes)| {
+            let escaped = (0xdc80..=0xdcff)
+                .contains(&code_point)
+                .then(|| (code_point - 0xdc00) as u8);
+            let bytes = if escaped.is_some() { &[][..] } else { bytes };
+            escaped.into_iter().chain(bytes.iter().copied())
+        })
+    }
+
+    /// Converts (potentially ill-formed) UTF-16, e.g., from a Windows path.
+    pub fn from_wide(units: impl IntoIterator<Item = u16>) -> ContainerResult<Self> {
+        let mut result = Self::default();
+        for decoded in char::decode_utf16(units) {
+            match decoded {
+                Ok(rune) => result.push_code_point(rune as u32)?,
+                Err(error) => result.push_code_point(error.unpaired_surrogate() as u32)?,
+            }
+        }
+        Ok(result)
+    }
+
+    /// Inverse of `from_wide`.
+    pub fn encode_wide(&self) -> impl Iterator<Item = u16> + '_ {
+        Wtf8CodePoints::new(&self.wtf8).flat_map(|(code_point, _)| {
+            let (first, second) = if code_point >= 0x10000 {
+                let code_point = code_point - 0x10000;
+                (
+                    0xd800 | (code_point >> 10) as u16,
+                    Some(0xdc00 | (code_point & 0x3ff) as u16),
+                )
+            } else {
+                (code_point as u16, None)
+            };
+            std::iter::once(first).chain(second)
+        })
+    }
+
+    /// The path as WTF-8.
+    pub fn as_wtf8(&self) -> &[u8] {
+        &self.wtf8
+    }
+
+    /// Inverse of `as_wtf8`; `wtf8` should be well-formed, e.g., from another path.
+    pub(crate) fn from_wtf8(wtf8: &[u8]) -> ContainerResult<Self> {
+        let mut result = Self::default();
+        result.wtf8.append(wtf8)?;
+        Ok(result)
+    }
+
+    /// Returns `None` if the path has any unpaired surrogates, i.e.,
+    /// it isn't valid UTF-8.
+    pub fn to_str(&self) -> Option<&str> {
+        std::str::from_utf8(&self.wtf8).ok()
+    }
+
+    /// Appends a code point to the WTF-8.  Appending a trailing surrogate
+    /// right after a leading surrogate would need to combine them, but we
+    /// never do that here since `char::decode_utf16` pairs them for us.
+    fn push_code_point(&mut self, code_point: u32) -> Containered {
+        let mut buffer = [0u8; 4];
+        let count = match code_point {
+            0..=0x7f => {
+                buffer[0] = code_point as u8;
+                1
+            }
+            0x80..=0x7ff => {
+                buffer[0] = 0xc0 | (code_point >> 6) as u8;
+                buffer[1] = 0x80 | (code_point & 0x3f) as u8;
+                2
+            }
+            0x800..=0xffff => {
+                buffer[0] = 0xe0 | (code_point >> 12) as u8;
+                buffer[1] = 0x80 | ((code_point >> 6) & 0x3f) as u8;
+                buffer[2] = 0x80 | (code_point & 0x3f) as u8;
+                3
+            }
+            _ => {
+                buffer[0] = 0xf0 | (code_point >> 18) as u8;
+                buffer[1] = 0x80 | ((code_point >> 12) & 0x3f) as u8;
+                buffer[2] = 0x80 | ((code_point >> 6) & 0x3f) as u8;
+                buffer[3] = 0x80 | (code_point & 0x3f) as u8;
+                4
+            }
+        };
+        self.wtf8.append(&buffer[0..count])
+    }
+}
+
+/// Iterates over code points (including unpaired surrogates) in well-formed
+/// WTF-8, along with the bytes for each code point.
+struct Wtf8CodePoints<'a> {
+    wtf8: &'a [u8],
+}
+
+impl<'a> Wtf8CodePoints<'a> {
+    fn new(wtf8: &'a [u8]) -> Self {
+        Self { wtf8 }
+    }
+}
+
+impl<'a> Iterator for Wtf8CodePoints<'a> {
+    type Item = (u32, &'a [u8]);
+
+    fn next(&mut self) -> Option<(u32, &'a [u8])> {
+        let first = *self.wtf8.first()?;
+        let count = match first {
+            0..=0x7f => 1,
+            0xc0..=0xdf => 2,
+            0xe0..=0xef => 3,
+            _ => 4,
+        };
+        let (bytes, rest) = self.wtf8.split_at(count);
+        self.wtf8 = rest;
+        let mut code_point = match count {
+            1 => first as u32,
+            2 => (first & 0x1f) as u32,
+            3 => (first & 0x0f) as u32,
+            _ => (first & 0x07) as u32,
+        };
+        for byte in &bytes[1..] {
+            code_point = (code_point << 6) | (byte & 0x3f) as u32;
+        }
+        Some((code_point, bytes))
+    }
+}
+
+impl std::ops::Deref for FilePath {
+    type Target = [u8];
+
+    /// The path as WTF-8.
+    fn deref(&self) -> &[u8] {
+        &self.wtf8
+    }
+}
+
+impl TryFrom<&str> for FilePath {
+    type Error = ContainerError;
+
+    fn try_from(value: &str) -> ContainerResult<Self> {
+        Ok(Self {
+            wtf8: ShtickOptimized8::try_from(value)?,
+        })
+    }
+}
+
+impl TryFrom<&OsStr> for FilePath {
+    type Error = ContainerError;
+
+    fn try_from(value: &OsStr) -> ContainerResult<Self> {
+        Self::from_os_str(value)
+    }
+}
+
+impl TryFrom<&std::path::Path> for FilePath {
+    type Error = ContainerError;
+
+    fn try_from(value: &std::path::Path) -> ContainerResult<Self> {
+        Self::from_os_str(value.as_os_str())
+    }
+}
+
+impl TryClone for FilePath {
+    type Error = ContainerError;
+
+    fn try_clone(&self) -> ContainerResult<Self> {
+        Ok(Self {
+            wtf8: self.wtf8.try_clone()?,
+        })
+    }
+}
+
+/// Prints unpaired surrogates as U+FFFD.
+impl std::fmt::Display for FilePath {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        use std::fmt::Write;
+        for (code_point, _) in Wtf8CodePoints::new(&self.wtf8) {
+            f.write_char(char::from_u32(code_point).unwrap_or(char::REPLACEMENT_CHARACTER))?;
+        }
+        Ok(())
+    }
+}
+
+impl std::fmt::Debug for FilePath {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self.to_str() {
+            Some(string) => write!(f, "FilePath::try_from({:?})", string),
+            None => write!(
+                f,
+                "FilePath::from_wide({:?})",
+                self.encode_wide().collect::<Vec<u16>>()
+            ),
+        }
+    }
+}
+
+/// Separator we add when making paths, e.g., for `DirectoryPath`s.
+pub const FILE_PATH_SEPARATOR: u8 = std::path::MAIN_SEPARATOR as u8;
+
+/// `/` is a separator everywhere; Windows also allows `\`.
+pub fn is_file_path_separator(byte: u8) -> bool {
+    byte == b'/' || (cfg!(windows) && byte == b'\\')
+}
+
+/// Path operations from `core/file/path.oh`.  These are purely mechanical and
+/// don't touch the file system, except for `resolve`.
+impl FilePath {
+    /// A directory path has a trailing separator, e.g., `a/b/`.  An empty path
+    /// is the current directory.
+    pub fn is_directory(&self) -> bool {
+        self.wtf8
+            .last()
+            .is_none_or(|byte| is_file_path_separator(*byte))
+    }
+
+    pub fn is_absolute(&self) -> bool {
+        self.wtf8
+            .first()
+            .is_some_and(|byte| is_file_path_separator(*byte))
+    }
+
+    /// Uses the trailing-separator convention to decide if this is a directory
+    /// or a regular file; use `resolve` to check the file system.
+    pub fn into_typed(self) -> ValidFilePath {
+        if self.is_directory() {
+            ValidFilePath::Directory(DirectoryPath { path: self })
+        } else {
+            ValidFilePath::RegularFile(RegularFilePath { path: self })
+        }
+    }
+
+    /// For a regular file, returns the directory it's in (e.g., `x/file.txt` -> `x/`).
+    /// For a directory, returns the parent directory (e.g., `x/y/` -> `x/`).
+    /// Root (`/`) and empty paths return themselves, and a single regular file or
+    /// directory (e.g., `file.txt` or `x/`) returns an empty path (the current directory).
+    /// This doesn't interpret `.` or `..`, so `normalize` first if you need to.
+    pub fn parent(&self) -> ContainerResult<DirectoryPath> {
+        let mut path = FilePath::default();
+        path.wtf8.append(&self.wtf8[0..self.parent_len()])?;
+        Ok(DirectoryPath { path })
+    }
+
+    /// Like `parent` but reuses this path's memory.
+    pub fn into_parent(mut self) -> DirectoryPath {
+        let parent_len = self.parent_len();
+        self.truncate(parent_len);
+        DirectoryPath { path: self }
+    }
+
+    /// Removes `.` components, empty components (e.g., `a//b`), and `..` components
+    /// (along with the component before them), e.g., `a/./b/../c` -> `a/c`.
+    /// Relative paths keep leading `..` components (e.g., `a/../../b` -> `../b`),
+    /// while absolute paths can't go above the root (`/../a` -> `/a`).
+    /// Paths ending in `.` or `..` become directories (e.g., `a/b/..` -> `a/`).
+    pub fn normalize(&self) -> ContainerResult<FilePath> {
+        let mut result = FilePath::default();
+        let root_len = if self.is_absolute() {
+            result.wtf8.append(&[FILE_PATH_SEPARATOR])?;
+            1
+        } else {
+            0
+        };
+        let mut ends_in_dots = false;
+        for component in self.wtf8[..].split(|byte| is_file_path_separator(*byte)) {
+            if !component.is_empty() {
+                ends_in_dots = matches!(component, b"." | b"..");
+            }
+            match component {
+                b"" | b"." => {}
+                b".." => {
+                    // `result` is either empty, root, or components with trailing separators.
+                    let last_start = result.parent_len().max(root_len);
+                    if last_start < result.len()
+                        && result.wtf8[last_start..result.len() - 1] != *b".."
+                    {
+                        result.truncate(last_start);
+                    } else if root_len == 0 {
+                        result.wtf8.append(b"..")?;
+                        result.wtf8.append(&[FILE_PATH_SEPARATOR])?;
+                    }
+                }
+                _ => {
+                    result.wtf8.append(component)?;
+                    result.wtf8.append(&[FILE_PATH_SEPARATOR])?;
+                }
+            }
+        }
+        let is_directory = self.is_directory() || ends_in_dots;
+        if !is_directory && result.len() > root_len {
+            result.truncate(result.len() - 1);
+        }
+        Ok(result)
+    }
+
+    /// Checks the file system to make sure that this path is (or could be made)
+    /// valid, i.e., that no directory in the path is a regular file.  This doesn't
+    /// care if the path exists, e.g., if `a/` exists but not `a/b/`, `a/b/c.txt` is
+    /// still a regular file path.  If `a/b` exists as a directory, we return a
+    /// directory path `a/b/`; otherwise `a/b` is a regular file path.
+    pub fn resolve(self) -> FileResult<ValidFilePath> {
+        let os_string = self.to_os_string();
+        let os_path = std::path::Path::new(&os_string);
+        // Check from the root up, since we can stop once something doesn't exist.
+        let ancestors: Vec<&std::path::Path> = os_path
+            .ancestors()
+            .skip(1)
+            .filter(|ancestor| !ancestor.as_os_str().is_empty())
+            .collect();
+        for ancestor in ancestors.into_iter().rev() {
+            match Self::is_existing_directory(ancestor)? {
+                Some(true) => {}
+                Some(false) => {
+                    return Err(FileError::NotADirectory(FileErrorDetails::at(
+                        FileOperation::Check,
+                        ancestor,
+                        None,
+                    )?));
+                }
+                None => return Ok(self.into_typed()),
+            }
+        }
+        match Self::is_existing_directory(os_path)? {
+            Some(false) if self.is_directory() => Err(FileError::NotADirectory(
+                FileErrorDetails::new(FileOperation::Check, &self, None)?,
+            )),
+            Some(true) if !self.is_directory() => {
+                let mut path = self;
+                path.wtf8
+                    .append(&[FILE_PATH_SEPARATOR])
+                    .map_err(|_| FileError::OutOfMemory)?;
+                Ok(ValidFilePath::Directory(DirectoryPath { path }))
+            }
+            _ => Ok(self.into_typed()),
+        }
+    }
+
+    /// `None` if nothing exists at `path`.
+    fn is_existing_directory(path: &std::path::Path) -> FileResult<Option<bool>> {
+        match std::fs::metadata(path) {
+            Ok(metadata) => Ok(Some(metadata.is_dir())),
+            Err(error) if error.kind() == std::io::ErrorKind::NotFound => Ok(None),
+            Err(error) => Err(FileError::io_at(FileOperation::Check, path, &error)),
+        }
+    }
+
+    /// Length of `parent()`, including its trailing separator.
+    fn parent_len(&self) -> usize {
+        let mut end = self.len();
+        if self.is_directory() {
+            if end <= 1 {
+                // Empty or root.
+                return end;
+            }
+            end -= 1;
+        }
+        match self.wtf8[0..end]
+            .iter()
+            .rposition(|byte| is_file_path_separator(*byte))
+        {
+            Some(separator) => separator + 1,
+            None => 0,
+        }
+    }
+
+    /// Replaces `range` of the WTF-8 with `bytes`.  We only do this next to
+    /// separators or `.`s, so we don't need to worry about pairing surrogates.
+    fn splice(&mut self, range: std::ops::Range<usize>, bytes: &[u8]) -> Containered {
+        if range.len() == bytes.len() {
+            self.wtf8[range].copy_from_slice(bytes);
+            return Ok(());
+        }
+        let mut wtf8 = ShtickOptimized8::default();
+        wtf8.append(&self.wtf8[0..range.start])?;
+        wtf8.append(bytes)?;
+        wtf8.append(&self.wtf8[range.end..])?;
+        self.wtf8 = wtf8;
+        Ok(())
+    }
+
+    fn truncate(&mut self, len: usize) {
+        self.wtf8
+            .set_count(Count::of(len).expect("should fit"))
+            .expect("truncating should not alloc");
+    }
+}
+
+/// A `FilePath` which is a directory, i.e., empty or with a trailing separator.
+#[derive(Default, Eq, PartialEq, Debug)]
+pub struct DirectoryPath {
+    path: FilePath,
+}
+
+impl DirectoryPath {
+    /// Joins `path` onto this directory, e.g., `a/` joined with `b/c.txt` gives
+    /// `a/b/c.txt`.  An absolute `path` is returned as is.
+    pub fn join(&self, path: &FilePath) -> ContainerResult<FilePath> {
+        let mut result = FilePath::default();
+        if !path.is_absolute() {
+            result.wtf8.append(&self.path)?;
+        }
+        result.wtf8.append(path)?;
+        Ok(result)
+    }
+
+    /// The last component without its trailing separator, e.g., `a/b/` -> `b`.
+    /// Empty for the root and the current directory.
+    pub fn name(&self) -> &[u8] {
+        if self.path.len() <= 1 {
+            return &[];
+        }
+        &self.path.wtf8[self.path.parent_len()..self.path.len() - 1]
+    }
+
+    pub fn into_path(self) -> FilePath {
+        self.path
+    }
+}
+
+impl TryClone for DirectoryPath {
+    type Error = ContainerError;
+
+    fn try_clone(&self) -> ContainerResult<Self> {
+        Ok(Self {
+            path: self.path.try_clone()?,
+        })
+    }
+}
+
+impl TryFrom<FilePath> for DirectoryPath {
+    type Error = ContainerError;
+
+    /// Adds a trailing separator if necessary, e.g., `a/b` -> `a/b/`.
+    fn try_from(mut path: FilePath) -> ContainerResult<Self> {
+        if !path.is_directory() {
+            path.wtf8.append(&[FILE_PATH_SEPARATOR])?;
+        }
+        Ok(Self { path })
+    }
+}
+
+impl std::ops::Deref for DirectoryPath {
+    type Target = FilePath;
+
+    fn deref(&self) -> &FilePath {
+        &self.path
+    }
+}
+
+/// Why `RegularFilePath::set_file_name` (etc.) rejected a name.
+#[derive(Eq, PartialEq, Copy, Clone, Debug, Hash)]
+pub enum FileNameError {
+    /// Program ran out of memory.
+    OutOfMemory,
+    /// The name is empty, which would make the path a directory.
+    Empty,
+    /// The name is `.` or `..`, which refer to directories.
+    Dots,
+    /// The name contains a separator, e.g., `/`, so it isn't just a name.
+    HasSeparator,
+}
+
+pub type FileNameResult<T> = Result<T, FileNameError>;
+
+impl From<ContainerError> for FileNameError {
+    fn from(_error: ContainerError) -> Self {
+        FileNameError::OutOfMemory
+    }
+}
+
+impl FileNameError {
+    /// Checks that `name` can be the file name (or base name) of a regular file.
+    pub fn check(name: &str) -> FileNameResult<()> {
+        match name {
+            "" => Err(FileNameError::Empty),
+            "." | ".." => Err(FileNameError::Dots),
+            _ => Self::check_separators(name),
+        }
+    }
+
+    fn check_separators(name: &str) -> FileNameResult<()> {
+        if name.bytes().any(is_file_path_separator) {
+            Err(FileNameError::HasSeparator)
+        } else {
+            Ok(())
+        }
+    }
+}
+
+/// A `FilePath` which isn't a directory, i.e., non-empty without a trailing separator.
+#[derive(Eq, PartialEq, Debug)]
+pub struct RegularFilePath {
+    path: FilePath,
+}
+
+impl RegularFilePath {
+    /// Everything after the last separator, e.g., `a/b.tar.gz` -> `b.tar.gz`.
+    pub fn file_name(&self) -> &[u8] {
+        &self.path.wtf8[self.file_name_start()..]
+    }
+
+    /// The file name without its extension, e.g., `a/b.tar.gz` -> `b.tar`.
+    pub fn base_name(&self) -> &[u8] {
+        &self.path.wtf8[self.file_name_start()..self.base_name_end()]
+    }
+
+    /// Everything after the last `.` in the file name, e.g., `a/b.tar.gz` -> `gz`.
+    /// A leading `.` doesn't count, so `.profile` has no extension.
+    pub fn extension(&self) -> &[u8] {
+        &self.path.wtf8[self.extension_start().unwrap_or(self.path.len())..]
+    }
+
+    /// Errors (without changing the path) if `file_name` is empty, `.` or `..`,
+    /// or has any separators.
+    pub fn set_file_name(&mut self, file_name: &str) -> FileNameResult<()> {
+        FileNameError::check(file_name)?;
+        let start = self.file_name_start();
+        Ok(self
+            .path
+            .splice(start..self.path.len(), file_name.as_bytes())?)
+    }
+
+    /// Keeps the extension, e.g., `a/b.txt` with `c` becomes `a/c.txt`.
+    /// Errors like `set_file_name`, e.g., an empty base name would turn
+    /// `a/b.txt` into the hidden `a/.txt`.
+    pub fn set_base_name(&mut self, base_name: &str) -> FileNameResult<()> {
+        FileNameError::check(base_name)?;
+        let range = self.file_name_start()..self.base_name_end();
+        Ok(self.path.splice(range, base_name.as_bytes())?)
+    }
+
+    /// An empty `extension` removes the `.` as well, e.g., `a/b.txt` with `` becomes `a/b`.
+    /// Errors if `extension` has any separators.
+    pub fn set_extension(&mut self, extension: &str) -> FileNameResult<()> {
+        FileNameError::check_separators(extension)?;
+        Ok(self.replace_extension(extension)?)
+    }
+
+    fn replace_extension(&mut self, extension: &str) -> Containered {
+        if extension.is_empty() {
+            let base_name_end = self.base_name_end();
+            self.path.truncate(base_name_end);
+            return Ok(());
+        }
+        match self.extension_start() {
+            Some(start) => {
+                let len = self.path.len();
+                self.path.splice(start..len, extension.as_bytes())
+            }
+            None => {
+                self.path.wtf8.append(b".")?;
+                self.path.wtf8.append(extension.as_bytes())
+            }
+        }
+    }
+
+    /// Consuming version of `set_file_name`.
+    pub fn with_file_name(mut self, file_name: &str) -> FileNameResult<Self> {
+        self.set_file_name(file_name)?;
+        Ok(self)
+    }
+
+    /// Consuming version of `set_base_name`.
+    pub fn with_base_name(mut self, base_name: &str) -> FileNameResult<Self> {
+        self.set_base_name(base_name)?;
+        Ok(self)
+    }
+
+    /// Consuming version of `set_extension`.
+    pub fn with_extension(mut self, extension: &str) -> FileNameResult<Self> {
+        self.set_extension(extension)?;
+        Ok(self)
+    }
+
+    pub fn into_path(self) -> FilePath {
+        self.path
+    }
+
+    fn file_name_start(&self) -> usize {
+        self.path.parent_len()
+    }
+
+    /// Offset of the first byte after the `.` of the extension.
+    fn extension_start(&self) -> Option<usize> {
+        let start = self.file_name_start();
+        match self.path.wtf8[start..]
+            .iter()
+            .rposition(|byte| *byte == b'.')
+        {
+            Some(0) | None => None,
+            Some(dot) => Some(start + dot + 1),
+        }
+    }
+
+    fn base_name_end(&self) -> usize {
+        match self.extension_start() {
+            Some(start) => start - 1,
+            None => self.path.len(),
+        }
+    }
+}
+
+impl TryFrom<FilePath> for RegularFilePath {
+    /// Gives back the path if it's a directory.
+    type Error = FilePath;
+
+    fn try_from(path: FilePath) -> Result<Self, FilePath> {
+        if path.is_directory() {
+            Err(path)
+        } else {
+            Ok(Self { path })
+        }
+    }
+}
+
+impl TryClone for RegularFilePath {
+    type Error = ContainerError;
+
+    fn try_clone(&self) -> ContainerResult<Self> {
+        Ok(Self {
+            path: self.path.try_clone()?,
+        })
+    }
+}
+
+impl std::ops::Deref for RegularFilePath {
+    type Target = FilePath;
+
+    fn deref(&self) -> &FilePath {
+        &self.path
+    }
+}
+
+#[derive(Eq, PartialEq, Debug)]
+pub enum ValidFilePath {
+    RegularFile(RegularFilePath),
+    Directory(DirectoryPath),
+}
+
+impl ValidFilePath {
+    /// Types `path` as a directory (adding a trailing separator if necessary)
+    /// or a regular file, e.g., from what the file system says it is.
+    pub fn of(path: FilePath, is_directory: bool) -> ContainerResult<Self> {
+        if is_directory {
+            Ok(ValidFilePath::Directory(DirectoryPath::try_from(path)?))
+        } else {
+            Ok(path.into_typed())
+        }
+    }
+
+    pub fn exists(&self) -> bool {
+        std::fs::exists(self.to_os_string()).unwrap_or(false)
+    }
+
+    pub fn into_path(self) -> FilePath {
+        match self {
+            ValidFilePath::RegularFile(path) => path.into_path(),
+            ValidFilePath::Directory(path) => path.into_path(),
+        }
+    }
+}
+
+/// The current directory, i.e., an empty path.
+impl Default for ValidFilePath {
+    fn default() -> Self {
+        ValidFilePath::Directory(DirectoryPath::default())
+    }
+}
+
+impl TryClone for ValidFilePath {
+    type Error = ContainerError;
+
+    fn try_clone(&self) -> ContainerResult<Self> {
+        Ok(match self {
+            ValidFilePath::RegularFile(path) => ValidFilePath::RegularFile(path.try_clone()?),
+            ValidFilePath::Directory(path) => ValidFilePath::Directory(path.try_clone()?),
+        })
+    }
+}
+
+impl std::ops::Deref for ValidFilePath {
+    type Target = FilePath;
+
+    fn deref(&self) -> &FilePath {
+        match self {
+            ValidFilePath::RegularFile(path) => path,
+            ValidFilePath::Directory(path) => path,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use proptest::prelude::*;
+
+    #[test]
+    fn utf8_paths_are_unchanged() {
+        let path = FilePath::try_from("dir/fïlé-🙂.oh").expect("ok");
+        assert_eq!(path.as_wtf8(), "dir/fïlé-🙂.oh".as_bytes());
+        assert_eq!(path.to_str(), Some("dir/fïlé-🙂.oh"));
+        assert_eq!(path.to_os_string(), OsString::from("dir/fïlé-🙂.oh"));
+        assert_eq!(
+            FilePath::from_os_str(OsStr::new("dir/fïlé-🙂.oh")).expect("ok"),
+            path
+        );
+        assert_eq!(
+            format!("{:?}", path),
+            "FilePath::try_from(\"dir/fïlé-🙂.oh\")"
+        );
+    }
+
+    #[test]
+    fn non_utf8_unix_bytes_become_surrogates() {
+        let path = FilePath::from_unix_bytes(b"a\xffb\xed\xa0\x80").expect("ok");
+        assert_eq!(
+            path.as_wtf8(),
+            b"a\xed\xb3\xbfb\xed\xb3\xad\xed\xb2\xa0\xed\xb2\x80"
+        );
+        assert_eq!(path.to_str(), None);
+        assert_eq!(format!("{}", path), "a\u{fffd}b\u{fffd}\u{fffd}\u{fffd}");
+        assert_eq!(
+            path.unix_bytes().collect::<Vec<u8>>(),
+            b"a\xffb\xed\xa0\x80"
+        );
+    }
+
+    #[test]
+    fn unpaired_surrogates_from_wide() {
+        let wide = [0x61, 0xd83d, 0xde42, 0xd800, 0x62, 0xdfff];
+        let path = FilePath::from_wide(wide).expect("ok");
+        assert_eq!(
+            path.as_wtf8(),
+            b"a\xf0\x9f\x99\x82\xed\xa0\x80b\xed\xbf\xbf"
+        );
+        assert_eq!(path.encode_wide().collect::<Vec<u16>>(), wide);
+        assert_eq!(
+            format!("{:?}", path),
+            "FilePath::from_wide([97, 55357, 56898, 55296, 98, 57343])"
+        );
+    }
+
+    #[test]
+    fn converts_os_paths() {
+        let path = FilePath::try_from(std::path::Path::new("tests/sample-file.txt")).expect("ok");
+        assert_eq!(
+            path,
+            FilePath::try_from("tests/sample-file.txt").expect("ok")
+        );
+    }
+
+    fn path(string: &str) -> FilePath {
+        FilePath::try_from(string).expect("ok")
+    }
+
+    /// Expected path with the OS separator, for paths that we build.
+    fn os(string: &str) -> String {
+        string.replace('/', std::path::MAIN_SEPARATOR_STR)
+    }
+
+    fn regular(string: &str) -> RegularFilePath {
+        RegularFilePath::try_from(path(string)).expect("ok")
+    }
+
+    #[test]
+    fn directories_have_trailing_separators() {
+        assert!(path("").is_directory());
+        assert!(path("/").is_directory());
+        assert!(path("a/b/").is_directory());
+        assert!(!path("a/b").is_directory());
+        assert!(path("/a").is_absolute());
+        assert!(!path("a/").is_absolute());
+
+        assert!(matches!(
+            path("a/").into_typed(),
+            ValidFilePath::Directory(_)
+        ));
+        assert!(matches!(
+            path("a").into_typed(),
+            ValidFilePath::RegularFile(_)
+        ));
+        assert_eq!(RegularFilePath::try_from(path("a/")), Err(path("a/")));
+        let directory = DirectoryPath::try_from(path("a/b")).expect("ok");
+        assert_eq!(directory.to_str(), Some(os("a/b/").as_str()));
+    }
+
+    #[test]
+    fn parents() {
+        for (child, parent) in [
+            ("x/file.txt", "x/"),
+            ("x/y/", "x/"),
+            ("/x", "/"),
+            ("/", "/"),
+            ("", ""),
+            ("file.txt", ""),
+            ("x/", ""),
+            ("x//y", "x//"),
+        ] {
+            assert_eq!(path(child).parent().expect("ok").to_str(), Some(parent));
+            assert_eq!(path(child).into_parent().to_str(), Some(parent));
+        }
+    }
+
+    #[test]
+    fn directory_names() {
+        for (directory, name) in [("a/b/", "b"), ("b/", "b"), ("/", ""), ("", "")] {
+            let directory = DirectoryPath::try_from(path(directory)).expect("ok");
+            assert_eq!(directory.name(), name.as_bytes());
+        }
+    }
+
+    #[test]
+    fn normalizing() {
+        for (messy, clean) in [
+            ("a/./b/../c", "a/c"),
+            ("a//b/", "a/b/"),
+            ("./a", "a"),
+            ("a/b/..", "a/"),
+            ("a/b/.", "a/b/"),
+            ("a/..", ""),
+            ("a/../../b", "../b"),
+            ("../../a/..", "../../"),
+            ("/../a", "/a"),
+            ("/a/../..", "/"),
+            ("", ""),
+            ("/", "/"),
+        ] {
+            assert_eq!(
+                path(messy).normalize().expect("ok").to_str(),
+                Some(os(clean).as_str()),
+                "normalizing {:?}",
+                messy
+            );
+        }
+    }
+
+    #[test]
+    fn joining() {
+        let directory = DirectoryPath::try_from(path("a/")).expect("ok");
+        assert_eq!(
+            directory.join(&path("b/c.txt")).expect("ok").to_str(),
+            Some("a/b/c.txt")
+        );
+        assert_eq!(
+            directory.join(&path("/abs")).expect("ok").to_str(),
+            Some("/abs")
+        );
+        assert_eq!(
+            DirectoryPath::default()
+                .join(&path("b"))
+                .expect("ok")
+                .to_str(),
+            Some("b")
+        );
+    }
+
+    #[test]
+    fn file_name_parts() {
+        let file = regular("/this/is/my/file.tar.ok");
+        assert_eq!(file.file_name(), b"file.tar.ok");
+        assert_eq!(file.base_name(), b"file.tar");
+        assert_eq!(file.extension(), b"ok");
+
+        let hidden = regular("dir/.profile");
+        assert_eq!(hidden.base_name(), b".profile");
+        assert_eq!(hidden.extension(), b"");
+        let bare = regular("README");
+        assert_eq!(bare.file_name(), b"README");
+        assert_eq!(bare.extension(), b"");
+        let dotted = regular("dir.d/file.");
+        assert_eq!(dotted.base_name(), b"file");
+        assert_eq!(dotted.extension(), b"");
+    }
+
+    #[test]
+    fn setting_file_name_parts() {
+        let mut file = regular("/my/file.ok");
+        file.set_extension("txt").expect("ok");
+        assert_eq!(file.to_str(), Some("/my/file.txt"));
+        file.set_extension("").expect("ok");
+        assert_eq!(file.to_str(), Some("/my/file"));
+        file.set_extension("md").expect("ok");
+        assert_eq!(file.to_str(), Some("/my/file.md"));
+        file.set_base_name("notes").expect("ok");
+        assert_eq!(file.to_str(), Some("/my/notes.md"));
+        file.set_file_name(".hidden").expect("ok");
+        assert_eq!(file.to_str(), Some("/my/.hidden"));
+        file.set_extension("oh").expect("ok");
+        assert_eq!(file.to_str(), Some("/my/.hidden.oh"));
+
+        let file = regular("a.b")
+            .with_base_name("c")
+            .and_then(|file| file.with_extension("d"))
+            .and_then(|file| file.with_file_name("e.f.g"))
+            .expect("ok");
+        assert_eq!(file.to_str(), Some("e.f.g"));
+    }
+
+    #[test]
+    fn setting_file_name_parts_checks_names() {
+        let mut file = regular("a/x.txt");
+        assert_eq!(file.set_file_name(""), Err(FileNameError::Empty));
+        assert_eq!(file.set_file_name(".."), Err(FileNameError::Dots));
+        assert_eq!(file.set_file_name("b/c"), Err(FileNameError::HasSeparator));
+        assert_eq!(file.set_base_name(""), Err(FileNameError::Empty));
+        assert_eq!(file.set_base_name("."), Err(FileNameError::Dots));
+        assert_eq!(file.set_base_name("/"), Err(FileNameError::HasSeparator));
+        assert_eq!(file.set_extension("t/xt"), Err(FileNameError::HasSeparator));
+        // Nothing changed.
+        assert_eq!(file.to_str(), Some("a/x.txt"));
+        assert!(!file.is_directory());
+
+        assert_eq!(
+            regular("a/b")
+                .with_file_name("")
+                .map(|file| file.into_path()),
+            Err(FileNameError::Empty)
+        );
+    }
+
+    #[test]
+    fn resolving_checks_for_conflicts() {
+        let root = std::env::temp_dir().join(format!("oh-file-path-{}", std::process::id()));
+        _ = std::fs::remove_dir_all(&root);
+        std::fs::create_dir_all(root.join("dir")).expect("ok");
+        std::fs::write(root.join("file.txt"), b"hi").expect("ok");
+        let under_root = |relative: &str| {
+            let path = FilePath::try_from(root.join(relative).as_path()).expect("ok");
+            if relative.ends_with('/') {
+                DirectoryPath::try_from(path).expect("ok").into_path()
+            } else {
+                path
+            }
+        };
+
+        let resolved = under_root("dir").resolve().expect("ok");
+        assert!(matches!(resolved, ValidFilePath::Directory(_)));
+        assert!(resolved.exists());
+        let resolved = under_root("file.txt").resolve().expect("ok");
+        assert!(matches!(resolved, ValidFilePath::RegularFile(_)));
+        assert!(resolved.exists());
+        let resolved = under_root("dir/new/deeper.txt").resolve().expect("ok");
+        assert!(matches!(resolved, ValidFilePath::RegularFile(_)));
+        assert!(!resolved.exists());
+        let resolved = under_root("new/").resolve().expect("ok");
+        assert!(matches!(resolved, ValidFilePath::Directory(_)));
+
+        // Depending on the OS, we might find this ourselves or get an error.
+        for (relative, not_a_directory) in [
+            ("file.txt/", "file.txt/"),
+            ("file.txt/nested.txt", "file.txt"),
+        ] {
+            match under_root(relative).resolve() {
+                Err(FileError::NotADirectory(details)) => {
+                    assert_eq!(details.operation, FileOperation::Check);
+                    assert_eq!(details.path, under_root(not_a_directory));
+                }
+                result => panic!("expected not a directory, got {:?}", result),
+            }
+        }
+        std::fs::remove_dir_all(&root).expect("ok");
+    }
+
+    proptest! {
+        #[test]
+        fn unix_bytes_round_trip(bytes: Vec<u8>) {
+            let path = FilePath::from_unix_bytes(&bytes).expect("ok");
+            prop_assert_eq!(path.unix_bytes().collect::<Vec<u8>>(), bytes);
+        }
+
+        #[test]
+        fn wide_round_trips(wide: Vec<u16>) {
+            let path = FilePath::from_wide(wide.iter().copied()).expect("ok");
+            prop_assert_eq!(path.encode_wide().collect::<Vec<u16>>(), wide);
+        }
+
+        #[test]
+        fn surrogate_heavy_wide_round_trips(
+            wide in prop::collection::vec(prop_oneof![0xd800u16..0xe000, 0u16..0x80], 0..32)
+        ) {
+            let path = FilePath::from_wide(wide.iter().copied()).expect("ok");
+            prop_assert_eq!(path.encode_wide().collect::<Vec<u16>>(), wide);
+        }
+
+        #[test]
+        fn utf8_matches_wtf8(string: String) {
+            let path = FilePath::try_from(string.as_str()).expect("ok");
+            prop_assert_eq!(path.to_str(), Some(string.as_str()));
+            prop_assert_eq!(
+                FilePath::from_wide(string.encode_utf16()).expect("ok"),
+                path
+            );
+        }
+
+        #[test]
+        fn os_string_round_trips(bytes: Vec<u8>) {
+            let os_string = os_string_from_test_bytes(&bytes);
+            let path = FilePath::from_os_str(&os_string).expect("ok");
+            prop_assert_eq!(path.to_os_string(), os_string);
+        }
+    }
+
+    #[cfg(unix)]
+    fn os_string_from_test_bytes(bytes: &[u8]) -> OsString {
+        use std::os::unix::ffi::OsStringExt;
+        OsString::from_vec(bytes.to_vec())
+    }
+
+    #[cfg(windows)]
+    fn os_string_from_test_bytes(bytes: &[u8]) -> OsString {
+        use std::os::windows::ffi::OsStringExt;
+        let wide: Vec<u16> = bytes
+            .chunks(2)
+            .map(|pair| u16::from_le_bytes([pair[0], *pair.get(1).unwrap_or(&0)]))
+            .collect();
+        OsString::from_wide(&wide)
+    }
+
+    #[cfg(not(any(unix, windows)))]
+    fn os_string_from_test_bytes(bytes: &[u8]) -> OsString {
+        OsString::from(String::from_utf8_lossy(bytes).into_owned())
+    }
+}
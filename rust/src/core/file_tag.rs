@@ -0,0 +1,245 @@
+use crate::core::bit_array::*;
+use crate::core::non_local_array::*;
+use crate::core::shtick::*;
+use crate::core::utf8_shtick::*;
+
+pub use crate::core::array::*;
+
+/// File tags from `core/file/tag.oh`, which turn a file path into a shorter,
+/// reversible string, e.g., for prefixing generated C code.
+impl<S: SignedPrimitive, const N_LOCAL: usize> Utf8ShtickOptimized<S, N_LOCAL> {
+    /// Substitutes common di/trigraphs (e.g., `.oh` -> `.`), escapes bytes which
+    /// would be confused with a substitution, then swizzles the runes.
+    pub fn file_tag(path: &str) -> ContainerResult<Self> {
+        swizzle(&file_tag_encode(path)?)
+    }
+
+    /// Inverse of `file_tag`.
+    pub fn file_untag(tag: &str) -> ContainerResult<Self> {
+        file_tag_decode(&swizzle::<S, N_LOCAL>(tag)?)
+    }
+}
+
+/// Substitutions for (in order of preference) trigraphs and digraphs.
+/// Each substitution must be unique so that we can decode it.
+const FILE_TAG_SUBSTITUTIONS: [(&[u8], u8); 27] = [
+    (b".oh", b'.'),
+    (b"ar", b'^'),
+    (b"ck", b'K'),
+    (b"co", b'{'),
+    (b"de", b'}'),
+    (b"ee", b'E'),
+    (b"en", b'&'),
+    (b"er", b'>'),
+    (b"he", b'H'),
+    (b"ie", b'?'),
+    (b"in", b'I'),
+    (b"ir", b'<'),
+    (b"it", b'T'),
+    (b"ll", b'L'),
+    (b"nn", b'N'),
+    (b"qu", b'q'),
+    (b"oa", b'\''),
+    (b"on", b']'),
+    (b"or", b'|'),
+    (b"oo", b'O'),
+    (b"ou", b'U'),
+    (b"ow", b'!'),
+    (b"th", b'#'),
+    (b"te", b';'),
+    (b"ti", b'['),
+    (b"to", b'Q'),
+    (b"ur", b'V'),
+];
+
+const FILE_TAG_ESCAPE: u8 = b'\\';
+
+/// Substituted bytes and the escape itself need escaping when they appear as is.
+const FILE_TAG_NEEDS_ESCAPE: BitArray256 = {
+    let mut set = BitArray256::of(&[FILE_TAG_ESCAPE]);
+    let mut i = 0;
+    while i < FILE_TAG_SUBSTITUTIONS.len() {
+        set = set.union(BitArray256::of(&[FILE_TAG_SUBSTITUTIONS[i].1]));
+        i += 1;
+    }
+    set
+};
+
+fn file_tag_encode(path: &str) -> ContainerResult<Utf8ShtickOptimized64> {
+    let path = path.as_bytes();
+    let mut result = ShtickOptimized64::default();
+    let mut index = 0;
+    while index < path.len() {
+        let remaining = &path[index..];
+        if let Some((from, to)) = FILE_TAG_SUBSTITUTIONS
+            .iter()
+            .find(|(from, _)| remaining.starts_with(from))
+        {
+            result.insert(OrderedInsert::AtEnd(*to))?;
+            index += from.len();
+            continue;
+        }
+        let mut byte = path[index];
+        if cfg!(windows) && byte == b'\\' {
+            // `/` is invalid in a Windows file name, so use it for the
+            // directory separator instead of `\`.
+            byte = b'/';
+        }
+        if FILE_TAG_NEEDS_ESCAPE.get(byte) {
+            result.insert(OrderedInsert::AtEnd(FILE_TAG_ESCAPE))?;
+        }
+        result.insert(OrderedInsert::AtEnd(byte))?;
+        index += 1;
+    }
+    // We only removed or added whole ASCII sequences, so this is still UTF-8.
+    Ok(unsafe { Utf8ShtickOptimized::from_utf8_unchecked(result) })
+}
+
+fn file_tag_decode<S: SignedPrimitive, const N_LOCAL: usize>(
+    tag: &str,
+) -> ContainerResult<Utf8ShtickOptimized<S, N_LOCAL>> {
+    let mut result = ShtickOptimized::<S, N_LOCAL>::default();
+    let mut escaped = false;
+    for byte in tag.bytes() {
+        if escaped {
+            escaped = false;
+            result.insert(OrderedInsert::AtEnd(byte))?;
+        } else if byte == FILE_TAG_ESCAPE {
+            escaped = true;
+        } else {
+            match FILE_TAG_SUBSTITUTIONS.iter().find(|(_, to)| *to == byte) {
+                Some((from, _)) => result.append(from)?,
+                None => result.insert(OrderedInsert::AtEnd(byte))?,
+            }
+        }
+    }
+    // We only removed or added whole ASCII sequences, so this is still UTF-8.
+    Ok(unsafe { Utf8ShtickOptimized::from_utf8_unchecked(result) })
+}
+
+/// Swaps every other rune from the start with those from the back.  For an odd
+/// number of runes, we start swapping on the first or second rune, alternating
+/// based on the count.  This is its own inverse.
+fn swizzle<S: SignedPrimitive, const N_LOCAL: usize>(
+    string: &str,
+) -> ContainerResult<Utf8ShtickOptimized<S, N_LOCAL>> {
+    let mut runes = NonLocalArrayCount64::<char>::default();
+    for rune in string.chars() {
+        runes.insert(OrderedInsert::AtEnd(rune))?;
+    }
+    let rune_count = runes.len();
+    let exclusive_halfway_index = rune_count / 2;
+    let mut index = if rune_count % 2 == 0 {
+        0
+    } else {
+        1 - exclusive_halfway_index % 2
+    };
+    while index < exclusive_halfway_index {
+        runes.swap(index, rune_count - 1 - index);
+        index += 2;
+    }
+    let mut result = Utf8ShtickOptimized::default();
+    for rune in runes.iter() {
+        result.insert(OrderedInsert::AtEnd(*rune))?;
+    }
+    Ok(result)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use proptest::prelude::*;
+
+    fn encode(path: &str) -> String {
+        file_tag_encode(path).expect("ok").to_string()
+    }
+
+    fn swizzled(string: &str) -> String {
+        swizzle::<i64, 16>(string).expect("ok").to_string()
+    }
+
+    #[test]
+    fn encode_dot_oh() {
+        assert_eq!(encode(".oh"), ".");
+        assert_eq!(encode(".or"), "\\.|");
+        assert_eq!(encode("en.oh"), "&.");
+    }
+
+    #[test]
+    fn encode_qu_and_q() {
+        assert_eq!(encode("quell"), "qeL");
+        assert_eq!(encode("qwack"), "\\qwaK");
+    }
+
+    #[test]
+    fn encode_brackets_and_braces() {
+        assert_eq!(encode("tion"), "[]");
+        assert_eq!(encode("code"), "{}");
+    }
+
+    #[test]
+    fn encode_escapes_the_escape() {
+        if !cfg!(windows) {
+            assert_eq!(encode("a\\b"), "a\\\\b");
+        }
+    }
+
+    #[test]
+    fn substitutions_are_reversible() {
+        for (i, (_, to)) in FILE_TAG_SUBSTITUTIONS.iter().enumerate() {
+            assert_ne!(*to, FILE_TAG_ESCAPE);
+            for (_, other) in &FILE_TAG_SUBSTITUTIONS[i + 1..] {
+                assert_ne!(to, other);
+            }
+        }
+    }
+
+    #[test]
+    fn swizzle_small_string_edge_cases() {
+        assert_eq!(swizzled(""), "");
+        assert_eq!(swizzled("q"), "q");
+    }
+
+    #[test]
+    fn swizzle_odd_sized_strings_alternate_first_and_second() {
+        assert_eq!(swizzled("xyz"), "zyx");
+        assert_eq!(swizzled("54321"), "52341");
+        assert_eq!(swizzled("pqrstuv"), "vqtsrup");
+        assert_eq!(swizzled("123456789"), "183654729");
+    }
+
+    #[test]
+    fn swizzle_even_sized_strings_start_on_first() {
+        assert_eq!(swizzled("mn"), "nm");
+        assert_eq!(swizzled("abcd"), "dbca");
+        assert_eq!(swizzled("123456"), "624351");
+        assert_eq!(swizzled("builders"), "sueldirb");
+    }
+
+    #[test]
+    fn swizzle_runes() {
+        assert_eq!(swizzled("añ€"), "€ña");
+    }
+
+    #[test]
+    fn tag_and_untag() {
+        let tag = Utf8ShtickOptimized16::file_tag("core/file/tag.oh").expect("ok");
+        assert_eq!(tag.as_str(), "{getfeli//ar.");
+        assert_eq!(
+            Utf8ShtickOptimized16::file_untag(&tag)
+                .expect("ok")
+                .as_str(),
+            "core/file/tag.oh"
+        );
+    }
+
+    proptest! {
+        #[test]
+        fn untag_reverses_tag(path in "[a-z./\\\\|{}qéπ🙂]*") {
+            prop_assume!(!cfg!(windows));
+            let tag = Utf8ShtickOptimized8::file_tag(&path).expect("ok");
+            let untagged = Utf8ShtickOptimized8::file_untag(&tag).expect("ok");
+            prop_assert_eq!(untagged.as_str(), path.as_str());
+        }
+    }
+}
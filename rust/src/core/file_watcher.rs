@@ -0,0 +1,501 @@
+use crate::core::file_error::*;
+use crate::core::file_path::*;
+use crate::core::filesystem::*;
+use crate::core::in_memory_file::*;
+use crate::core::non_local_array::*;
+
+pub use crate::core::array::*;
+
+use inotify::{EventMask, Inotify, WatchDescriptor, WatchMask};
+use std::collections::{BTreeMap, BTreeSet};
+use std::time::{Duration, Instant};
+
+/// Watches files and directories for changes (via inotify), e.g., so that
+/// `oh watch` can recompile only what changed.  Events are debounced: a batch
+/// is only returned once nothing has happened for `debounce`, so that e.g.
+/// an editor's save (write a temporary file, then rename it) is one change.
+///
+/// Events say something *may* have changed; e.g., touching a file is a
+/// `Changed` event.  `InMemoryFile::has_changed` is the source of truth,
+/// see `InMemoryFile::reload_if_watched`.
+pub struct FileWatcher {
+    inotify: Inotify,
+    /// Regular files are watched via their directory, so that we still see
+    /// them after they're replaced by a rename (like `InMemoryFile::write`).
+    directories: BTreeMap<i32, WatchedDirectory>,
+    /// Changes since the last batch, by path.
+    pending: BTreeMap<Vec<u8>, FileWatchEvent>,
+    last_event: Option<Instant>,
+    pub debounce: Duration,
+}
+
+struct WatchedDirectory {
+    descriptor: WatchDescriptor,
+    path: DirectoryPath,
+    /// Whether all entries are watched, or only those in `names`.
+    whole: bool,
+    names: BTreeSet<Vec<u8>>,
+    /// Watched entries which exist (as far as events tell us), so that
+    /// renaming onto one of them is a change rather than an addition.
+    existing: BTreeSet<Vec<u8>>,
+}
+
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq, Hash)]
+pub enum FileWatchChange {
+    Added,
+    Removed,
+    /// Contents (or metadata) may have changed.
+    #[default]
+    Changed,
+}
+
+#[derive(Debug, Default, Eq, PartialEq)]
+pub struct FileWatchEvent {
+    /// Directories have a trailing separator, as usual.
+    pub path: ValidFilePath,
+    pub change: FileWatchChange,
+}
+
+impl TryClone for FileWatchEvent {
+    type Error = ContainerError;
+
+    fn try_clone(&self) -> ContainerResult<Self> {
+        Ok(Self {
+            path: self.path.try_clone()?,
+            change: self.change,
+        })
+    }
+}
+
+/// A debounced batch of events, sorted by path, with at most one per path.
+pub type FileWatchEvents = NonLocalArrayCount32<FileWatchEvent>;
+
+const FILE_WATCHER_MASK: WatchMask = WatchMask::CREATE
+    .union(WatchMask::DELETE)
+    .union(WatchMask::MODIFY)
+    .union(WatchMask::CLOSE_WRITE)
+    .union(WatchMask::ATTRIB)
+    .union(WatchMask::MOVED_FROM)
+    .union(WatchMask::MOVED_TO)
+    .union(WatchMask::DELETE_SELF)
+    .union(WatchMask::MOVE_SELF)
+    .union(WatchMask::ONLYDIR);
+
+impl FileWatcher {
+    pub fn new(debounce: Duration) -> FileResult<Self> {
+        let inotify = Inotify::init()
+            .map_err(|error| FileError::io(FileOperation::Watch, &FilePath::default(), &error))?;
+        Ok(Self {
+            inotify,
+            directories: BTreeMap::new(),
+            pending: BTreeMap::new(),
+            last_event: None,
+            debounce,
+        })
+    }
+
+    /// Watches an `InMemoryFile`'s path, i.e., a regular file's contents or
+    /// a directory's entries (see `watch_file` and `watch_directory`).  Only files
+    /// on disk can be watched, e.g., not ones in a `MemoryFilesystem`.
+    pub fn watch(&mut self, file: &InMemoryFile) -> Filed {
+        if !file.filesystem().is_disk() {
+            let os_error = OsError {
+                kind: std::io::ErrorKind::Unsupported,
+                errno: None,
+            };
+            return Err(FileError::Open(FileErrorDetails::new(
+                FileOperation::Watch,
+                &file.path,
+                Some(os_error),
+            )?));
+        }
+        match file.path.try_clone()?.into_typed() {
+            ValidFilePath::RegularFile(path) => self.watch_file(&path),
+            ValidFilePath::Directory(path) => self.watch_directory(&path),
+        }
+    }
+
+    /// Reports the file (on disk) being added, removed, or changed.  Its directory must exist.
+    pub fn watch_file(&mut self, path: &RegularFilePath) -> Filed {
+        let directory = path.parent()?;
+        let name = path.file_name().to_vec();
+        let exists = std::fs::symlink_metadata(path.to_os_string()).is_ok();
+        let directory = self.watched_directory(directory)?;
+        if exists {
+            directory.existing.insert(name.clone());
+        }
+        directory.names.insert(name);
+        Ok(())
+    }
+
+    /// Reports entries (on disk) being added, removed, or changed (but not entries of
+    /// subdirectories; watch those separately), and the directory being removed.
+    pub fn watch_directory(&mut self, path: &DirectoryPath) -> Filed {
+        let directory = self.watched_directory(path.try_clone()?)?;
+        directory.whole = true;
+        // Listed after we start watching, so we don't miss anything in between.
+        let read_dir = std::fs::read_dir(Self::os_path(path))
+            .map_err(|error| FileError::io(FileOperation::Watch, path, &error))?;
+        for entry in read_dir {
+            let entry = entry.map_err(|error| FileError::io(FileOperation::Watch, path, &error))?;
+            let name = FilePath::from_os_str(&entry.file_name())?;
+            directory.existing.insert(name.as_wtf8().to_vec());
+        }
+        Ok(())
+    }
+
+    fn os_path(path: &DirectoryPath) -> std::ffi::OsString {
+        if path.is_empty() {
+            std::ffi::OsString::from(".")
+        } else {
+            path.to_os_string()
+        }
+    }
+
+    fn watched_directory(&mut self, path: DirectoryPath) -> FileResult<&mut WatchedDirectory> {
+        let descriptor = self
+            .inotify
+            .watches()
+            .add(Self::os_path(&path), FILE_WATCHER_MASK)
+            .map_err(|error| FileError::io(FileOperation::Watch, &path, &error))?;
+        // inotify gives the same descriptor if we're already watching the directory.
+        Ok(self
+            .directories
+            .entry(descriptor.get_watch_descriptor_id())
+            .or_insert_with(|| WatchedDirectory {
+                descriptor,
+                path,
+                whole: false,
+                names: BTreeSet::new(),
+                existing: BTreeSet::new(),
+            }))
+    }
+
+    /// Stops watching everything, e.g., to start over with a new set of files.
+    pub fn clear(&mut self) -> Filed {
+        for (_, directory) in std::mem::take(&mut self.directories) {
+            // Fails if the directory was already removed, which is fine.
+            let _ = self.inotify.watches().remove(directory.descriptor);
+        }
+        self.pending.clear();
+        self.last_event = None;
+        Ok(())
+    }
+
+    /// Returns a batch of changes if there are any and nothing has happened
+    /// for `debounce`; otherwise returns an empty batch without waiting.
+    pub fn poll(&mut self) -> FileResult<FileWatchEvents> {
+        self.read_events()?;
+        let mut events = FileWatchEvents::default();
+        let quiet = self
+            .last_event
+            .is_some_and(|last_event| last_event.elapsed() >= self.debounce);
+        if quiet {
+            for (_, event) in std::mem::take(&mut self.pending) {
+                events.insert(OrderedInsert::AtEnd(event))?;
+            }
+            self.last_event = None;
+        }
+        Ok(events)
+    }
+
+    /// Like `poll`, but waits up to `timeout` for a batch of changes.
+    pub fn wait(&mut self, timeout: Duration) -> FileResult<FileWatchEvents> {
+        let start = Instant::now();
+        loop {
+            let events = self.poll()?;
+            if !events.is_empty() || start.elapsed() >= timeout {
+                return Ok(events);
+            }
+            // Good enough for human-scale edits, and simpler than `poll(2)`.
+            std::thread::sleep(self.debounce.min(Duration::from_millis(10)));
+        }
+    }
+
+    fn read_events(&mut self) -> Filed {
+        let mut buffer = [0u8; 4096];
+        loop {
+            let events = match self.inotify.read_events(&mut buffer) {
+                Ok(events) => events,
+                Err(error) if error.kind() == std::io::ErrorKind::WouldBlock => return Ok(()),
+                Err(error) => {
+                    return Err(FileError::io(
+                        FileOperation::Watch,
+                        &FilePath::default(),
+                        &error,
+                    ));
+                }
+            };
+            for event in events {
+                if event.mask.contains(EventMask::Q_OVERFLOW) {
+                    self.assume_everything_changed()?;
+                    continue;
+                }
+                let id = event.wd.get_watch_descriptor_id();
+                if event.mask.contains(EventMask::IGNORED) {
+                    // The directory was removed (or unwatched).
+                    self.directories.remove(&id);
+                    continue;
+                }
+                let Some(directory) = self.directories.get_mut(&id) else {
+                    continue;
+                };
+                let Some(name) = event.name else {
+                    if event
+                        .mask
+                        .intersects(EventMask::DELETE_SELF | EventMask::MOVE_SELF)
+                        && directory.whole
+                    {
+                        let path = ValidFilePath::Directory(directory.path.try_clone()?);
+                        self.add_event(path, FileWatchChange::Removed)?;
+                    }
+                    continue;
+                };
+                let name = FilePath::from_os_str(name)?;
+                if !directory.whole && !directory.names.contains(name.as_wtf8()) {
+                    continue;
+                }
+                let change = if event
+                    .mask
+                    .intersects(EventMask::CREATE | EventMask::MOVED_TO)
+                {
+                    if directory.existing.insert(name.as_wtf8().to_vec()) {
+                        FileWatchChange::Added
+                    } else {
+                        // Replaced by a rename, e.g., an editor's save.
+                        FileWatchChange::Changed
+                    }
+                } else if event
+                    .mask
+                    .intersects(EventMask::DELETE | EventMask::MOVED_FROM)
+                {
+                    directory.existing.remove(name.as_wtf8());
+                    FileWatchChange::Removed
+                } else {
+                    FileWatchChange::Changed
+                };
+                let path = ValidFilePath::of(
+                    directory.path.join(&name)?,
+                    event.mask.contains(EventMask::ISDIR),
+                )?;
+                self.add_event(path, change)?;
+            }
+        }
+    }
+
+    /// Merges with any pending change, e.g., a file which was added and then
+    /// changed is just added, and one which was added then removed is nothing.
+    /// Only these (watched) events hold off the batch, not e.g. unwatched siblings.
+    fn add_event(&mut self, path: ValidFilePath, change: FileWatchChange) -> Filed {
+        self.last_event = Some(Instant::now());
+        let key = path.as_wtf8().to_vec();
+        let merged = match (self.pending.get(&key).map(|event| event.change), change) {
+            (None, change) => Some(change),
+            (Some(FileWatchChange::Added), FileWatchChange::Removed) => None,
+            (Some(FileWatchChange::Added), _) => Some(FileWatchChange::Added),
+            (Some(FileWatchChange::Removed), FileWatchChange::Removed) => {
+                Some(FileWatchChange::Removed)
+            }
+            (Some(FileWatchChange::Removed), _) => Some(FileWatchChange::Changed),
+            (Some(FileWatchChange::Changed), FileWatchChange::Removed) => {
+                Some(FileWatchChange::Removed)
+            }
+            (Some(FileWatchChange::Changed), _) => Some(FileWatchChange::Changed),
+        };
+        match merged {
+            Some(change) => {
+                self.pending.insert(key, FileWatchEvent { path, change });
+            }
+            None => {
+                self.pending.remove(&key);
+            }
+        }
+        Ok(())
+    }
+
+    /// inotify dropped events, so report everything we watch as changed.
+    fn assume_everything_changed(&mut self) -> Filed {
+        let mut paths = NonLocalArrayCount32::<ValidFilePath>::default();
+        for directory in self.directories.values() {
+            if directory.whole {
+                paths.insert(OrderedInsert::AtEnd(ValidFilePath::Directory(
+                    directory.path.try_clone()?,
+                )))?;
+            }
+            for name in directory.names.iter() {
+                let path = directory.path.join(&FilePath::from_wtf8(name)?)?;
+                paths.insert(OrderedInsert::AtEnd(path.into_typed()))?;
+            }
+        }
+        for path in paths.iter_mut() {
+            self.add_event(std::mem::take(path), FileWatchChange::Changed)?;
+        }
+        Ok(())
+    }
+}
+
+impl InMemoryFile {
+    /// Re-reads the file if `events` (from a `FileWatcher`) mention it (or for
+    /// a directory, one of its entries) and it `has_changed`, returning true if so.
+    /// Note that this discards any edits to `lines`.
+    pub fn reload_if_watched(&mut self, events: &FileWatchEvents) -> FileResult<bool> {
+        let mentioned = events.iter().any(|event| {
+            event.path.as_wtf8() == self.path.as_wtf8()
+                || (self.is_directory()
+                    && event
+                        .path
+                        .parent()
+                        .is_ok_and(|parent| parent.as_wtf8() == self.path.as_wtf8()))
+        });
+        if !mentioned {
+            return Ok(false);
+        }
+        self.reload_if_changed()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::core::memory_filesystem::*;
+    use crate::core::testing::*;
+
+    const DEBOUNCE: Duration = Duration::from_millis(50);
+    const TIMEOUT: Duration = Duration::from_secs(5);
+
+    fn file_path(path: &std::path::Path) -> FilePath {
+        FilePath::try_from(path).expect("ok")
+    }
+
+    /// Events as `(name relative to directory, change)`.
+    fn changes(
+        events: &FileWatchEvents,
+        directory: &std::path::Path,
+    ) -> Vec<(String, FileWatchChange)> {
+        let prefix = file_path(directory).to_str().expect("ok").len() + 1;
+        events
+            .iter()
+            .map(|event| {
+                (
+                    event.path.to_str().expect("ok")[prefix..].to_string(),
+                    event.change,
+                )
+            })
+            .collect()
+    }
+
+    #[test]
+    fn reports_debounced_changes_in_a_directory() {
+        let directory = TestingDirectory::new("file-watcher-directory");
+        std::fs::write(directory.join("changed.oh"), b"old").expect("ok");
+        std::fs::write(directory.join("removed.oh"), b"").expect("ok");
+        std::fs::write(directory.join("replaced.oh"), b"old").expect("ok");
+        let mut watcher = FileWatcher::new(DEBOUNCE).expect("ok");
+        let path = DirectoryPath::try_from(file_path(&directory)).expect("ok");
+        watcher.watch_directory(&path).expect("ok");
+        assert!(watcher.poll().expect("ok").is_empty());
+
+        std::fs::write(directory.join("added.oh"), b"").expect("ok");
+        std::fs::write(directory.join("added.oh"), b"edited").expect("ok");
+        std::fs::write(directory.join("changed.oh"), b"new").expect("ok");
+        std::fs::remove_file(directory.join("removed.oh")).expect("ok");
+        std::fs::write(directory.join("temporary.oh"), b"").expect("ok");
+        std::fs::remove_file(directory.join("temporary.oh")).expect("ok");
+        std::fs::write(directory.join("replaced.oh.tmp"), b"new").expect("ok");
+        std::fs::rename(
+            directory.join("replaced.oh.tmp"),
+            directory.join("replaced.oh"),
+        )
+        .expect("ok");
+        std::fs::create_dir(directory.join("subdirectory")).expect("ok");
+        let events = watcher.wait(TIMEOUT).expect("ok");
+        assert_eq!(
+            changes(&events, &directory),
+            vec![
+                ("added.oh".to_string(), FileWatchChange::Added),
+                ("changed.oh".to_string(), FileWatchChange::Changed),
+                ("removed.oh".to_string(), FileWatchChange::Removed),
+                ("replaced.oh".to_string(), FileWatchChange::Changed),
+                ("subdirectory/".to_string(), FileWatchChange::Added),
+            ]
+        );
+        assert!(watcher.poll().expect("ok").is_empty());
+    }
+
+    #[test]
+    fn follows_files_replaced_by_renames() {
+        let directory = TestingDirectory::new("file-watcher-file");
+        let path = directory.join("main.oh");
+        std::fs::write(&path, b"main(): null\n").expect("ok");
+        std::fs::write(directory.join("other.oh"), b"").expect("ok");
+        let mut file = InMemoryFile::open(file_path(&path)).expect("ok");
+        let mut watcher = FileWatcher::new(DEBOUNCE).expect("ok");
+        watcher.watch(&file).expect("ok");
+
+        // Unwatched siblings are ignored, and don't hold off the next batch.
+        std::fs::write(directory.join("other.oh"), b"edit").expect("ok");
+        assert!(watcher.poll().expect("ok").is_empty());
+        assert_eq!(watcher.last_event, None);
+        // Like an editor's save (or `InMemoryFile::write`).
+        std::fs::write(directory.join("main.oh.tmp"), b"main(): 3\n").expect("ok");
+        std::fs::rename(directory.join("main.oh.tmp"), &path).expect("ok");
+        let events = watcher.wait(TIMEOUT).expect("ok");
+        assert_eq!(
+            changes(&events, &directory),
+            vec![("main.oh".to_string(), FileWatchChange::Changed)]
+        );
+        assert_eq!(file.reload_if_watched(&events), Ok(true));
+        assert_eq!(&file.lines[..], ["main(): 3"]);
+
+        // Still watching after the rename; touching isn't a real change.
+        let touched = std::time::SystemTime::now() - Duration::from_secs(60);
+        std::fs::File::options()
+            .write(true)
+            .open(&path)
+            .expect("ok")
+            .set_modified(touched)
+            .expect("ok");
+        let events = watcher.wait(TIMEOUT).expect("ok");
+        assert_eq!(
+            changes(&events, &directory),
+            vec![("main.oh".to_string(), FileWatchChange::Changed)]
+        );
+        assert_eq!(file.reload_if_watched(&events), Ok(false));
+    }
+
+    #[test]
+    fn only_files_on_disk_are_watched() {
+        let filesystem = std::sync::Arc::new(MemoryFilesystem::default());
+        filesystem.insert_file("main.oh", b"").expect("ok");
+        let path = FilePath::try_from("main.oh").expect("ok");
+        let file = InMemoryFile::open_in(
+            filesystem,
+            path.try_clone().expect("ok"),
+            MissingFile::Error,
+        )
+        .expect("ok");
+        let mut watcher = FileWatcher::new(DEBOUNCE).expect("ok");
+        match watcher.watch(&file) {
+            Err(FileError::Open(details)) => {
+                assert_eq!(details.operation, FileOperation::Watch);
+                assert_eq!(details.path, path);
+            }
+            result => panic!("should not watch memory files: {:?}", result),
+        }
+    }
+
+    #[test]
+    fn missing_directories_are_errors() {
+        let directory = TestingDirectory::new("file-watcher-missing");
+        let mut watcher = FileWatcher::new(DEBOUNCE).expect("ok");
+        let path =
+            RegularFilePath::try_from(file_path(&directory.join("missing/file.oh"))).expect("ok");
+        match watcher.watch_file(&path) {
+            Err(FileError::Missing(details)) => {
+                assert_eq!(details.operation, FileOperation::Watch);
+                assert_eq!(details.path, file_path(&directory.join("missing/")));
+            }
+            result => panic!("should be missing: {:?}", result),
+        }
+    }
+}
@@ -0,0 +1,325 @@
+use crate::core::directory::*;
+use crate::core::file_error::*;
+use crate::core::file_path::*;
+use crate::core::non_local_array::*;
+
+pub use crate::core::array::*;
+
+use std::io::{Read, Seek, Write};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, LazyLock};
+
+/// Everything we do with files goes through this, so that tests can use a
+/// `MemoryFilesystem` instead of real files (see `DiskFilesystem`).
+/// Paths use the trailing-separator convention of `FilePath` for directories.
+pub trait Filesystem: Send + Sync {
+    /// Opens a regular file for reading.
+    fn open(&self, path: &FilePath) -> FileResult<Box<dyn Read + '_>>;
+
+    /// Opens a regular file to read and/or write a piece at a time, e.g., for a
+    /// `RegularFile`.  Unlike `write`, readers can see partial writes.
+    fn open_file(
+        &self,
+        path: &FilePath,
+        options: FileOpenOptions,
+    ) -> FileResult<Box<dyn FileHandle>>;
+
+    /// Reads a whole regular file.
+    fn read(&self, path: &FilePath) -> FileResult<NonLocalArrayCount32<u8>>;
+
+    /// Replaces (or creates) a regular file with `bytes`, so that readers see
+    /// either the old or new contents, never a partial write.  The file's
+    /// directory must already exist.
+    fn write(&self, path: &FilePath, bytes: &[u8]) -> Filed;
+
+    /// A directory's entries (without `.` and `..`), sorted by path.
+    fn list(&self, directory: &DirectoryPath) -> FileResult<DirectoryEntries>;
+
+    /// `None` if nothing exists at `path`.
+    fn stat(&self, path: &FilePath) -> FileResult<Option<FileStat>>;
+
+    /// Moves a file or directory, replacing any regular file at `to`.
+    fn rename(&self, from: &FilePath, to: &FilePath) -> Filed;
+
+    /// Whether paths are real paths on disk, e.g., which a `FileWatcher` can watch.
+    fn is_disk(&self) -> bool {
+        false
+    }
+}
+
+/// How `Filesystem::open_file` opens a file, like `std::fs::OpenOptions`.
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq, Hash)]
+pub struct FileOpenOptions {
+    pub read: bool,
+    pub write: bool,
+    /// Every write goes to the end of the file.
+    pub append: bool,
+    /// Creates the file if it doesn't exist.
+    pub create: bool,
+    /// Empties the file if it does exist.
+    pub truncate: bool,
+}
+
+/// A regular file opened by `Filesystem::open_file`.
+pub trait FileHandle: Read + Write + Seek + Send {
+    /// In bytes, including anything written so far.
+    fn size(&mut self) -> std::io::Result<u64>;
+}
+
+impl FileHandle for std::fs::File {
+    fn size(&mut self) -> std::io::Result<u64> {
+        Ok(self.metadata()?.len())
+    }
+}
+
+/// A `Filesystem` which can be shared, e.g., by a few `InMemoryFile`s.
+pub type SharedFilesystem = Arc<dyn Filesystem>;
+
+/// What `Filesystem::stat` knows about a path.
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
+pub struct FileStat {
+    pub is_directory: bool,
+    /// In bytes, for a regular file.
+    pub size: u64,
+    pub modified: Option<std::time::SystemTime>,
+    /// Which file this is, if the file system can tell; `None` in memory,
+    /// where there are no links.
+    pub identity: Option<FileIdentity>,
+}
+
+/// Identifies a file (or directory) no matter which path leads to it, e.g.,
+/// through symbolic links.  On Unix, these are the device and inode numbers.
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash, Ord, PartialOrd)]
+pub struct FileIdentity {
+    pub device: u64,
+    pub inode: u64,
+}
+
+impl FileIdentity {
+    #[cfg(unix)]
+    fn of(metadata: &std::fs::Metadata) -> Option<Self> {
+        use std::os::unix::fs::MetadataExt;
+        Some(Self {
+            device: metadata.dev(),
+            inode: metadata.ino(),
+        })
+    }
+
+    #[cfg(not(unix))]
+    fn of(_metadata: &std::fs::Metadata) -> Option<Self> {
+        None
+    }
+}
+
+/// The real file system, via `std::fs`.
+#[derive(Copy, Clone, Debug, Default)]
+pub struct DiskFilesystem;
+
+static DISK_FILESYSTEM: LazyLock<SharedFilesystem> = LazyLock::new(|| Arc::new(DiskFilesystem));
+
+impl DiskFilesystem {
+    pub fn shared() -> SharedFilesystem {
+        DISK_FILESYSTEM.clone()
+    }
+
+    /// An empty path is the current directory.
+    fn os_path(path: &FilePath) -> std::ffi::OsString {
+        if path.is_empty() {
+            std::ffi::OsString::from(".")
+        } else {
+            path.to_os_string()
+        }
+    }
+
+    /// Creates a new hidden file next to the file we're writing, e.g.,
+    /// `.name.txt.1234-0.tmp` for `name.txt`.
+    fn create_temporary_file(
+        directory: &std::path::Path,
+        file_name: &std::ffi::OsStr,
+    ) -> FileResult<(std::fs::File, std::path::PathBuf)> {
+        static TEMPORARY_FILE_COUNT: AtomicUsize = AtomicUsize::new(0);
+        loop {
+            let mut temporary_name = std::ffi::OsString::from(".");
+            temporary_name.push(file_name);
+            temporary_name.push(format!(
+                ".{}-{}.tmp",
+                std::process::id(),
+                TEMPORARY_FILE_COUNT.fetch_add(1, Ordering::Relaxed)
+            ));
+            let temporary_path = directory.join(temporary_name);
+            match std::fs::OpenOptions::new()
+                .write(true)
+                .create_new(true)
+                .open(&temporary_path)
+            {
+                Ok(file) => return Ok((file, temporary_path)),
+                // Probably left over from a crashed process with the same id.
+                Err(error) if error.kind() == std::io::ErrorKind::AlreadyExists => continue,
+                Err(error) => {
+                    return Err(FileError::io_at(
+                        FileOperation::Write,
+                        &temporary_path,
+                        &error,
+                    ));
+                }
+            }
+        }
+    }
+
+    /// Writes `bytes` to `file` (at `temporary_path`), copying permissions from `path`.
+    fn write_temporary_file(
+        mut file: std::fs::File,
+        temporary_path: &std::path::Path,
+        path: &std::path::Path,
+        bytes: &[u8],
+    ) -> Filed {
+        let writing =
+            |error: std::io::Error| FileError::io_at(FileOperation::Write, temporary_path, &error);
+        file.write_all(bytes).map_err(writing)?;
+        if let Ok(metadata) = std::fs::metadata(path) {
+            // Keep the original file's permissions, e.g., if it's executable.
+            file.set_permissions(metadata.permissions())
+                .map_err(writing)?;
+        }
+        file.sync_all().map_err(writing)
+    }
+
+    /// Makes a rename durable on Unix-like systems; there's no equivalent on Windows.
+    fn sync_directory(directory: &std::path::Path) {
+        if cfg!(unix)
+            && let Ok(directory) = std::fs::File::open(directory)
+        {
+            let _ = directory.sync_all();
+        }
+    }
+}
+
+impl Filesystem for DiskFilesystem {
+    fn is_disk(&self) -> bool {
+        true
+    }
+
+    fn open(&self, path: &FilePath) -> FileResult<Box<dyn Read + '_>> {
+        let file = std::fs::File::open(path.to_os_string())
+            .map_err(|error| FileError::io(FileOperation::Open, path, &error))?;
+        Ok(Box::new(file))
+    }
+
+    fn open_file(
+        &self,
+        path: &FilePath,
+        options: FileOpenOptions,
+    ) -> FileResult<Box<dyn FileHandle>> {
+        let file = std::fs::OpenOptions::new()
+            .read(options.read)
+            .write(options.write)
+            .append(options.append)
+            .create(options.create)
+            .truncate(options.truncate)
+            .open(path.to_os_string())
+            .map_err(|error| FileError::io(FileOperation::Open, path, &error))?;
+        Ok(Box::new(file))
+    }
+
+    fn read(&self, path: &FilePath) -> FileResult<NonLocalArrayCount32<u8>> {
+        let mut file = std::fs::File::open(path.to_os_string())
+            .map_err(|error| FileError::io(FileOperation::Open, path, &error))?;
+        let reading = |error: std::io::Error| FileError::io(FileOperation::Read, path, &error);
+        let size = file
+            .metadata()
+            .map_err(reading)?
+            .len()
+            .try_into()
+            .map_err(|_| FileError::OutOfMemory)?;
+        let mut bytes = NonLocalArrayCount32::<u8>::default();
+        bytes.set_count(Count::of(size).map_err(|_| FileError::OutOfMemory)?)?;
+        let mut bytes_read = 0;
+        while bytes_read < size {
+            match file.read(&mut bytes[bytes_read..]).map_err(reading)? {
+                0 => break,
+                count => bytes_read += count,
+            }
+        }
+        if bytes_read < size {
+            // The file shrank since we got its size.
+            bytes
+                .set_count(Count::of(bytes_read).expect("smaller"))
+                .expect("shrinking should not alloc");
+        }
+        // In case the file grew since we got its size.
+        let mut buffer = [0u8; 4096];
+        loop {
+            let count = file.read(&mut buffer).map_err(reading)?;
+            if count == 0 {
+                break;
+            }
+            bytes.insert_few(OrderedInsertFew::AtEnd(
+                &buffer[0..count],
+                TypeMarker,
+                TypeMarker,
+            ))?;
+        }
+        Ok(bytes)
+    }
+
+    /// Writes to a temporary file in the same directory, syncs it, then renames
+    /// it over `path`.
+    fn write(&self, path: &FilePath, bytes: &[u8]) -> Filed {
+        let os_path = path.to_os_string();
+        let os_path = std::path::Path::new(&os_path);
+        let file_name = os_path.file_name().ok_or(FileError::Unknown)?;
+        let directory = match os_path.parent() {
+            Some(parent) if !parent.as_os_str().is_empty() => parent,
+            _ => std::path::Path::new("."),
+        };
+        let (file, temporary_path) = Self::create_temporary_file(directory, file_name)?;
+        let result =
+            Self::write_temporary_file(file, &temporary_path, os_path, bytes).and_then(|()| {
+                std::fs::rename(&temporary_path, os_path)
+                    .map_err(|error| FileError::io(FileOperation::Rename, path, &error))
+            });
+        if result.is_err() {
+            // Best effort; the original error is more useful.
+            let _ = std::fs::remove_file(&temporary_path);
+        } else {
+            Self::sync_directory(directory);
+        }
+        result
+    }
+
+    /// Symbolic links are followed, and broken ones are listed as regular files.
+    fn list(&self, directory: &DirectoryPath) -> FileResult<DirectoryEntries> {
+        let read_dir = std::fs::read_dir(Self::os_path(directory))
+            .map_err(|error| FileError::io(FileOperation::List, directory, &error))?;
+        let mut entries = DirectoryEntries::default();
+        for entry in read_dir {
+            let entry =
+                entry.map_err(|error| FileError::io(FileOperation::List, directory, &error))?;
+            let name = FilePath::from_os_str(&entry.file_name())?;
+            let path = directory.join(&name)?;
+            let is_directory =
+                std::fs::metadata(entry.path()).is_ok_and(|metadata| metadata.is_dir());
+            entries.insert(OrderedInsert::AtEnd(ValidFilePath::of(path, is_directory)?))?;
+        }
+        entries.sort_unstable_by(|a, b| a.as_wtf8().cmp(b.as_wtf8()));
+        Ok(entries)
+    }
+
+    fn stat(&self, path: &FilePath) -> FileResult<Option<FileStat>> {
+        match std::fs::metadata(Self::os_path(path)) {
+            Ok(metadata) => Ok(Some(FileStat {
+                is_directory: metadata.is_dir(),
+                size: metadata.len(),
+                modified: metadata.modified().ok(),
+                identity: FileIdentity::of(&metadata),
+            })),
+            Err(error) if error.kind() == std::io::ErrorKind::NotFound => Ok(None),
+            Err(error) => Err(FileError::io(FileOperation::Check, path, &error)),
+        }
+    }
+
+    fn rename(&self, from: &FilePath, to: &FilePath) -> Filed {
+        std::fs::rename(from.to_os_string(), to.to_os_string())
+            .map_err(|error| FileError::io(FileOperation::Rename, from, &error))
+    }
+}
@@ -1,16 +1,18 @@
+use crate::core::directory::*;
+use crate::core::encoding::*;
+use crate::core::file_error::*;
+use crate::core::file_path::*;
+use crate::core::filesystem::*;
 use crate::core::moot::*;
 use crate::core::non_local_array::*;
 use crate::core::shtick::*;
 
-use std::ffi::{OsStr, OsString};
 use std::io::Read;
 
 /// If you have more than 128 characters per line, it's a moral failing. /s
 /// But it won't break because Shtick will internally expand to a `max_array` if necessary.
 pub type FileLine = ShtickOptimized8;
 
-pub type FilePath = ShtickOptimized8;
-
 /// We probably don't need (or want) more than 2**31 lines in a file.
 /// Especially not for an in-memory file.  This also makes everything
 /// fit nicely in 16 bytes (8 for the pointer, 4 + 4 for 32 bit count + capacity).
@@ -23,50 +25,161 @@ pub struct InMemoryFile {
     /// Include a trailing OS separator (e.g., `/` on Unix-like systems)
     /// in order to indicate that this is a directory and not a file.
     pub path: FilePath,
+    /// Lines without their line endings (`\n` or `\r\n`).
     pub lines: InMemoryFileLines,
+    /// Used to end each line when writing; see `line_endings` for `Mixed`.
+    pub line_ending: LineEnding,
+    /// Whether the last line is followed by a line ending.  New files get one.
+    pub ends_with_newline: bool,
+    /// Whether the file starts with a UTF-8 byte order mark, which isn't in
+    /// `lines` but is written back.
+    pub byte_order_mark: bool,
+    /// For `LineEnding::Mixed`, the (`Lf` or `CrLf`) ending of each line as read,
+    /// so that we can write the file back exactly.  Lines past the end get `Lf`.
+    /// Empty for other styles.
+    line_endings: NonLocalArrayCount32<LineEnding>,
+    /// Whether `read` should fail if the file doesn't exist.
+    pub missing_file: MissingFile,
+    /// The file as of our last `read` or `write`; `None` if it didn't exist.
+    snapshot: Option<FileSnapshot>,
+    /// Where `path` lives, e.g., `DiskFilesystem` or a `MemoryFilesystem` for tests.
+    filesystem: SharedFilesystem,
+}
+
+/// What a file looked like when we last read or wrote it, so we can tell
+/// whether someone else has changed it since.
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
+struct FileSnapshot {
+    size: u64,
+    modified: Option<std::time::SystemTime>,
+    hash: u64,
+}
+
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq, Hash)]
+pub enum LineEnding {
+    /// `\n`, e.g., on Unix-like systems.  Files without any line endings use this.
+    #[default]
+    Lf,
+    /// `\r\n`, e.g., on Windows.
+    CrLf,
+    /// Some lines end with `\n` and others with `\r\n`.
+    Mixed,
+}
+
+impl LineEnding {
+    pub fn as_bytes(self) -> &'static [u8] {
+        match self {
+            LineEnding::Lf | LineEnding::Mixed => b"\n",
+            LineEnding::CrLf => b"\r\n",
+        }
+    }
 }
 
 impl InMemoryFile {
+    /// Returns `FileError::Missing` if nothing exists at `path`.
     pub fn open(path: FilePath) -> FileResult<Self> {
+        Self::open_with(path, MissingFile::Error)
+    }
+
+    /// Treats a missing file as empty, e.g., for a file we're about to create.
+    pub fn open_or_empty(path: FilePath) -> FileResult<Self> {
+        Self::open_with(path, MissingFile::Empty)
+    }
+
+    pub fn open_with(path: FilePath, missing_file: MissingFile) -> FileResult<Self> {
+        Self::open_in(DiskFilesystem::shared(), path, missing_file)
+    }
+
+    /// Like `open_with`, but reads (and writes) via `filesystem` instead of the disk.
+    pub fn open_in(
+        filesystem: SharedFilesystem,
+        path: FilePath,
+        missing_file: MissingFile,
+    ) -> FileResult<Self> {
         let mut result = Self {
             path,
             lines: Default::default(),
+            line_ending: LineEnding::Lf,
+            ends_with_newline: true,
+            byte_order_mark: false,
+            line_endings: Default::default(),
+            missing_file,
+            snapshot: None,
+            filesystem,
         };
         result.read()?;
         Ok(result)
     }
 
+    /// Where `path` lives, e.g., `DiskFilesystem`.
+    pub fn filesystem(&self) -> &dyn Filesystem {
+        &*self.filesystem
+    }
+
     pub fn is_directory(&self) -> bool {
-        let path_len = self.path.len();
-        if path_len == 0 {
-            // Assume an empty path is the current directory.
-            true
-        } else {
-            let last_char = self.path[path_len - 1];
-            last_char == std::path::MAIN_SEPARATOR as u8
-        }
+        self.path.is_directory()
     }
 
+    /// Invalid UTF-8, tabs, and non-standard whitespace in `lines`, by position,
+    /// e.g., to report before compiling a source file.
+    pub fn encoding_issues(&self) -> ContainerResult<EncodingIssues> {
+        EncodingIssue::find(self.lines.iter().map(|line| &line[..]))
+    }
+
+    /// Reads the file's lines, or for a directory, the names of its entries
+    /// (in sorted order, with a trailing separator for subdirectories).
+    /// A missing file or directory is an error unless `missing_file` is
+    /// `MissingFile::Empty`, in which case it has no lines.
     pub fn read(&mut self) -> Filed {
         if self.is_directory() {
-            // TODO
-            return Err(FileError::Unknown);
-        }
-        let mut file = if let Ok(file) = self.open_file(FileOpen::Read) {
-            file
-        } else {
-            self.lines.set_count(Count::of(0).expect("ok")).expect("ok");
+            let (lines, hasher) = self.read_directory()?;
+            self.lines = lines;
+            self.line_ending = LineEnding::Lf;
+            self.ends_with_newline = false;
+            self.byte_order_mark = false;
+            self.line_endings = Default::default();
+            self.snapshot = hasher.map(|hasher| hasher.snapshot(None));
             return Ok(());
+        }
+        // The reader borrows `filesystem`, so share it rather than borrowing `self`.
+        let filesystem = self.filesystem.clone();
+        let mut file = match filesystem.open(&self.path) {
+            Ok(file) => file,
+            Err(FileError::Missing(_)) if self.missing_file == MissingFile::Empty => {
+                self.lines.set_count(Count::of(0).expect("ok")).expect("ok");
+                self.line_ending = LineEnding::Lf;
+                // Like a new file, so that writing it ends with a newline.
+                self.ends_with_newline = true;
+                self.byte_order_mark = false;
+                self.line_endings = Default::default();
+                self.snapshot = None;
+                return Ok(());
+            }
+            Err(error) => return Err(error),
         };
+        // Get this before reading so that any changes during the read will
+        // look like changes afterwards.
+        let modified = filesystem
+            .stat(&self.path)
+            .ok()
+            .flatten()
+            .and_then(|stat| stat.modified);
+        let mut hasher = FileHasher::default();
         let mut lines = InMemoryFileLines::default();
+        let mut line_endings = NonLocalArrayCount32::<LineEnding>::default();
         let mut buffer = [0u8; 256];
         let mut current_line = FileLine::default();
+        let mut ends_with_newline = false;
         loop {
-            let bytes_read = file.read(&mut buffer).map_err(|_| FileError::Read)?;
+            let bytes_read = file
+                .read(&mut buffer)
+                .map_err(|error| FileError::io(FileOperation::Read, &self.path, &error))?;
             if bytes_read == 0 {
                 break;
             }
             let buffer = &buffer[0..bytes_read];
+            hasher.update(buffer);
+            ends_with_newline = buffer[bytes_read - 1] == b'\n';
             let mut handled_up_to = 0;
             for i in 0..bytes_read {
                 if buffer[i] == b'\n' {
@@ -77,9 +190,20 @@ impl InMemoryFile {
                             TypeMarker,
                         ))
                         .map_err(|_| FileError::OutOfMemory)?;
+                    // Check after appending, since the `\r` might have been
+                    // at the end of the previous buffer.
+                    let line_ending = if current_line.last() == Some(&b'\r') {
+                        current_line.remove(OrderedRemove::Last);
+                        LineEnding::CrLf
+                    } else {
+                        LineEnding::Lf
+                    };
                     lines
                         .insert(OrderedInsert::AtEnd(moot(&mut current_line)))
                         .map_err(|_| FileError::OutOfMemory)?;
+                    line_endings
+                        .insert(OrderedInsert::AtEnd(line_ending))
+                        .map_err(|_| FileError::OutOfMemory)?;
                     handled_up_to = i + 1;
                 }
             }
@@ -96,43 +220,211 @@ impl InMemoryFile {
                 .insert(OrderedInsert::AtEnd(current_line))
                 .map_err(|_| FileError::OutOfMemory)?;
         }
+        let mut byte_order_mark = false;
+        if let Some(first_line) = lines.first_mut() {
+            if looks_like_utf16(first_line) {
+                return Err(FileError::Encoding(FileErrorDetails::new(
+                    FileOperation::Read,
+                    &self.path,
+                    None,
+                )?));
+            }
+            if first_line.starts_with(UTF8_BYTE_ORDER_MARK) {
+                byte_order_mark = true;
+                let mut line = FileLine::default();
+                line.insert_few(OrderedInsertFew::AtEnd(
+                    &first_line[UTF8_BYTE_ORDER_MARK.len()..],
+                    TypeMarker,
+                    TypeMarker,
+                ))?;
+                *first_line = line;
+            }
+        }
+        self.line_ending = match line_endings.first() {
+            Some(first) if line_endings.iter().any(|line_ending| line_ending != first) => {
+                LineEnding::Mixed
+            }
+            Some(first) => *first,
+            None => LineEnding::Lf,
+        };
+        if self.line_ending != LineEnding::Mixed {
+            line_endings = Default::default();
+        }
         self.lines = lines;
+        self.ends_with_newline = ends_with_newline;
+        self.byte_order_mark = byte_order_mark;
+        self.line_endings = line_endings;
+        self.snapshot = Some(hasher.snapshot(modified));
         Ok(())
     }
 
-    fn open_file(&self, file_open: FileOpen) -> FileResult<std::fs::File> {
-        match file_open {
-            FileOpen::Read => {
-                if cfg!(unix) {
-                    use std::os::unix::ffi::OsStrExt;
-                    let os_str = std::path::Path::new(OsStr::from_bytes(&self.path[..]));
-                    std::fs::File::open(os_str)
+    /// Whether the file on disk (or in `filesystem`) differs from our last `read` or `write`.
+    /// Only hashes the contents if the size is the same but the modification
+    /// time isn't, e.g., if the file was touched but not edited.
+    pub fn has_changed(&self) -> FileResult<bool> {
+        match (self.snapshot, self.filesystem.stat(&self.path)?) {
+            (None, None) => Ok(false),
+            (None, Some(_)) | (Some(_), None) => Ok(true),
+            (Some(snapshot), Some(_)) if self.is_directory() => {
+                let (_, hasher) = self.read_directory()?;
+                Ok(hasher.is_none_or(|hasher| hasher.hash != snapshot.hash))
+            }
+            (Some(snapshot), Some(stat)) => {
+                if stat.size != snapshot.size {
+                    Ok(true)
+                } else if stat.modified == snapshot.modified {
+                    Ok(false)
                 } else {
-                    // Need to convert to a UTF16-like string for Windows.
-                    let os_path: OsString =
-                        String::from(String::from_utf8_lossy(&self.path[..])).into();
-                    std::fs::File::open(std::path::Path::new(&os_path))
+                    Ok(self.hash_file()? != snapshot.hash)
                 }
-                .map_err(|_| FileError::Open)
             }
         }
     }
 
-    // TODO: add a `fn has_changed()` method
+    /// Re-reads the file if it `has_changed`, returning true if so.
+    /// Note that this discards any edits to `lines`.
+    pub fn reload_if_changed(&mut self) -> FileResult<bool> {
+        if !self.has_changed()? {
+            return Ok(false);
+        }
+        self.read()?;
+        Ok(true)
+    }
+
+    /// Entry names as lines, with a hash of the names (joined by `\n`) so that
+    /// we can tell if an entry is added or removed.  `None` if missing and
+    /// `missing_file` is `MissingFile::Empty`.
+    fn read_directory(&self) -> FileResult<(InMemoryFileLines, Option<FileHasher>)> {
+        let directory =
+            DirectoryPath::try_from(self.path.try_clone().map_err(|_| FileError::OutOfMemory)?)
+                .map_err(|_| FileError::OutOfMemory)?;
+        let mut lines = InMemoryFileLines::default();
+        let entries = match self.filesystem.list(&directory) {
+            Ok(entries) => entries,
+            Err(FileError::Missing(_)) if self.missing_file == MissingFile::Empty => {
+                return Ok((lines, None));
+            }
+            Err(error) => return Err(error),
+        };
+        let mut hasher = FileHasher::default();
+        for entry in entries.iter() {
+            let name = &entry[self.path.len()..];
+            if !lines.is_empty() {
+                hasher.update(b"\n");
+            }
+            hasher.update(name);
+            let mut line = FileLine::default();
+            line.insert_few(OrderedInsertFew::AtEnd(name, TypeMarker, TypeMarker))
+                .map_err(|_| FileError::OutOfMemory)?;
+            lines
+                .insert(OrderedInsert::AtEnd(line))
+                .map_err(|_| FileError::OutOfMemory)?;
+        }
+        Ok((lines, Some(hasher)))
+    }
+
+    fn hash_file(&self) -> FileResult<u64> {
+        let mut file = self.filesystem.open(&self.path)?;
+        let mut hasher = FileHasher::default();
+        let mut buffer = [0u8; 256];
+        loop {
+            let bytes_read = file
+                .read(&mut buffer)
+                .map_err(|error| FileError::io(FileOperation::Read, &self.path, &error))?;
+            if bytes_read == 0 {
+                return Ok(hasher.hash);
+            }
+            hasher.update(&buffer[0..bytes_read]);
+        }
+    }
+
+    /// Writes `lines` via `Filesystem::write`, so that readers see either the
+    /// old or new contents, never a partial write.  Returns `FileError::Conflict`
+    /// (without writing) if the file `has_changed` since we last read or wrote it,
+    /// so that we don't clobber someone else's edits; `reload_if_changed` to resolve.
+    pub fn write(&mut self) -> Filed {
+        if self.is_directory() {
+            let os_error = OsError {
+                kind: std::io::ErrorKind::IsADirectory,
+                errno: None,
+            };
+            return Err(FileError::Write(FileErrorDetails::new(
+                FileOperation::Write,
+                &self.path,
+                Some(os_error),
+            )?));
+        }
+        if self.has_changed()? {
+            return Err(FileError::Conflict(FileErrorDetails::new(
+                FileOperation::Write,
+                &self.path,
+                None,
+            )?));
+        }
+        let mut bytes = NonLocalArrayCount32::<u8>::default();
+        let mut hasher = FileHasher::default();
+        let mut write = |line: &[u8]| -> Filed {
+            hasher.update(line);
+            bytes.insert_few(OrderedInsertFew::AtEnd(line, TypeMarker, TypeMarker))?;
+            Ok(())
+        };
+        if self.byte_order_mark {
+            write(UTF8_BYTE_ORDER_MARK)?;
+        }
+        for (index, line) in self.lines.iter().enumerate() {
+            write(line)?;
+            if index + 1 < self.lines.len() || self.ends_with_newline {
+                write(self.line_ending_at(index).as_bytes())?;
+            }
+        }
+        self.filesystem.write(&self.path, &bytes)?;
+        let modified = self
+            .filesystem
+            .stat(&self.path)
+            .ok()
+            .flatten()
+            .and_then(|stat| stat.modified);
+        self.snapshot = Some(hasher.snapshot(modified));
+        Ok(())
+    }
+
+    fn line_ending_at(&self, index: usize) -> LineEnding {
+        match self.line_ending {
+            LineEnding::Mixed => self.line_endings.get(index).copied().unwrap_or_default(),
+            line_ending => line_ending,
+        }
+    }
 }
 
-enum FileOpen {
-    Read,
+/// FNV-1a (64 bit) over the file's bytes, along with their count.
+struct FileHasher {
+    size: u64,
+    hash: u64,
 }
 
-#[derive(Debug, Eq, PartialEq, Hash)]
-pub enum FileError {
-    OutOfMemory,
-    Open,
-    Read,
-    Unknown,
+impl Default for FileHasher {
+    fn default() -> Self {
+        Self {
+            size: 0,
+            hash: 0xcbf2_9ce4_8422_2325,
+        }
+    }
 }
 
-pub type FileResult<T> = Result<T, FileError>;
+impl FileHasher {
+    fn update(&mut self, bytes: &[u8]) {
+        self.size += bytes.len() as u64;
+        for byte in bytes {
+            self.hash ^= *byte as u64;
+            self.hash = self.hash.wrapping_mul(0x0100_0000_01b3);
+        }
+    }
 
-pub type Filed = FileResult<()>;
+    fn snapshot(&self, modified: Option<std::time::SystemTime>) -> FileSnapshot {
+        FileSnapshot {
+            size: self.size,
+            modified,
+            hash: self.hash,
+        }
+    }
+}
@@ -677,10 +677,20 @@ impl<S: SignedPrimitive, const N_LOCAL: usize, T: TryClone> TryClone
     type Error = ContainerError;
 
     fn try_clone(&self) -> Result<Self, ContainerError> {
-        let mut result = Self::default();
         // Only need to clone up to `count`, not the full `capacity`.
+        self.try_clone_with_capacity(self.count().to_usize())
+    }
+}
+
+impl<S: SignedPrimitive, const N_LOCAL: usize, T: TryClone> TryCloneWithCapacity
+    for MaybeLocalArrayOptimized<S, N_LOCAL, T>
+{
+    fn try_clone_with_capacity(&self, capacity: usize) -> Result<Self, ContainerError> {
+        let mut result = Self::default();
         let count = self.count();
-        result.set_capacity(count)?;
+        let capacity = CountMax::of(capacity.max(count.to_usize()))
+            .map_err(|_| ContainerError::OutOfMemory)?;
+        result.set_capacity(capacity)?;
         for i in 0..count.to_usize() {
             let clone = self[i].try_clone().map_err(|_| ContainerError::Unknown)?;
             result
@@ -712,6 +722,17 @@ mod test {
         assert_eq!(slice.len(), 0);
     }
 
+    #[test]
+    fn debug_prints_borrowed_elements() {
+        let owned = String::from("borrowed");
+        let mut array = MaybeLocalArrayOptimized32::<2, &str>::default();
+        array.insert(OrderedInsert::AtEnd(&owned[..])).expect("ok");
+        assert_eq!(
+            format!("{:?}", array),
+            "MaybeLocalArrayOptimized32::<2, _>::from([\"borrowed\", ])"
+        );
+    }
+
     #[test]
     fn insert_and_remove_unallocated_buffer() {
         let mut array = MaybeLocalArrayOptimized64::<16, u8>::default();
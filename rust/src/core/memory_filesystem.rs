@@ -0,0 +1,740 @@
+use crate::core::directory::*;
+use crate::core::file_error::*;
+use crate::core::file_path::*;
+use crate::core::filesystem::*;
+use crate::core::non_local_array::*;
+
+pub use crate::core::array::*;
+
+use std::collections::BTreeMap;
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime};
+
+/// A `Filesystem` which only lives in memory, e.g., for building whole project
+/// trees in tests.  Paths are normalized (see `FilePath::normalize`), so `a/./b.oh`
+/// and `a/b.oh` are the same file.  The current directory (an empty path) and
+/// the root (`/`) always exist.
+#[derive(Default)]
+pub struct MemoryFilesystem {
+    /// Shared with any `MemoryFileHandle`s.
+    state: Arc<Mutex<MemoryFilesystemState>>,
+}
+
+#[derive(Default)]
+struct MemoryFilesystemState {
+    /// Keyed by normalized WTF-8 path, without a trailing separator.
+    entries: BTreeMap<Vec<u8>, MemoryEntry>,
+    /// Fake clock which ticks on every write, so modification times always change.
+    clock: u64,
+}
+
+enum MemoryEntry {
+    RegularFile {
+        bytes: Vec<u8>,
+        modified: SystemTime,
+    },
+    Directory,
+}
+
+impl MemoryFilesystem {
+    /// Adds (or replaces) a regular file, creating its parent directories if necessary.
+    pub fn insert_file(&self, path: &str, bytes: &[u8]) -> Filed {
+        let path = FilePath::try_from(path)?;
+        let key = Self::key(&path)?;
+        let mut state = self.lock();
+        Self::insert_directories(&mut state, Self::parent_key(&key), &path)?;
+        if let Some(MemoryEntry::Directory) = state.entries.get(&key) {
+            return Err(Self::failure(FileError::Write, FileOperation::Write, &path));
+        }
+        let modified = state.tick();
+        state.entries.insert(
+            key,
+            MemoryEntry::RegularFile {
+                bytes: bytes.to_vec(),
+                modified,
+            },
+        );
+        Ok(())
+    }
+
+    /// Adds a directory (and its parent directories) if necessary.
+    pub fn insert_directory(&self, path: &str) -> Filed {
+        let path = FilePath::try_from(path)?;
+        let key = Self::key(&path)?;
+        Self::insert_directories(&mut self.lock(), &key, &path)
+    }
+
+    fn insert_directories(state: &mut MemoryFilesystemState, key: &[u8], path: &FilePath) -> Filed {
+        if Self::is_root(key) {
+            return Ok(());
+        }
+        Self::insert_directories(state, Self::parent_key(key), path)?;
+        match state.entries.get(key) {
+            Some(MemoryEntry::Directory) => Ok(()),
+            Some(MemoryEntry::RegularFile { .. }) => Err(Self::failure(
+                FileError::NotADirectory,
+                FileOperation::Write,
+                path,
+            )),
+            None => {
+                state.entries.insert(key.to_vec(), MemoryEntry::Directory);
+                Ok(())
+            }
+        }
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, MemoryFilesystemState> {
+        self.state
+            .lock()
+            .expect("should not panic while holding the lock")
+    }
+
+    fn key(path: &FilePath) -> FileResult<Vec<u8>> {
+        let normalized = path.normalize()?;
+        let mut key = normalized.as_wtf8().to_vec();
+        if key.len() > 1 && normalized.is_directory() {
+            key.pop();
+        }
+        Ok(key)
+    }
+
+    fn parent_key(key: &[u8]) -> &[u8] {
+        match key.iter().rposition(|byte| is_file_path_separator(*byte)) {
+            // Keep the separator for the root, e.g., `/a` -> `/`.
+            Some(0) if key.len() > 1 => &key[0..1],
+            Some(separator) => &key[0..separator],
+            None => &[],
+        }
+    }
+
+    fn is_root(key: &[u8]) -> bool {
+        key.is_empty() || (key.len() == 1 && is_file_path_separator(key[0]))
+    }
+
+    fn failure(
+        variant: fn(FileErrorDetails) -> FileError,
+        operation: FileOperation,
+        path: &FilePath,
+    ) -> FileError {
+        match FileErrorDetails::new(operation, path, None) {
+            Ok(details) => variant(details),
+            Err(error) => error,
+        }
+    }
+
+    /// Checks that `path` is an existing regular file and returns its bytes.
+    fn with_file<U>(
+        &self,
+        operation: FileOperation,
+        path: &FilePath,
+        f: impl FnOnce(&[u8]) -> FileResult<U>,
+    ) -> FileResult<U> {
+        let key = Self::key(path)?;
+        let state = self.lock();
+        match state.entries.get(&key) {
+            _ if Self::is_root(&key) => Err(Self::failure(FileError::Open, operation, path)),
+            Some(MemoryEntry::RegularFile { .. }) if path.is_directory() => {
+                Err(Self::failure(FileError::NotADirectory, operation, path))
+            }
+            Some(MemoryEntry::RegularFile { bytes, .. }) => f(bytes),
+            Some(MemoryEntry::Directory) => Err(Self::failure(FileError::Open, operation, path)),
+            None => Err(Self::failure(FileError::Missing, operation, path)),
+        }
+    }
+
+    /// Checks that `key`'s parent exists as a directory.
+    fn check_parent(
+        state: &MemoryFilesystemState,
+        key: &[u8],
+        operation: FileOperation,
+        path: &FilePath,
+    ) -> Filed {
+        let parent = Self::parent_key(key);
+        match state.entries.get(parent) {
+            _ if Self::is_root(parent) => Ok(()),
+            Some(MemoryEntry::Directory) => Ok(()),
+            Some(MemoryEntry::RegularFile { .. }) => {
+                Err(Self::failure(FileError::NotADirectory, operation, path))
+            }
+            None => Err(Self::failure(FileError::Missing, operation, path)),
+        }
+    }
+}
+
+impl MemoryFilesystemState {
+    fn tick(&mut self) -> SystemTime {
+        self.clock += 1;
+        SystemTime::UNIX_EPOCH + Duration::from_secs(self.clock)
+    }
+}
+
+/// A file opened by `MemoryFilesystem::open_file`, which reads and writes the
+/// file's bytes in place, so that e.g. `Filesystem::read` sees writes right away.
+/// Unlike on disk, this fails with `NotFound` once the file is removed or renamed.
+struct MemoryFileHandle {
+    state: Arc<Mutex<MemoryFilesystemState>>,
+    key: Vec<u8>,
+    options: FileOpenOptions,
+    position: u64,
+}
+
+impl MemoryFileHandle {
+    /// Calls `f` with the file's bytes, updating its modification time if `writing`.
+    fn with_bytes<U>(
+        &self,
+        writing: bool,
+        f: impl FnOnce(&mut Vec<u8>) -> std::io::Result<U>,
+    ) -> std::io::Result<U> {
+        let mut state = self
+            .state
+            .lock()
+            .expect("should not panic while holding the lock");
+        let now = writing.then(|| state.tick());
+        match state.entries.get_mut(&self.key) {
+            Some(MemoryEntry::RegularFile { bytes, modified }) => {
+                if let Some(now) = now {
+                    *modified = now;
+                }
+                f(bytes)
+            }
+            _ => Err(std::io::ErrorKind::NotFound.into()),
+        }
+    }
+
+    /// Like using a file descriptor which wasn't opened for reading (or writing).
+    fn not_opened_for(allowed: bool) -> std::io::Result<()> {
+        if allowed {
+            Ok(())
+        } else {
+            Err(std::io::ErrorKind::PermissionDenied.into())
+        }
+    }
+}
+
+impl Read for MemoryFileHandle {
+    fn read(&mut self, buffer: &mut [u8]) -> std::io::Result<usize> {
+        Self::not_opened_for(self.options.read)?;
+        let position = usize::try_from(self.position).unwrap_or(usize::MAX);
+        let count = self.with_bytes(false, |bytes| {
+            let start = position.min(bytes.len());
+            let count = buffer.len().min(bytes.len() - start);
+            buffer[0..count].copy_from_slice(&bytes[start..start + count]);
+            Ok(count)
+        })?;
+        self.position += count as u64;
+        Ok(count)
+    }
+}
+
+impl Write for MemoryFileHandle {
+    fn write(&mut self, buffer: &[u8]) -> std::io::Result<usize> {
+        Self::not_opened_for(self.options.write || self.options.append)?;
+        let append = self.options.append;
+        let position = self.position;
+        self.position = self.with_bytes(true, |bytes| {
+            let start = if append {
+                bytes.len()
+            } else {
+                usize::try_from(position).map_err(|_| std::io::ErrorKind::OutOfMemory)?
+            };
+            let end = start
+                .checked_add(buffer.len())
+                .ok_or(std::io::ErrorKind::OutOfMemory)?;
+            if end > bytes.len() {
+                // Writing past the end fills the gap with zeros, like on disk.
+                bytes
+                    .try_reserve(end - bytes.len())
+                    .map_err(|_| std::io::ErrorKind::OutOfMemory)?;
+                bytes.resize(end, 0);
+            }
+            bytes[start..end].copy_from_slice(buffer);
+            Ok(end as u64)
+        })?;
+        Ok(buffer.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+impl Seek for MemoryFileHandle {
+    fn seek(&mut self, from: SeekFrom) -> std::io::Result<u64> {
+        let (base, offset) = match from {
+            SeekFrom::Start(offset) => (0, i64::try_from(offset).ok()),
+            SeekFrom::Current(offset) => (self.position, Some(offset)),
+            SeekFrom::End(offset) => (self.size()?, Some(offset)),
+        };
+        // Like on disk, positions need to fit in an `i64`.
+        self.position = offset
+            .and_then(|offset| base.checked_add_signed(offset))
+            .filter(|position| *position <= i64::MAX as u64)
+            .ok_or(std::io::ErrorKind::InvalidInput)?;
+        Ok(self.position)
+    }
+}
+
+impl FileHandle for MemoryFileHandle {
+    fn size(&mut self) -> std::io::Result<u64> {
+        self.with_bytes(false, |bytes| Ok(bytes.len() as u64))
+    }
+}
+
+impl Filesystem for MemoryFilesystem {
+    fn open(&self, path: &FilePath) -> FileResult<Box<dyn Read + '_>> {
+        let bytes = self.with_file(FileOperation::Open, path, |bytes| Ok(bytes.to_vec()))?;
+        Ok(Box::new(std::io::Cursor::new(bytes)))
+    }
+
+    fn open_file(
+        &self,
+        path: &FilePath,
+        options: FileOpenOptions,
+    ) -> FileResult<Box<dyn FileHandle>> {
+        let key = Self::key(path)?;
+        let mut state = self.lock();
+        let exists = match state.entries.get(&key) {
+            _ if Self::is_root(&key) => {
+                return Err(Self::failure(FileError::Open, FileOperation::Open, path));
+            }
+            Some(MemoryEntry::RegularFile { .. }) if path.is_directory() => {
+                return Err(Self::failure(
+                    FileError::NotADirectory,
+                    FileOperation::Open,
+                    path,
+                ));
+            }
+            Some(MemoryEntry::RegularFile { .. }) => true,
+            Some(MemoryEntry::Directory) => {
+                return Err(Self::failure(FileError::Open, FileOperation::Open, path));
+            }
+            None if !options.create => {
+                return Err(Self::failure(FileError::Missing, FileOperation::Open, path));
+            }
+            None if path.is_directory() => {
+                return Err(Self::failure(FileError::Open, FileOperation::Open, path));
+            }
+            None => false,
+        };
+        if !exists {
+            Self::check_parent(&state, &key, FileOperation::Open, path)?;
+        }
+        if !exists || options.truncate {
+            let modified = state.tick();
+            state.entries.insert(
+                key.clone(),
+                MemoryEntry::RegularFile {
+                    bytes: Vec::new(),
+                    modified,
+                },
+            );
+        }
+        Ok(Box::new(MemoryFileHandle {
+            state: self.state.clone(),
+            key,
+            options,
+            position: 0,
+        }))
+    }
+
+    fn read(&self, path: &FilePath) -> FileResult<NonLocalArrayCount32<u8>> {
+        self.with_file(FileOperation::Open, path, |bytes| {
+            let mut array = NonLocalArrayCount32::<u8>::default();
+            array.insert_few(OrderedInsertFew::AtEnd(bytes, TypeMarker, TypeMarker))?;
+            Ok(array)
+        })
+    }
+
+    fn write(&self, path: &FilePath, bytes: &[u8]) -> Filed {
+        let key = Self::key(path)?;
+        let mut state = self.lock();
+        if path.is_directory() || Self::is_root(&key) {
+            return Err(Self::failure(FileError::Write, FileOperation::Write, path));
+        }
+        Self::check_parent(&state, &key, FileOperation::Write, path)?;
+        if let Some(MemoryEntry::Directory) = state.entries.get(&key) {
+            return Err(Self::failure(FileError::Write, FileOperation::Write, path));
+        }
+        let modified = state.tick();
+        state.entries.insert(
+            key,
+            MemoryEntry::RegularFile {
+                bytes: bytes.to_vec(),
+                modified,
+            },
+        );
+        Ok(())
+    }
+
+    fn list(&self, directory: &DirectoryPath) -> FileResult<DirectoryEntries> {
+        let key = Self::key(directory)?;
+        let state = self.lock();
+        match state.entries.get(&key) {
+            _ if Self::is_root(&key) => {}
+            Some(MemoryEntry::Directory) => {}
+            Some(MemoryEntry::RegularFile { .. }) => {
+                return Err(Self::failure(
+                    FileError::NotADirectory,
+                    FileOperation::List,
+                    directory,
+                ));
+            }
+            None => {
+                return Err(Self::failure(
+                    FileError::Missing,
+                    FileOperation::List,
+                    directory,
+                ));
+            }
+        }
+        let mut entries = DirectoryEntries::default();
+        for (child_key, entry) in state.entries.iter() {
+            if child_key == &key || Self::parent_key(child_key) != key.as_slice() {
+                continue;
+            }
+            // Skip the separator after `key`, unless it's the root.
+            let name_start = if Self::is_root(&key) {
+                key.len()
+            } else {
+                key.len() + 1
+            };
+            let name = FilePath::from_wtf8(&child_key[name_start..])?;
+            let path = directory.join(&name)?;
+            entries.insert(OrderedInsert::AtEnd(ValidFilePath::of(
+                path,
+                matches!(entry, MemoryEntry::Directory),
+            )?))?;
+        }
+        entries.sort_unstable_by(|a, b| a.as_wtf8().cmp(b.as_wtf8()));
+        Ok(entries)
+    }
+
+    fn stat(&self, path: &FilePath) -> FileResult<Option<FileStat>> {
+        let key = Self::key(path)?;
+        let state = self.lock();
+        match state.entries.get(&key) {
+            _ if Self::is_root(&key) => Ok(Some(FileStat {
+                is_directory: true,
+                size: 0,
+                modified: None,
+                identity: None,
+            })),
+            Some(MemoryEntry::Directory) => Ok(Some(FileStat {
+                is_directory: true,
+                size: 0,
+                modified: None,
+                identity: None,
+            })),
+            Some(MemoryEntry::RegularFile { .. }) if path.is_directory() => Err(Self::failure(
+                FileError::NotADirectory,
+                FileOperation::Check,
+                path,
+            )),
+            Some(MemoryEntry::RegularFile { bytes, modified }) => Ok(Some(FileStat {
+                is_directory: false,
+                size: bytes.len() as u64,
+                modified: Some(*modified),
+                identity: None,
+            })),
+            None => Ok(None),
+        }
+    }
+
+    fn rename(&self, from: &FilePath, to: &FilePath) -> Filed {
+        let from_key = Self::key(from)?;
+        let to_key = Self::key(to)?;
+        let mut state = self.lock();
+        if Self::is_root(&from_key) || Self::is_root(&to_key) {
+            return Err(Self::failure(FileError::Write, FileOperation::Rename, from));
+        }
+        if !state.entries.contains_key(&from_key) {
+            return Err(Self::failure(
+                FileError::Missing,
+                FileOperation::Rename,
+                from,
+            ));
+        }
+        if from_key == to_key {
+            return Ok(());
+        }
+        Self::check_parent(&state, &to_key, FileOperation::Rename, to)?;
+        let moving_directory = matches!(state.entries.get(&from_key), Some(MemoryEntry::Directory));
+        match state.entries.get(&to_key) {
+            Some(MemoryEntry::Directory) => {
+                return Err(Self::failure(FileError::Write, FileOperation::Rename, to));
+            }
+            Some(MemoryEntry::RegularFile { .. }) if moving_directory => {
+                return Err(Self::failure(
+                    FileError::NotADirectory,
+                    FileOperation::Rename,
+                    to,
+                ));
+            }
+            _ => {}
+        }
+        if moving_directory
+            && to_key.starts_with(&from_key)
+            && to_key
+                .get(from_key.len())
+                .is_some_and(|byte| is_file_path_separator(*byte))
+        {
+            // Can't move a directory inside itself.
+            return Err(Self::failure(FileError::Write, FileOperation::Rename, to));
+        }
+        let moving: Vec<Vec<u8>> = state
+            .entries
+            .keys()
+            .filter(|key| {
+                **key == from_key
+                    || (key.starts_with(&from_key)
+                        && key
+                            .get(from_key.len())
+                            .is_some_and(|byte| is_file_path_separator(*byte)))
+            })
+            .cloned()
+            .collect();
+        for key in moving {
+            let entry = state.entries.remove(&key).expect("just found");
+            let mut new_key = to_key.clone();
+            new_key.extend_from_slice(&key[from_key.len()..]);
+            state.entries.insert(new_key, entry);
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn path(path: &str) -> FilePath {
+        FilePath::try_from(path).expect("ok")
+    }
+
+    fn directory(path: &str) -> DirectoryPath {
+        DirectoryPath::try_from(FilePath::try_from(path).expect("ok")).expect("ok")
+    }
+
+    fn names(entries: &DirectoryEntries) -> Vec<&str> {
+        entries
+            .iter()
+            .map(|entry| entry.to_str().expect("ok"))
+            .collect()
+    }
+
+    #[test]
+    fn reads_and_writes_normalized_paths() {
+        let filesystem = MemoryFilesystem::default();
+        filesystem.insert_file("a/b/c.oh", b"hi\n").expect("ok");
+        assert_eq!(
+            &filesystem.read(&path("a/./b/../b/c.oh")).expect("ok")[..],
+            b"hi\n"
+        );
+        let mut contents = Vec::new();
+        filesystem
+            .open(&path("a/b/c.oh"))
+            .expect("ok")
+            .read_to_end(&mut contents)
+            .expect("ok");
+        assert_eq!(contents, b"hi\n");
+
+        let before = filesystem
+            .stat(&path("a/b/c.oh"))
+            .expect("ok")
+            .expect("exists");
+        filesystem.write(&path("a/b/c.oh"), b"bye").expect("ok");
+        let after = filesystem
+            .stat(&path("a/b/c.oh"))
+            .expect("ok")
+            .expect("exists");
+        assert_eq!(after.size, 3);
+        assert!(!after.is_directory);
+        assert!(after.modified > before.modified);
+
+        let stat = filesystem.stat(&path("a/b/")).expect("ok").expect("exists");
+        assert!(stat.is_directory);
+        assert_eq!(filesystem.stat(&path("a/x")), Ok(None));
+        assert!(filesystem.stat(&path("")).expect("ok").is_some());
+    }
+
+    #[test]
+    fn lists_typed_entries_in_order() {
+        let filesystem = MemoryFilesystem::default();
+        filesystem.insert_file("top.oh", b"").expect("ok");
+        filesystem.insert_file("a/z.oh", b"").expect("ok");
+        filesystem.insert_file("a/b/c.oh", b"").expect("ok");
+        filesystem.insert_directory("a/empty").expect("ok");
+        assert_eq!(
+            names(&filesystem.list(&directory("")).expect("ok")),
+            vec!["a/", "top.oh"]
+        );
+        let entries = filesystem.list(&directory("a/")).expect("ok");
+        assert_eq!(names(&entries), vec!["a/b/", "a/empty/", "a/z.oh"]);
+        assert!(matches!(entries[2], ValidFilePath::RegularFile(_)));
+
+        filesystem.insert_file("/abs/file", b"").expect("ok");
+        assert_eq!(
+            names(&filesystem.list(&directory("/")).expect("ok")),
+            vec!["/abs/"]
+        );
+    }
+
+    #[test]
+    fn fails_like_the_disk() {
+        let filesystem = MemoryFilesystem::default();
+        filesystem.insert_file("a/file.txt", b"").expect("ok");
+        for (error, expected, operation) in [
+            (
+                filesystem.read(&path("a/missing.txt")).err(),
+                "Missing",
+                FileOperation::Open,
+            ),
+            (
+                filesystem.read(&path("a/")).err(),
+                "Open",
+                FileOperation::Open,
+            ),
+            (
+                filesystem.write(&path("b/new.txt"), b"").err(),
+                "Missing",
+                FileOperation::Write,
+            ),
+            (
+                filesystem.write(&path("a/file.txt/new.txt"), b"").err(),
+                "NotADirectory",
+                FileOperation::Write,
+            ),
+            (
+                filesystem.list(&directory("a/file.txt")).err(),
+                "NotADirectory",
+                FileOperation::List,
+            ),
+            (
+                filesystem.list(&directory("b")).err(),
+                "Missing",
+                FileOperation::List,
+            ),
+            (
+                filesystem.stat(&path("a/file.txt/")).err(),
+                "NotADirectory",
+                FileOperation::Check,
+            ),
+            (
+                filesystem
+                    .open_file(&path("a/missing.txt"), FileOpenOptions::default())
+                    .err(),
+                "Missing",
+                FileOperation::Open,
+            ),
+            (
+                filesystem
+                    .open_file(
+                        &path("b/new.txt"),
+                        FileOpenOptions {
+                            write: true,
+                            create: true,
+                            ..FileOpenOptions::default()
+                        },
+                    )
+                    .err(),
+                "Missing",
+                FileOperation::Open,
+            ),
+            (
+                filesystem
+                    .open_file(&path("a/"), FileOpenOptions::default())
+                    .err(),
+                "Open",
+                FileOperation::Open,
+            ),
+            (
+                filesystem
+                    .rename(&path("a/missing.txt"), &path("a/b.txt"))
+                    .err(),
+                "Missing",
+                FileOperation::Rename,
+            ),
+        ] {
+            let error = error.expect("should fail");
+            assert!(format!("{:?}", error).starts_with(expected), "{:?}", error);
+            let details = error.details().expect("has details");
+            assert_eq!(details.operation, operation);
+            assert_eq!(details.os_error, None);
+        }
+    }
+
+    #[test]
+    fn opened_files_change_in_place() {
+        let filesystem = MemoryFilesystem::default();
+        filesystem.insert_file("a.txt", b"hello").expect("ok");
+        let read_write = FileOpenOptions {
+            read: true,
+            write: true,
+            ..FileOpenOptions::default()
+        };
+        let mut file = filesystem
+            .open_file(&path("a.txt"), read_write)
+            .expect("ok");
+        let before = filesystem
+            .stat(&path("a.txt"))
+            .expect("ok")
+            .expect("exists");
+        assert_eq!(file.seek(SeekFrom::End(-2)).expect("ok"), 3);
+        file.write_all(b"p!").expect("ok");
+        // Past the end leaves a gap of zeros.
+        assert_eq!(file.seek(SeekFrom::Current(1)).expect("ok"), 6);
+        file.write_all(b"?").expect("ok");
+        assert_eq!(
+            &filesystem.read(&path("a.txt")).expect("ok")[..],
+            b"help!\0?"
+        );
+        let after = filesystem
+            .stat(&path("a.txt"))
+            .expect("ok")
+            .expect("exists");
+        assert!(after.modified > before.modified);
+
+        file.seek(SeekFrom::Start(0)).expect("ok");
+        let mut contents = Vec::new();
+        file.read_to_end(&mut contents).expect("ok");
+        assert_eq!(contents, b"help!\0?");
+        assert!(file.seek(SeekFrom::Current(-10)).is_err());
+
+        let read_only = FileOpenOptions {
+            read: true,
+            ..FileOpenOptions::default()
+        };
+        let mut reader = filesystem.open_file(&path("a.txt"), read_only).expect("ok");
+        assert!(reader.write(b"x").is_err());
+        filesystem
+            .rename(&path("a.txt"), &path("b.txt"))
+            .expect("ok");
+        assert_eq!(
+            file.size().map_err(|error| error.kind()),
+            Err(std::io::ErrorKind::NotFound)
+        );
+    }
+
+    #[test]
+    fn renames_files_and_directories() {
+        let filesystem = MemoryFilesystem::default();
+        filesystem.insert_file("a/x.oh", b"x").expect("ok");
+        filesystem.insert_file("a/b/y.oh", b"y").expect("ok");
+        filesystem.insert_file("c.oh", b"c").expect("ok");
+
+        filesystem
+            .rename(&path("c.oh"), &path("a/x.oh"))
+            .expect("ok");
+        assert_eq!(&filesystem.read(&path("a/x.oh")).expect("ok")[..], b"c");
+        assert_eq!(filesystem.stat(&path("c.oh")), Ok(None));
+
+        filesystem.rename(&path("a/"), &path("d")).expect("ok");
+        assert_eq!(
+            names(&filesystem.list(&directory("")).expect("ok")),
+            vec!["d/"]
+        );
+        assert_eq!(&filesystem.read(&path("d/b/y.oh")).expect("ok")[..], b"y");
+        assert!(filesystem.rename(&path("d/"), &path("d/b/e")).is_err());
+    }
+}
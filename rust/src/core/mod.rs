@@ -9,12 +9,44 @@ pub use allocation::*;
 pub mod array;
 pub use array::*;
 
+pub mod bit_array;
+pub use bit_array::*;
+
 pub mod container;
 pub use container::*;
 
 pub mod count;
 pub use count::*;
 
+pub mod deque;
+pub use deque::*;
+
+pub mod directory;
+pub use directory::*;
+
+pub mod encoding;
+pub use encoding::*;
+
+pub mod file_error;
+pub use file_error::*;
+
+pub mod file_lines;
+pub use file_lines::*;
+
+pub mod file_path;
+pub use file_path::*;
+
+pub mod file_tag;
+pub use file_tag::*;
+
+#[cfg(target_os = "linux")]
+pub mod file_watcher;
+#[cfg(target_os = "linux")]
+pub use file_watcher::*;
+
+pub mod filesystem;
+pub use filesystem::*;
+
 pub mod index;
 pub use index::*;
 
@@ -27,6 +59,9 @@ pub use likely::*;
 pub mod maybe_local_array;
 pub use maybe_local_array::*;
 
+pub mod memory_filesystem;
+pub use memory_filesystem::*;
+
 pub mod moot;
 pub use moot::*;
 
@@ -39,12 +74,30 @@ pub use number::*;
 pub mod offset;
 pub use offset::*;
 
+pub mod regular_file;
+pub use regular_file::*;
+
+pub mod shared_array;
+pub use shared_array::*;
+
 pub mod signed;
 pub use signed::*;
 
 pub mod shtick;
 pub use shtick::*;
 
+pub mod shtick_syntax;
+pub use shtick_syntax::*;
+
+pub mod stack_array;
+pub use stack_array::*;
+
+pub mod struct_of_arrays;
+pub use struct_of_arrays::*;
+
+pub mod symbol;
+pub use symbol::*;
+
 pub mod symmetric;
 pub use symmetric::*;
 
@@ -53,3 +106,6 @@ pub use testing::*;
 
 pub mod traits;
 pub use traits::*;
+
+pub mod utf8_shtick;
+pub use utf8_shtick::*;
@@ -244,9 +244,17 @@ impl<S: SignedPrimitive, T: TryClone> TryClone for NonLocalArrayCount<S, T> {
     type Error = ContainerError;
 
     fn try_clone(&self) -> Result<Self, ContainerError> {
-        let mut result = Self::default();
         // Only need to clone up to `count`, not the full `capacity`.
-        result.set_capacity(self.count())?;
+        self.try_clone_with_capacity(self.count.to_usize())
+    }
+}
+
+impl<S: SignedPrimitive, T: TryClone> TryCloneWithCapacity for NonLocalArrayCount<S, T> {
+    fn try_clone_with_capacity(&self, capacity: usize) -> Result<Self, ContainerError> {
+        let mut result = Self::default();
+        let capacity = Count::<S>::of(capacity.max(self.count.to_usize()))
+            .map_err(|_| ContainerError::OutOfMemory)?;
+        result.set_capacity(capacity)?;
         for i in 0..self.count.to_usize() {
             let clone = self[i].try_clone().map_err(|_| ContainerError::Unknown)?;
             result
@@ -0,0 +1,531 @@
+use crate::core::file_error::*;
+use crate::core::file_path::*;
+use crate::core::filesystem::*;
+use crate::core::in_memory_file::*;
+use crate::core::non_local_array::*;
+
+pub use crate::core::array::*;
+
+use std::io::{Read, Seek, SeekFrom, Write};
+
+/// How a `RegularFile` is opened, like `mode_` in `core/file/regular.oh`.
+/// Reads need a `ReadableFileMode` and writes need a `WritableFileMode`,
+/// so e.g. writing to a `RegularFile<ReadOnly>` doesn't compile.
+pub trait FileMode {
+    fn open_options() -> FileOpenOptions;
+}
+
+pub trait ReadableFileMode: FileMode {}
+
+pub trait WritableFileMode: FileMode {}
+
+/// The file must exist.
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq, Hash)]
+pub struct ReadOnly;
+
+/// The file must exist; reads and writes share one position.
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq, Hash)]
+pub struct ReadWrite;
+
+/// The file is created, or truncated if already present.
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq, Hash)]
+pub struct TruncateWrite;
+
+/// The file is created if necessary, and every write goes to its end.
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq, Hash)]
+pub struct AppendWrite;
+
+impl FileMode for ReadOnly {
+    fn open_options() -> FileOpenOptions {
+        FileOpenOptions {
+            read: true,
+            ..FileOpenOptions::default()
+        }
+    }
+}
+
+impl FileMode for ReadWrite {
+    fn open_options() -> FileOpenOptions {
+        FileOpenOptions {
+            read: true,
+            write: true,
+            ..FileOpenOptions::default()
+        }
+    }
+}
+
+impl FileMode for TruncateWrite {
+    fn open_options() -> FileOpenOptions {
+        FileOpenOptions {
+            write: true,
+            create: true,
+            truncate: true,
+            ..FileOpenOptions::default()
+        }
+    }
+}
+
+impl FileMode for AppendWrite {
+    fn open_options() -> FileOpenOptions {
+        FileOpenOptions {
+            append: true,
+            create: true,
+            ..FileOpenOptions::default()
+        }
+    }
+}
+
+impl ReadableFileMode for ReadOnly {}
+impl ReadableFileMode for ReadWrite {}
+impl WritableFileMode for ReadWrite {}
+impl WritableFileMode for TruncateWrite {}
+impl WritableFileMode for AppendWrite {}
+
+/// A regular file which is read or written a buffer at a time, like
+/// `regular_file_{mode:}` in `core/file/regular.oh`.  Unlike `InMemoryFile`,
+/// this never holds the whole file, e.g., for large generated C outputs.
+/// Buffered writes are flushed when seeking, reading, or dropping; use
+/// `flush` to see any errors.
+pub struct RegularFile<M: FileMode> {
+    path: FilePath,
+    file: Box<dyn FileHandle>,
+    /// Allocated on first use.  Holds either unread bytes
+    /// (`buffer[read_start..read_end]`) or bytes we haven't written yet
+    /// (`buffer[0..write_end]`), never both.
+    buffer: NonLocalArrayCount32<u8>,
+    read_start: usize,
+    read_end: usize,
+    write_end: usize,
+    mode: TypeMarker<M>,
+}
+
+const REGULAR_FILE_BUFFER_SIZE: usize = 8192;
+
+impl<M: FileMode> RegularFile<M> {
+    pub fn open(path: &RegularFilePath) -> FileResult<Self> {
+        Self::open_in(&DiskFilesystem, path)
+    }
+
+    /// Like `open`, but via `filesystem` instead of the disk.
+    pub fn open_in(filesystem: &dyn Filesystem, path: &RegularFilePath) -> FileResult<Self> {
+        let file = filesystem.open_file(path, M::open_options())?;
+        Ok(Self {
+            path: FilePath::try_clone(path)?,
+            file,
+            buffer: Default::default(),
+            read_start: 0,
+            read_end: 0,
+            write_end: 0,
+            mode: TypeMarker,
+        })
+    }
+
+    pub fn path(&self) -> &FilePath {
+        &self.path
+    }
+
+    /// The number of bytes in this file, including any we haven't flushed yet.
+    pub fn size(&mut self) -> FileResult<u64> {
+        self.flush()?;
+        self.file
+            .size()
+            .map_err(|error| FileError::io(FileOperation::Check, &self.path, &error))
+    }
+
+    /// The byte offset where the next read or write happens (for `AppendWrite`,
+    /// writes always go to the end).
+    pub fn position(&mut self) -> FileResult<u64> {
+        let position = self
+            .file
+            .stream_position()
+            .map_err(|error| FileError::io(FileOperation::Check, &self.path, &error))?;
+        Ok(position + self.write_end as u64 - (self.read_end - self.read_start) as u64)
+    }
+
+    /// Moves to the byte at `offset`, e.g., `seek(0)` to go back to the start.
+    pub fn seek(&mut self, offset: u64) -> Filed {
+        self.flush()?;
+        self.read_start = 0;
+        self.read_end = 0;
+        self.file
+            .seek(SeekFrom::Start(offset))
+            .map_err(|error| FileError::io(FileOperation::Check, &self.path, &error))?;
+        Ok(())
+    }
+
+    /// Writes any buffered bytes to the file.
+    pub fn flush(&mut self) -> Filed {
+        if self.write_end > 0 {
+            let write_end = self.write_end;
+            self.write_end = 0;
+            self.file
+                .write_all(&self.buffer[0..write_end])
+                .map_err(|error| FileError::io(FileOperation::Write, &self.path, &error))?;
+        }
+        Ok(())
+    }
+
+    fn ensure_buffer(&mut self) -> Filed {
+        if self.buffer.is_empty() {
+            self.buffer
+                .set_count(Count::of(REGULAR_FILE_BUFFER_SIZE).expect("ok"))?;
+        }
+        Ok(())
+    }
+}
+
+impl<M: ReadableFileMode> RegularFile<M> {
+    /// Appends the rest of the file to `bytes`, keeping any newlines, and
+    /// returns how many bytes were appended.  Right after opening, this reads
+    /// the whole file; at the end of the file, it appends nothing.
+    pub fn read_into(&mut self, bytes: &mut impl Array<u8>) -> FileResult<usize> {
+        let mut count = 0;
+        while self.fill_buffer()? {
+            let unread = &self.buffer[self.read_start..self.read_end];
+            bytes.insert_few(OrderedInsertFew::AtEnd(unread, TypeMarker, TypeMarker))?;
+            count += unread.len();
+            self.read_start = self.read_end;
+        }
+        Ok(count)
+    }
+
+    /// Appends the next line to `line` without its `\n` or `\r\n`.  Returns
+    /// false (appending nothing) at the end of the file; an empty line is true.
+    pub fn read_line_into(&mut self, line: &mut impl Array<u8>) -> FileResult<bool> {
+        let mut found_any = false;
+        while self.fill_buffer()? {
+            found_any = true;
+            let unread = &self.buffer[self.read_start..self.read_end];
+            if let Some(newline) = unread.iter().position(|byte| *byte == b'\n') {
+                let end = if newline > 0 && unread[newline - 1] == b'\r' {
+                    newline - 1
+                } else {
+                    newline
+                };
+                line.insert_few(OrderedInsertFew::AtEnd(
+                    &unread[0..end],
+                    TypeMarker,
+                    TypeMarker,
+                ))?;
+                self.read_start += newline + 1;
+                return Ok(true);
+            }
+            // Hold back a trailing `\r` in case the next buffer starts with `\n`.
+            let end = unread.len() - (unread.last() == Some(&b'\r')) as usize;
+            line.insert_few(OrderedInsertFew::AtEnd(
+                &unread[0..end],
+                TypeMarker,
+                TypeMarker,
+            ))?;
+            self.read_start += end;
+            if end == 0 {
+                // Only a `\r` is left; it's part of the line if the file ends here.
+                if !self.fill_more()? {
+                    line.insert(OrderedInsert::AtEnd(b'\r'))?;
+                    self.read_start = self.read_end;
+                }
+            }
+        }
+        Ok(found_any)
+    }
+
+    /// The next line without its `\n` or `\r\n`; `None` at the end of the file.
+    pub fn read_line(&mut self) -> FileResult<Option<FileLine>> {
+        let mut line = FileLine::default();
+        Ok(self.read_line_into(&mut line)?.then_some(line))
+    }
+
+    /// All remaining lines, without their line endings.
+    pub fn read_lines(&mut self) -> FileResult<InMemoryFileLines> {
+        let mut lines = InMemoryFileLines::default();
+        while let Some(line) = self.read_line()? {
+            lines.insert(OrderedInsert::AtEnd(line))?;
+        }
+        Ok(lines)
+    }
+
+    /// Iterates over the remaining lines, like `read_line`.
+    pub fn lines(&mut self) -> RegularFileLines<'_, M> {
+        RegularFileLines { file: self }
+    }
+
+    /// Makes sure there are unread bytes in the buffer, reading from the file
+    /// if necessary; false at the end of the file.
+    fn fill_buffer(&mut self) -> FileResult<bool> {
+        if self.read_start < self.read_end {
+            return Ok(true);
+        }
+        self.fill_more()
+    }
+
+    /// Reads more bytes after any unread ones; false if there weren't any.
+    fn fill_more(&mut self) -> FileResult<bool> {
+        self.flush()?;
+        self.ensure_buffer()?;
+        self.buffer.copy_within(self.read_start..self.read_end, 0);
+        self.read_end -= self.read_start;
+        self.read_start = 0;
+        loop {
+            match self.file.read(&mut self.buffer[self.read_end..]) {
+                Ok(count) => {
+                    self.read_end += count;
+                    return Ok(count > 0);
+                }
+                Err(error) if error.kind() == std::io::ErrorKind::Interrupted => continue,
+                Err(error) => return Err(FileError::io(FileOperation::Read, &self.path, &error)),
+            }
+        }
+    }
+}
+
+impl<M: WritableFileMode> RegularFile<M> {
+    /// Writes `bytes` as is (buffered).
+    pub fn write(&mut self, bytes: &[u8]) -> Filed {
+        if self.read_start < self.read_end {
+            // We read ahead of our position (for `ReadWrite`), so go back to it.
+            let position = self.position()?;
+            self.seek(position)?;
+        }
+        if self.write_end + bytes.len() > REGULAR_FILE_BUFFER_SIZE {
+            self.flush()?;
+        }
+        if bytes.len() >= REGULAR_FILE_BUFFER_SIZE {
+            return self
+                .file
+                .write_all(bytes)
+                .map_err(|error| FileError::io(FileOperation::Write, &self.path, &error));
+        }
+        self.ensure_buffer()?;
+        self.buffer[self.write_end..self.write_end + bytes.len()].copy_from_slice(bytes);
+        self.write_end += bytes.len();
+        Ok(())
+    }
+
+    /// Writes `line` followed by `line_ending`.
+    pub fn write_line(&mut self, line: &[u8], line_ending: LineEnding) -> Filed {
+        self.write(line)?;
+        self.write(line_ending.as_bytes())
+    }
+
+    /// Writes each line followed by `line_ending`.
+    pub fn write_lines<'a>(
+        &mut self,
+        lines: impl IntoIterator<Item = &'a [u8]>,
+        line_ending: LineEnding,
+    ) -> Filed {
+        for line in lines {
+            self.write_line(line, line_ending)?;
+        }
+        Ok(())
+    }
+}
+
+/// Best effort; call `flush` first to see any errors.
+impl<M: FileMode> Drop for RegularFile<M> {
+    fn drop(&mut self) {
+        let _ = self.flush();
+    }
+}
+
+/// See `RegularFile::lines`.
+pub struct RegularFileLines<'a, M: ReadableFileMode> {
+    file: &'a mut RegularFile<M>,
+}
+
+impl<M: ReadableFileMode> Iterator for RegularFileLines<'_, M> {
+    type Item = FileResult<FileLine>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.file.read_line().transpose()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::core::memory_filesystem::*;
+    use crate::core::testing::*;
+
+    /// `name` inside `directory`, with `contents` if any.
+    fn file_in(
+        directory: &TestingDirectory,
+        name: &str,
+        contents: Option<&[u8]>,
+    ) -> RegularFilePath {
+        let path = directory.join(name);
+        if let Some(contents) = contents {
+            std::fs::write(&path, contents).expect("ok");
+        }
+        RegularFilePath::try_from(FilePath::try_from(path.as_path()).expect("ok")).expect("ok")
+    }
+
+    fn strings(lines: &InMemoryFileLines) -> Vec<&str> {
+        lines
+            .iter()
+            .map(|line| std::str::from_utf8(line).expect("ok"))
+            .collect()
+    }
+
+    #[test]
+    fn reads_lines_across_buffers() {
+        // Put a `\r\n` across the first buffer boundary.
+        let mut contents = vec![b'a'; REGULAR_FILE_BUFFER_SIZE - 1];
+        contents.extend_from_slice(b"\r\nsecond\n\nlast\r");
+        let directory = TestingDirectory::new("regular-file-lines");
+        let path = file_in(&directory, "file.txt", Some(&contents));
+        let mut file = RegularFile::<ReadOnly>::open(&path).expect("ok");
+        let first = file.read_line().expect("ok").expect("some");
+        assert_eq!(&first[..], &contents[0..REGULAR_FILE_BUFFER_SIZE - 1]);
+        assert_eq!(file.position(), Ok(REGULAR_FILE_BUFFER_SIZE as u64 + 1));
+        assert_eq!(
+            strings(&file.read_lines().expect("ok")),
+            ["second", "", "last\r"]
+        );
+        assert_eq!(file.read_line(), Ok(None));
+
+        file.seek(REGULAR_FILE_BUFFER_SIZE as u64 + 1).expect("ok");
+        let lines: Vec<FileLine> = file.lines().map(|line| line.expect("ok")).collect();
+        assert_eq!(lines.len(), 3);
+        assert_eq!(file.size(), Ok(contents.len() as u64));
+
+        file.seek(0).expect("ok");
+        let mut bytes = NonLocalArrayCount32::<u8>::default();
+        assert_eq!(file.read_into(&mut bytes), Ok(contents.len()));
+        assert_eq!(&bytes[..], &contents[..]);
+        assert_eq!(file.read_into(&mut bytes), Ok(0));
+    }
+
+    #[test]
+    fn writes_truncates_and_appends() {
+        let directory = TestingDirectory::new("regular-file-write");
+        let path = file_in(
+            &directory,
+            "file.txt",
+            Some(b"old contents which are longer\n"),
+        );
+        {
+            let mut file = RegularFile::<TruncateWrite>::open(&path).expect("ok");
+            file.write_lines([&b"one"[..], b"two"], LineEnding::CrLf)
+                .expect("ok");
+            file.write(&vec![b'x'; REGULAR_FILE_BUFFER_SIZE])
+                .expect("ok");
+            assert_eq!(file.size(), Ok(10 + REGULAR_FILE_BUFFER_SIZE as u64));
+            file.write(b"\n").expect("ok");
+        }
+        {
+            let mut file = RegularFile::<AppendWrite>::open(&path).expect("ok");
+            file.write_line(b"appended", LineEnding::Lf).expect("ok");
+            file.flush().expect("ok");
+        }
+        let mut expected = b"one\r\ntwo\r\n".to_vec();
+        expected.extend(vec![b'x'; REGULAR_FILE_BUFFER_SIZE]);
+        expected.extend_from_slice(b"\nappended\n");
+        assert_eq!(std::fs::read(path.to_os_string()).expect("ok"), expected);
+
+        // Not a read, even though seeking is mostly for reading.
+        let mut file = RegularFile::<TruncateWrite>::open(&path).expect("ok");
+        match file.seek(u64::MAX) {
+            Err(FileError::Open(details)) => {
+                assert_eq!(details.operation, FileOperation::Check);
+                assert_eq!(&details.path, &*path);
+            }
+            result => panic!("should fail to seek, got {:?}", result),
+        }
+        drop(file);
+
+        let created = file_in(&directory, "created.txt", None);
+        RegularFile::<AppendWrite>::open(&created)
+            .expect("ok")
+            .write(b"new")
+            .expect("ok");
+        assert_eq!(std::fs::read(created.to_os_string()).expect("ok"), b"new");
+    }
+
+    #[test]
+    fn reads_and_writes_at_one_position() {
+        let directory = TestingDirectory::new("regular-file-read-write");
+        let path = file_in(&directory, "file.txt", Some(b"first\nsecond\nthird\n"));
+        let mut file = RegularFile::<ReadWrite>::open(&path).expect("ok");
+        assert_eq!(
+            file.read_line().expect("ok").as_deref(),
+            Some(&b"first"[..])
+        );
+        // Overwrites `second` even though we've read ahead into the buffer.
+        file.write(b"SECOND").expect("ok");
+        assert_eq!(file.read_line().expect("ok").as_deref(), Some(&b""[..]));
+        assert_eq!(
+            file.read_line().expect("ok").as_deref(),
+            Some(&b"third"[..])
+        );
+        drop(file);
+        assert_eq!(
+            std::fs::read(path.to_os_string()).expect("ok"),
+            b"first\nSECOND\nthird\n"
+        );
+    }
+
+    #[test]
+    fn reads_and_writes_in_memory() {
+        let filesystem = MemoryFilesystem::default();
+        filesystem.insert_file("out/old.c", b"old\n").expect("ok");
+        let path =
+            RegularFilePath::try_from(FilePath::try_from("out/main.c").expect("ok")).expect("ok");
+        {
+            let mut file = RegularFile::<TruncateWrite>::open_in(&filesystem, &path).expect("ok");
+            file.write_lines([&b"int main() {"[..], b"}"], LineEnding::Lf)
+                .expect("ok");
+            assert_eq!(file.size(), Ok(15));
+        }
+        {
+            let mut file = RegularFile::<AppendWrite>::open_in(&filesystem, &path).expect("ok");
+            file.write_line(b"// done", LineEnding::Lf).expect("ok");
+        }
+        assert_eq!(
+            &filesystem.read(&path).expect("ok")[..],
+            b"int main() {\n}\n// done\n"
+        );
+
+        let mut file = RegularFile::<ReadWrite>::open_in(&filesystem, &path).expect("ok");
+        assert_eq!(
+            file.read_line().expect("ok").as_deref(),
+            Some(&b"int main() {"[..])
+        );
+        file.write(b"]").expect("ok");
+        file.seek(0).expect("ok");
+        assert_eq!(
+            strings(&file.read_lines().expect("ok")),
+            ["int main() {", "]", "// done"]
+        );
+        assert_eq!(
+            file.seek(u64::MAX)
+                .map_err(|error| error.details().map(|details| details.operation)),
+            Err(Some(FileOperation::Check))
+        );
+
+        let missing = RegularFilePath::try_from(FilePath::try_from("out/missing.c").expect("ok"))
+            .expect("ok");
+        assert!(matches!(
+            RegularFile::<ReadOnly>::open_in(&filesystem, &missing).err(),
+            Some(FileError::Missing(_))
+        ));
+    }
+
+    #[test]
+    fn missing_files_need_a_creating_mode() {
+        let directory = TestingDirectory::new("regular-file-missing");
+        let path = file_in(&directory, "file.txt", None);
+        for result in [
+            RegularFile::<ReadOnly>::open(&path).err(),
+            RegularFile::<ReadWrite>::open(&path).err(),
+        ] {
+            match result {
+                Some(FileError::Missing(details)) => {
+                    assert_eq!(details.operation, FileOperation::Open);
+                    assert_eq!(&details.path, &*path);
+                }
+                _ => panic!("should be missing"),
+            }
+        }
+        assert!(RegularFile::<TruncateWrite>::open(&path).is_ok());
+    }
+}
@@ -0,0 +1,357 @@
+use crate::core::maybe_local_array::*;
+use crate::core::non_local_array::*;
+
+pub use crate::core::array::*;
+
+use std::rc::Rc;
+
+pub type SharedNonLocalArrayCount<S, T> = SharedArray<NonLocalArrayCount<S, T>>;
+pub type SharedNonLocalArrayCount64<T> = SharedNonLocalArrayCount<i64, T>;
+pub type SharedNonLocalArrayCount32<T> = SharedNonLocalArrayCount<i32, T>;
+pub type SharedNonLocalArrayCount16<T> = SharedNonLocalArrayCount<i16, T>;
+pub type SharedNonLocalArrayCount8<T> = SharedNonLocalArrayCount<i8, T>;
+
+pub type SharedMaybeLocalArrayOptimized<S, const N_LOCAL: usize, T> =
+    SharedArray<MaybeLocalArrayOptimized<S, N_LOCAL, T>>;
+
+/// Reference-counted, copy-on-write wrapper around an array `A`.
+/// Cloning is O(1) and shares the elements; the first mutation
+/// (e.g., `deref_mut`, `insert`, `remove`, `set_capacity`) on a
+/// shared array copies it (via `try_clone`, or `try_clone_with_capacity`
+/// if the mutation needs more room) so that other clones
+/// don't see the change.  If the array isn't shared, mutations
+/// happen in place.
+pub struct SharedArray<A> {
+    array: Rc<A>,
+}
+
+impl<A: Default> Default for SharedArray<A> {
+    fn default() -> Self {
+        Self {
+            array: Rc::new(A::default()),
+        }
+    }
+}
+
+impl<A> Clone for SharedArray<A> {
+    /// Doesn't copy any elements; see `SharedArray` docs.
+    fn clone(&self) -> Self {
+        Self {
+            array: Rc::clone(&self.array),
+        }
+    }
+}
+
+impl<A> SharedArray<A> {
+    pub fn new(array: A) -> Self {
+        Self {
+            array: Rc::new(array),
+        }
+    }
+
+    /// True iff no other clones share these elements.
+    pub fn is_unique(&self) -> bool {
+        Rc::strong_count(&self.array) == 1
+    }
+
+    /// True iff `self` and `other` share the same elements.
+    pub fn is_sharing(&self, other: &Self) -> bool {
+        Rc::ptr_eq(&self.array, &other.array)
+    }
+
+    pub fn get(&self) -> &A {
+        &self.array
+    }
+}
+
+impl<A: TryClone> SharedArray<A> {
+    /// Copies the elements if they're shared with another clone, so that
+    /// we can mutate them.  Use this before `deref_mut` if you want to
+    /// handle running out of memory rather than panicking.
+    pub fn get_mut(&mut self) -> ContainerResult<&mut A> {
+        if Rc::get_mut(&mut self.array).is_none() {
+            // Need `A::try_clone` here, since `Rc<A>` is `TryClone` as well.
+            let copy = A::try_clone(&self.array).map_err(|_| ContainerError::OutOfMemory)?;
+            self.array = Rc::new(copy);
+        }
+        Ok(Rc::get_mut(&mut self.array).expect("should be unique"))
+    }
+
+    /// Returns the array, copying the elements if they're shared.
+    pub fn unshare(self) -> ContainerResult<A> {
+        match Rc::try_unwrap(self.array) {
+            Ok(array) => Ok(array),
+            Err(array) => A::try_clone(&array).map_err(|_| ContainerError::OutOfMemory),
+        }
+    }
+}
+
+impl<A: TryCloneWithCapacity> SharedArray<A> {
+    /// Like `get_mut`, but if we need to copy the elements, copies them into
+    /// a buffer with room for `capacity` elements, e.g., right before growing,
+    /// so that we don't copy and then immediately reallocate.
+    pub fn get_mut_with_capacity(&mut self, capacity: usize) -> ContainerResult<&mut A> {
+        if Rc::get_mut(&mut self.array).is_none() {
+            let copy = A::try_clone_with_capacity(&self.array, capacity)
+                .map_err(|_| ContainerError::OutOfMemory)?;
+            self.array = Rc::new(copy);
+        }
+        Ok(Rc::get_mut(&mut self.array).expect("should be unique"))
+    }
+}
+
+impl<S: SignedPrimitive, T: TryClone> SharedNonLocalArrayCount<S, T> {
+    pub fn capacity(&self) -> Count<S> {
+        self.array.capacity()
+    }
+
+    pub fn set_capacity(&mut self, new_capacity: Count<S>) -> Containered {
+        if new_capacity == self.capacity() {
+            return Ok(());
+        }
+        self.get_mut_with_capacity(new_capacity.to_usize())?
+            .set_capacity(new_capacity)
+    }
+
+    /// Returns `None` if there was nothing to remove or we couldn't unshare the array.
+    pub fn remove(&mut self, remove: OrderedRemove) -> Option<T> {
+        if !self.array.count().is_positive() {
+            return None;
+        }
+        self.get_mut().ok()?.remove(remove)
+    }
+}
+
+impl<S: SignedPrimitive, const N_LOCAL: usize, T: TryClone>
+    SharedMaybeLocalArrayOptimized<S, N_LOCAL, T>
+{
+    pub fn capacity(&self) -> CountMax {
+        self.array.capacity()
+    }
+
+    pub fn set_capacity(&mut self, new_capacity: CountMax) -> Containered {
+        if new_capacity == self.capacity() {
+            return Ok(());
+        }
+        self.get_mut_with_capacity(new_capacity.to_usize())?
+            .set_capacity(new_capacity)
+    }
+
+    /// Returns `None` if there was nothing to remove or we couldn't unshare the array.
+    pub fn remove(&mut self, remove: OrderedRemove) -> Option<T> {
+        if !self.array.count().is_positive() {
+            return None;
+        }
+        self.get_mut().ok()?.remove(remove)
+    }
+}
+
+impl<T: Default + TryClone, A: Array<T> + TryCloneWithCapacity> Array<T> for SharedArray<A> {
+    fn len(&self) -> usize {
+        self.array.len()
+    }
+
+    fn insert(&mut self, insert: OrderedInsert<T>) -> Containered {
+        self.get_mut_with_capacity(self.array.len() + 1)?
+            .insert(insert)
+    }
+
+    fn insert_few<E, F: Few<T, Error = E>>(
+        &mut self,
+        insert: OrderedInsertFew<T, E, F>,
+    ) -> Containered {
+        let OrderedInsertFew::AtEnd(values, ..) = &insert;
+        let capacity = self.array.len() + values.size();
+        self.get_mut_with_capacity(capacity)?.insert_few(insert)
+    }
+}
+
+impl<S: SignedPrimitive, A: GetCount<S>> GetCount<S> for SharedArray<A> {
+    fn count(&self) -> Count<S> {
+        self.array.count()
+    }
+}
+
+impl<
+    S: SignedPrimitive,
+    A: SetCount<S, Error = ContainerError> + GetCount<S> + TryCloneWithCapacity,
+> SetCount<S> for SharedArray<A>
+{
+    type Error = ContainerError;
+
+    fn set_count(&mut self, new_count: Count<S>) -> Containered {
+        if new_count == self.array.count() {
+            return Ok(());
+        }
+        self.get_mut_with_capacity(new_count.to_usize())?
+            .set_count(new_count)
+    }
+}
+
+impl<T, A: std::ops::Deref<Target = [T]>> std::ops::Deref for SharedArray<A> {
+    type Target = [T];
+
+    fn deref(&self) -> &[T] {
+        self.array.deref()
+    }
+}
+
+impl<T, A: std::ops::DerefMut<Target = [T]> + TryClone> std::ops::DerefMut for SharedArray<A> {
+    /// Panics if we need to copy the array but run out of memory;
+    /// call `get_mut()` first if you need to handle that.
+    fn deref_mut(&mut self) -> &mut [T] {
+        self.get_mut()
+            .expect("should be able to unshare")
+            .deref_mut()
+    }
+}
+
+impl<A: PartialEq> PartialEq<Self> for SharedArray<A> {
+    fn eq(&self, other: &Self) -> bool {
+        self.is_sharing(other) || self.array == other.array
+    }
+}
+
+impl<A: Eq> Eq for SharedArray<A> {}
+
+impl<A: std::fmt::Debug> std::fmt::Debug for SharedArray<A> {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "SharedArray::new({:?})", self.array)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::core::testing::*;
+
+    fn noisy_array(values: &[i32]) -> SharedNonLocalArrayCount32<TestingNoisy> {
+        let mut array = NonLocalArrayCount32::<TestingNoisy>::default();
+        array
+            .set_capacity(Count::of(values.len()).expect("ok"))
+            .expect("ok");
+        for value in values {
+            array
+                .insert(OrderedInsert::AtEnd(TestingNoisy::new(*value)))
+                .expect("ok");
+        }
+        SharedArray::new(array)
+    }
+
+    #[test]
+    fn clone_is_shallow() {
+        let array = noisy_array(&[1, 2]);
+        _ = testing_prints();
+
+        let clone = array.try_clone().expect("ok");
+        assert!(clone.is_sharing(&array));
+        assert!(!array.is_unique());
+        assert_eq!(clone, array);
+        assert_eq!(clone[1].value(), 2);
+        testing_unprint(vec![]);
+    }
+
+    #[test]
+    fn deref_mut_copies_shared_elements() {
+        let array = noisy_array(&[1, 2]);
+        let mut clone = array.clone();
+        _ = testing_prints();
+
+        clone[0] = TestingNoisy::new(3);
+        testing_unprint(vec![
+            Vec::from(b"noisy_new(3)"),
+            Vec::from(b"create(B: 2)"),
+            Vec::from(b"noisy_clone(1)"),
+            Vec::from(b"noisy_clone(2)"),
+            Vec::from(b"noisy_drop(1)"),
+        ]);
+        assert!(!clone.is_sharing(&array));
+        assert!(clone.is_unique());
+        assert_eq!(array[0].value(), 1);
+        assert_eq!(clone[0].value(), 3);
+
+        // Now that it's unique, we don't copy again.
+        clone[1] = TestingNoisy::new(4);
+        testing_unprint(vec![
+            Vec::from(b"noisy_new(4)"),
+            Vec::from(b"noisy_drop(2)"),
+        ]);
+    }
+
+    #[test]
+    fn insert_and_remove_copy_shared_elements() {
+        let array = noisy_array(&[5]);
+        let mut clone = array.clone();
+        _ = testing_prints();
+
+        clone
+            .insert(OrderedInsert::AtEnd(TestingNoisy::new(6)))
+            .expect("ok");
+        testing_unprint(vec![
+            Vec::from(b"noisy_new(6)"),
+            // Copies straight into the capacity needed for the insert.
+            Vec::from(b"create(B: 2)"),
+            Vec::from(b"noisy_clone(5)"),
+        ]);
+        assert_eq!(array.len(), 1);
+        assert_eq!(clone.len(), 2);
+
+        let mut other = array.clone();
+        assert_eq!(
+            other.remove(OrderedRemove::Last).map(|n| n.value()),
+            Some(5)
+        );
+        testing_unprint(vec![
+            Vec::from(b"create(C: 1)"),
+            Vec::from(b"noisy_clone(5)"),
+            Vec::from(b"noisy_drop(5)"), // the removed value
+        ]);
+        assert_eq!(array.len(), 1);
+        assert_eq!(other.len(), 0);
+    }
+
+    #[test]
+    fn set_capacity_copies_shared_elements() {
+        let mut array = noisy_array(&[7]);
+        let clone = array.clone();
+        _ = testing_prints();
+
+        array.set_capacity(Count::of(1).expect("ok")).expect("ok");
+        testing_unprint(vec![]); // no change
+        array.set_capacity(Count::of(3).expect("ok")).expect("ok");
+        testing_unprint(vec![
+            Vec::from(b"create(B: 3)"),
+            Vec::from(b"noisy_clone(7)"),
+        ]);
+        assert_eq!(array.capacity(), Count::of(3).expect("ok"));
+        assert_eq!(clone.capacity(), Count::of(1).expect("ok"));
+    }
+
+    #[test]
+    fn drops_elements_after_last_clone() {
+        {
+            let array = noisy_array(&[8]);
+            {
+                let _clone = array.clone();
+            }
+            _ = testing_prints();
+        }
+        testing_unprint(vec![Vec::from(b"noisy_drop(8)"), Vec::from(b"delete(A)")]);
+    }
+
+    #[test]
+    fn unshare_moves_unique_elements() {
+        let array = noisy_array(&[9]);
+        let clone = array.clone();
+        _ = testing_prints();
+
+        let copied = clone.unshare().expect("ok");
+        testing_unprint(vec![
+            Vec::from(b"create(B: 1)"),
+            Vec::from(b"noisy_clone(9)"),
+        ]);
+        let moved = array.unshare().expect("ok");
+        testing_unprint(vec![]);
+        assert_eq!(copied, moved);
+    }
+}
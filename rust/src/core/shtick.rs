@@ -1,6 +1,8 @@
+use crate::core::bit_array::*;
 use crate::core::container::*;
 use crate::core::count::*;
 use crate::core::maybe_local_array::*;
+use crate::core::shared_array::*;
 use crate::core::signed::*;
 
 pub type ShtickOptimized8 = ShtickOptimized<i8, 15>;
@@ -8,7 +10,144 @@ pub type ShtickOptimized16 = ShtickOptimized<i16, 14>;
 pub type ShtickOptimized32 = ShtickOptimized<i32, 12>;
 pub type ShtickOptimized64 = ShtickOptimized<i64, 16>;
 
-pub type ShtickOptimized<S, const N_LOCAL: usize> = MaybeLocalArrayOptimized<S, N_LOCAL, u8>;
+/// A string of bytes (usually, but not necessarily, UTF-8), which keeps short
+/// strings locally.  Works like a `MaybeLocalArrayOptimized<S, N_LOCAL, u8>`,
+/// but prints as text.
+#[repr(transparent)]
+pub struct ShtickOptimized<S: SignedPrimitive, const N_LOCAL: usize>(
+    MaybeLocalArrayOptimized<S, N_LOCAL, u8>,
+);
+
+pub type SharedShtickOptimized8 = SharedShtickOptimized<i8, 15>;
+pub type SharedShtickOptimized16 = SharedShtickOptimized<i16, 14>;
+pub type SharedShtickOptimized32 = SharedShtickOptimized<i32, 12>;
+pub type SharedShtickOptimized64 = SharedShtickOptimized<i64, 16>;
+
+/// Copy-on-write `ShtickOptimized`, for shticks that get cloned a lot
+/// (e.g., identifiers) but rarely modified.
+pub type SharedShtickOptimized<S, const N_LOCAL: usize> = SharedArray<ShtickOptimized<S, N_LOCAL>>;
+
+impl<S: SignedPrimitive, const N_LOCAL: usize> Default for ShtickOptimized<S, N_LOCAL> {
+    fn default() -> Self {
+        Self(MaybeLocalArrayOptimized::default())
+    }
+}
+
+impl<S: SignedPrimitive, const N_LOCAL: usize> From<MaybeLocalArrayOptimized<S, N_LOCAL, u8>>
+    for ShtickOptimized<S, N_LOCAL>
+{
+    fn from(bytes: MaybeLocalArrayOptimized<S, N_LOCAL, u8>) -> Self {
+        Self(bytes)
+    }
+}
+
+impl<S: SignedPrimitive, const N_LOCAL: usize> ShtickOptimized<S, N_LOCAL> {
+    pub fn into_array(self) -> MaybeLocalArrayOptimized<S, N_LOCAL, u8> {
+        self.0
+    }
+
+    pub fn capacity(&self) -> CountMax {
+        self.0.capacity()
+    }
+
+    /// See `MaybeLocalArrayOptimized::set_capacity`.
+    pub fn set_capacity(&mut self, new_capacity: CountMax) -> Containered {
+        self.0.set_capacity(new_capacity)
+    }
+
+    /// Looking for `fn pop`? use `remove(OrderedRemove::Last)`
+    pub fn remove(&mut self, remove: OrderedRemove) -> Option<u8> {
+        self.0.remove(remove)
+    }
+
+    pub(crate) fn only_set_count(&mut self, new_count: CountMax) {
+        self.0.only_set_count(new_count)
+    }
+
+    pub(crate) fn fully_allocated_slice_mut(&mut self) -> &mut [u8] {
+        self.0.fully_allocated_slice_mut()
+    }
+}
+
+impl<S: SignedPrimitive, const N_LOCAL: usize> GetCount<i64> for ShtickOptimized<S, N_LOCAL> {
+    fn count(&self) -> CountMax {
+        self.0.count()
+    }
+}
+
+impl<S: SignedPrimitive, const N_LOCAL: usize> SetCount<i64> for ShtickOptimized<S, N_LOCAL> {
+    type Error = ContainerError;
+
+    fn set_count(&mut self, new_count: CountMax) -> Containered {
+        self.0.set_count(new_count)
+    }
+}
+
+impl<S: SignedPrimitive, const N_LOCAL: usize> Array<u8> for ShtickOptimized<S, N_LOCAL> {
+    fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    fn insert(&mut self, insert: OrderedInsert<u8>) -> Containered {
+        self.0.insert(insert)
+    }
+
+    fn insert_few<E, Values: Few<u8, Error = E>>(
+        &mut self,
+        insert: OrderedInsertFew<u8, E, Values>,
+    ) -> Containered {
+        self.0.insert_few(insert)
+    }
+}
+
+impl<S: SignedPrimitive, const N_LOCAL: usize> std::ops::Deref for ShtickOptimized<S, N_LOCAL> {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        &self.0
+    }
+}
+
+impl<S: SignedPrimitive, const N_LOCAL: usize> std::ops::DerefMut for ShtickOptimized<S, N_LOCAL> {
+    fn deref_mut(&mut self) -> &mut [u8] {
+        &mut self.0
+    }
+}
+
+impl<S: SignedPrimitive, const N_LOCAL: usize> PartialEq<Self> for ShtickOptimized<S, N_LOCAL> {
+    fn eq(&self, other: &Self) -> bool {
+        self.0 == other.0
+    }
+}
+
+impl<S: SignedPrimitive, const N_LOCAL: usize> Eq for ShtickOptimized<S, N_LOCAL> {}
+
+impl<S: SignedPrimitive, const N_LOCAL: usize> TryClone for ShtickOptimized<S, N_LOCAL> {
+    type Error = ContainerError;
+
+    fn try_clone(&self) -> ContainerResult<Self> {
+        Ok(Self(self.0.try_clone()?))
+    }
+}
+
+impl<S: SignedPrimitive, const N_LOCAL: usize> TryCloneWithCapacity
+    for ShtickOptimized<S, N_LOCAL>
+{
+    fn try_clone_with_capacity(&self, capacity: usize) -> ContainerResult<Self> {
+        Ok(Self(self.0.try_clone_with_capacity(capacity)?))
+    }
+}
+
+/// Prints as `ShtickOptimized8::try_from("text")` if the bytes are valid UTF-8,
+/// otherwise as the underlying array of bytes.
+impl<S: SignedPrimitive, const N_LOCAL: usize> std::fmt::Debug for ShtickOptimized<S, N_LOCAL> {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match std::str::from_utf8(self) {
+            Ok(string) => write!(f, "ShtickOptimized{}::try_from({:?})", S::BITS, string),
+            Err(_) => write!(f, "{:?}", self.0),
+        }
+    }
+}
 
 impl<S: SignedPrimitive, const N_LOCAL: usize> TryFrom<&str> for ShtickOptimized<S, N_LOCAL> {
     type Error = ContainerError;
@@ -25,6 +164,469 @@ impl<S: SignedPrimitive, const N_LOCAL: usize> TryFrom<&str> for ShtickOptimized
     }
 }
 
+impl<S: SignedPrimitive, const N_LOCAL: usize> SharedShtickOptimized<S, N_LOCAL> {
+    pub fn capacity(&self) -> CountMax {
+        self.get().capacity()
+    }
+
+    pub fn set_capacity(&mut self, new_capacity: CountMax) -> Containered {
+        if new_capacity == self.capacity() {
+            return Ok(());
+        }
+        self.get_mut_with_capacity(new_capacity.to_usize())?
+            .set_capacity(new_capacity)
+    }
+
+    /// Returns `None` if there was nothing to remove or we couldn't unshare the shtick.
+    pub fn remove(&mut self, remove: OrderedRemove) -> Option<u8> {
+        if self.is_empty() {
+            return None;
+        }
+        self.get_mut().ok()?.remove(remove)
+    }
+}
+
+impl<S: SignedPrimitive, const N_LOCAL: usize> TryFrom<&str> for SharedShtickOptimized<S, N_LOCAL> {
+    type Error = ContainerError;
+
+    fn try_from(value: &str) -> ContainerResult<Self> {
+        Ok(SharedArray::new(ShtickOptimized::try_from(value)?))
+    }
+}
+
+impl<S: SignedPrimitive, const N_LOCAL: usize> ShtickOptimized<S, N_LOCAL> {
+    /// Formats directly into a new shtick, without going through a `String`,
+    /// e.g., `ShtickOptimized8::format(format_args!("{}: {}", name, value))`.
+    pub fn format(arguments: std::fmt::Arguments) -> ContainerResult<Self> {
+        let mut result = Self::default();
+        result.insert_format(arguments)?;
+        Ok(result)
+    }
+
+    /// Formats onto the end of this shtick.  Unlike `write!`, which can only
+    /// return a `std::fmt::Error`, this tells you if we ran out of memory.
+    /// If formatting fails partway through, the shtick keeps what was written.
+    pub fn insert_format(&mut self, arguments: std::fmt::Arguments) -> Containered {
+        let mut writer = ShtickWriter {
+            shtick: self,
+            error: None,
+        };
+        match std::fmt::Write::write_fmt(&mut writer, arguments) {
+            Ok(()) => Ok(()),
+            // If we didn't see an allocation error, some `fmt` impl errored.
+            Err(_) => Err(writer.error.unwrap_or(ContainerError::Unknown)),
+        }
+    }
+}
+
+struct ShtickWriter<'a, S: SignedPrimitive, const N_LOCAL: usize> {
+    shtick: &'a mut ShtickOptimized<S, N_LOCAL>,
+    error: Option<ContainerError>,
+}
+
+impl<S: SignedPrimitive, const N_LOCAL: usize> std::fmt::Write for ShtickWriter<'_, S, N_LOCAL> {
+    fn write_str(&mut self, string: &str) -> std::fmt::Result {
+        self.shtick.append(string.as_bytes()).map_err(|error| {
+            self.error = Some(error);
+            std::fmt::Error
+        })
+    }
+}
+
+/// Allows `write!(shtick, ...)`; errors if we run out of memory.
+/// Use `insert_format` to find out which error happened.
+impl<S: SignedPrimitive, const N_LOCAL: usize> std::fmt::Write for ShtickOptimized<S, N_LOCAL> {
+    fn write_str(&mut self, string: &str) -> std::fmt::Result {
+        self.append(string.as_bytes()).map_err(|_| std::fmt::Error)
+    }
+}
+
+/// Prints as text; invalid UTF-8 sequences are printed as U+FFFD.
+impl<S: SignedPrimitive, const N_LOCAL: usize> std::fmt::Display for ShtickOptimized<S, N_LOCAL> {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        for chunk in self.utf8_chunks() {
+            f.write_str(chunk.valid())?;
+            if !chunk.invalid().is_empty() {
+                f.write_str(char::REPLACEMENT_CHARACTER.encode_utf8(&mut [0u8; 4]))?;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl<S: SignedPrimitive, const N_LOCAL: usize> PartialEq<str> for ShtickOptimized<S, N_LOCAL> {
+    fn eq(&self, other: &str) -> bool {
+        self[..] == *other.as_bytes()
+    }
+}
+
+impl<S: SignedPrimitive, const N_LOCAL: usize> PartialEq<&str> for ShtickOptimized<S, N_LOCAL> {
+    fn eq(&self, other: &&str) -> bool {
+        self[..] == *other.as_bytes()
+    }
+}
+
+impl<S: SignedPrimitive, const N_LOCAL: usize> PartialEq<ShtickOptimized<S, N_LOCAL>> for str {
+    fn eq(&self, other: &ShtickOptimized<S, N_LOCAL>) -> bool {
+        other == self
+    }
+}
+
+impl<S: SignedPrimitive, const N_LOCAL: usize> PartialEq<ShtickOptimized<S, N_LOCAL>> for &str {
+    fn eq(&self, other: &ShtickOptimized<S, N_LOCAL>) -> bool {
+        other == self
+    }
+}
+
+/// What to look for in a shtick, e.g., for `find` or `split`.  Usually built via
+/// `From`, so that `shtick.find(b"abc")`, `shtick.find(b'a')`, and
+/// `shtick.find(BitArray256::of(b" \t"))` all work.
+#[derive(Eq, PartialEq, Copy, Clone, Debug)]
+pub enum ShtickPattern<'a> {
+    /// Matches this exact byte.
+    Byte(u8),
+    /// Matches this exact sequence of bytes.  Like `str` patterns, an empty sequence
+    /// matches everywhere, i.e., at the start, between each byte, and at the end.
+    Bytes(&'a [u8]),
+    /// Matches any one byte in this set.
+    AnyOf(BitArray256),
+}
+
+impl ShtickPattern<'_> {
+    /// Number of bytes that a match of this pattern spans.
+    pub fn len(&self) -> usize {
+        match self {
+            ShtickPattern::Bytes(bytes) => bytes.len(),
+            ShtickPattern::Byte(_) | ShtickPattern::AnyOf(_) => 1,
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Offset of the first match in `haystack`, if any.
+    pub fn find_in(&self, haystack: &[u8]) -> Option<usize> {
+        match self {
+            ShtickPattern::Byte(byte) => find_byte(haystack, *byte),
+            ShtickPattern::Bytes(needle) => find_bytes(haystack, needle),
+            ShtickPattern::AnyOf(set) => haystack.iter().position(|byte| set.get(*byte)),
+        }
+    }
+
+    /// Offset of the last match in `haystack`, if any.
+    pub fn rfind_in(&self, haystack: &[u8]) -> Option<usize> {
+        match self {
+            ShtickPattern::Byte(byte) => rfind_byte(haystack, *byte),
+            ShtickPattern::Bytes(needle) => rfind_bytes(haystack, needle),
+            ShtickPattern::AnyOf(set) => haystack.iter().rposition(|byte| set.get(*byte)),
+        }
+    }
+
+    pub fn is_prefix_of(&self, haystack: &[u8]) -> bool {
+        match self {
+            ShtickPattern::Byte(byte) => haystack.first() == Some(byte),
+            ShtickPattern::Bytes(needle) => haystack.starts_with(needle),
+            ShtickPattern::AnyOf(set) => haystack.first().is_some_and(|byte| set.get(*byte)),
+        }
+    }
+
+    pub fn is_suffix_of(&self, haystack: &[u8]) -> bool {
+        match self {
+            ShtickPattern::Byte(byte) => haystack.last() == Some(byte),
+            ShtickPattern::Bytes(needle) => haystack.ends_with(needle),
+            ShtickPattern::AnyOf(set) => haystack.last().is_some_and(|byte| set.get(*byte)),
+        }
+    }
+}
+
+impl From<u8> for ShtickPattern<'_> {
+    fn from(byte: u8) -> Self {
+        ShtickPattern::Byte(byte)
+    }
+}
+
+impl<'a> From<&'a [u8]> for ShtickPattern<'a> {
+    fn from(bytes: &'a [u8]) -> Self {
+        ShtickPattern::Bytes(bytes)
+    }
+}
+
+impl<'a, const N: usize> From<&'a [u8; N]> for ShtickPattern<'a> {
+    fn from(bytes: &'a [u8; N]) -> Self {
+        ShtickPattern::Bytes(bytes)
+    }
+}
+
+impl<'a> From<&'a str> for ShtickPattern<'a> {
+    fn from(string: &'a str) -> Self {
+        ShtickPattern::Bytes(string.as_bytes())
+    }
+}
+
+impl From<BitArray256> for ShtickPattern<'_> {
+    fn from(set: BitArray256) -> Self {
+        ShtickPattern::AnyOf(set)
+    }
+}
+
+impl<S: SignedPrimitive, const N_LOCAL: usize> ShtickOptimized<S, N_LOCAL> {
+    /// Byte offset of the first match of `pattern`, if any.
+    pub fn find<'p>(&self, pattern: impl Into<ShtickPattern<'p>>) -> Option<usize> {
+        pattern.into().find_in(self)
+    }
+
+    /// Byte offset of the start of the last match of `pattern`, if any.
+    pub fn rfind<'p>(&self, pattern: impl Into<ShtickPattern<'p>>) -> Option<usize> {
+        pattern.into().rfind_in(self)
+    }
+
+    pub fn starts_with<'p>(&self, pattern: impl Into<ShtickPattern<'p>>) -> bool {
+        pattern.into().is_prefix_of(self)
+    }
+
+    pub fn ends_with<'p>(&self, pattern: impl Into<ShtickPattern<'p>>) -> bool {
+        pattern.into().is_suffix_of(self)
+    }
+
+    /// Borrows the bytes between each match of `separator`, e.g., splitting
+    /// `"a,,b"` by `b','` gives `"a"`, `""`, and `"b"`.  An empty separator
+    /// matches everywhere, so `"ab"` gives `""`, `"a"`, `"b"`, and `""`.
+    pub fn split<'p>(&self, separator: impl Into<ShtickPattern<'p>>) -> ShtickSplit<'_, 'p> {
+        ShtickSplit {
+            remaining: Some(self),
+            separator: separator.into(),
+            started: false,
+        }
+    }
+
+    /// Removes all leading matches of `pattern`, e.g., use
+    /// `trim_start(BitArray256::of(b" \t"))` for leading spaces and tabs.
+    pub fn trim_start<'p>(&self, pattern: impl Into<ShtickPattern<'p>>) -> &[u8] {
+        let pattern = pattern.into();
+        let mut bytes: &[u8] = self;
+        if pattern.is_empty() {
+            return bytes;
+        }
+        while pattern.is_prefix_of(bytes) {
+            bytes = &bytes[pattern.len()..];
+        }
+        bytes
+    }
+
+    /// Removes all trailing matches of `pattern`.
+    pub fn trim_end<'p>(&self, pattern: impl Into<ShtickPattern<'p>>) -> &[u8] {
+        let pattern = pattern.into();
+        let mut bytes: &[u8] = self;
+        if pattern.is_empty() {
+            return bytes;
+        }
+        while pattern.is_suffix_of(bytes) {
+            bytes = &bytes[..bytes.len() - pattern.len()];
+        }
+        bytes
+    }
+
+    /// Copies into a new shtick with the first match of `from` replaced by `to`.
+    pub fn replace<'p>(
+        &self,
+        from: impl Into<ShtickPattern<'p>>,
+        to: &[u8],
+    ) -> ContainerResult<Self> {
+        self.replacing(from.into(), to, 1)
+    }
+
+    /// Copies into a new shtick with every (non-overlapping) match of `from`
+    /// replaced by `to`.  An empty `from` matches everywhere, so replacing it
+    /// in `"ab"` with `"x"` gives `"xaxbx"`.
+    pub fn replace_all<'p>(
+        &self,
+        from: impl Into<ShtickPattern<'p>>,
+        to: &[u8],
+    ) -> ContainerResult<Self> {
+        self.replacing(from.into(), to, usize::MAX)
+    }
+
+    /// Copies into a new shtick with `times` copies of this one.
+    pub fn repeat(&self, times: usize) -> ContainerResult<Self> {
+        let total = self
+            .len()
+            .checked_mul(times)
+            .ok_or(ContainerError::OutOfMemory)?;
+        let mut result = Self::with_count_at_least(total)?;
+        for _ in 0..times {
+            result.append(self)?;
+        }
+        Ok(result)
+    }
+
+    fn replacing(&self, from: ShtickPattern, to: &[u8], mut times: usize) -> ContainerResult<Self> {
+        let mut result = Self::with_count_at_least(self.len())?;
+        let mut remaining: &[u8] = self;
+        while times > 0 {
+            let Some(at) = from.find_in(remaining) else {
+                break;
+            };
+            result.append(&remaining[0..at])?;
+            result.append(to)?;
+            times -= 1;
+            if !from.is_empty() {
+                remaining = &remaining[at + from.len()..];
+            } else if let Some((first, rest)) = remaining.split_first() {
+                // The next empty match is after this byte.
+                result.append(std::slice::from_ref(first))?;
+                remaining = rest;
+            } else {
+                break;
+            }
+        }
+        result.append(remaining)?;
+        Ok(result)
+    }
+
+    fn with_count_at_least(count: usize) -> ContainerResult<Self> {
+        let mut result = Self::default();
+        let count = Count::<i64>::of(count).map_err(|_| ContainerError::OutOfMemory)?;
+        if count > result.capacity() {
+            result.set_capacity(count)?;
+        }
+        Ok(result)
+    }
+
+    pub(crate) fn append(&mut self, bytes: &[u8]) -> Containered {
+        self.insert_few(OrderedInsertFew::AtEnd(bytes, TypeMarker, TypeMarker))
+    }
+}
+
+/// Iterator over the borrowed pieces of a shtick between matches of a separator,
+/// see `ShtickOptimized::split`.
+pub struct ShtickSplit<'a, 'p> {
+    remaining: Option<&'a [u8]>,
+    separator: ShtickPattern<'p>,
+    /// Whether we've given back the piece before an empty separator's first match.
+    started: bool,
+}
+
+impl<'a> Iterator for ShtickSplit<'a, '_> {
+    type Item = &'a [u8];
+
+    fn next(&mut self) -> Option<&'a [u8]> {
+        let remaining = self.remaining?;
+        if self.separator.is_empty() {
+            // Like `str::split("")`: an empty piece, each byte, then an empty piece.
+            if !self.started {
+                self.started = true;
+                return Some(&remaining[0..0]);
+            }
+            if remaining.is_empty() {
+                self.remaining = None;
+            } else {
+                self.remaining = Some(&remaining[1..]);
+            }
+            return Some(&remaining[0..remaining.len().min(1)]);
+        }
+        match self.separator.find_in(remaining) {
+            Some(at) => {
+                self.remaining = Some(&remaining[at + self.separator.len()..]);
+                Some(&remaining[0..at])
+            }
+            None => {
+                self.remaining = None;
+                Some(remaining)
+            }
+        }
+    }
+}
+
+const WORD_BYTES: usize = std::mem::size_of::<u64>();
+const LOW_BITS: u64 = 0x0101_0101_0101_0101;
+const HIGH_BITS: u64 = 0x8080_8080_8080_8080;
+
+/// Sets the high bit of each zero byte in `word` (and possibly of some bytes
+/// above a zero byte, so only the lowest set bit is reliable).
+#[inline]
+fn zero_byte_bits(word: u64) -> u64 {
+    word.wrapping_sub(LOW_BITS) & !word & HIGH_BITS
+}
+
+#[inline]
+fn read_word(bytes: &[u8], at: usize) -> u64 {
+    u64::from_le_bytes(
+        bytes[at..at + WORD_BYTES]
+            .try_into()
+            .expect("should be a word"),
+    )
+}
+
+/// Like `memchr`, checks a word (8 bytes) at a time.
+fn find_byte(haystack: &[u8], needle: u8) -> Option<usize> {
+    let repeated = LOW_BITS * needle as u64;
+    let mut at = 0;
+    while at + WORD_BYTES <= haystack.len() {
+        let found = zero_byte_bits(read_word(haystack, at) ^ repeated);
+        if found != 0 {
+            // Little endian, so the lowest bit is the earliest byte.
+            return Some(at + found.trailing_zeros() as usize / 8);
+        }
+        at += WORD_BYTES;
+    }
+    haystack[at..]
+        .iter()
+        .position(|byte| *byte == needle)
+        .map(|offset| at + offset)
+}
+
+/// Like `memrchr`, checks a word (8 bytes) at a time from the end.
+fn rfind_byte(haystack: &[u8], needle: u8) -> Option<usize> {
+    let repeated = LOW_BITS * needle as u64;
+    let mut end = haystack.len();
+    while end >= WORD_BYTES {
+        let at = end - WORD_BYTES;
+        if zero_byte_bits(read_word(haystack, at) ^ repeated) != 0 {
+            // The highest bit isn't reliable, so check bytes individually.
+            return haystack[at..end]
+                .iter()
+                .rposition(|byte| *byte == needle)
+                .map(|offset| at + offset);
+        }
+        end = at;
+    }
+    haystack[0..end].iter().rposition(|byte| *byte == needle)
+}
+
+/// Skips ahead with `find_byte` on the first byte of `needle`, then compares.
+fn find_bytes(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    let Some((first, rest)) = needle.split_first() else {
+        return Some(0);
+    };
+    let last_start = haystack.len().checked_sub(needle.len())?;
+    let mut at = 0;
+    while at <= last_start {
+        at += find_byte(&haystack[at..=last_start], *first)?;
+        if haystack[at + 1..at + needle.len()] == *rest {
+            return Some(at);
+        }
+        at += 1;
+    }
+    None
+}
+
+/// Skips back with `rfind_byte` on the first byte of `needle`, then compares.
+fn rfind_bytes(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    let Some((first, rest)) = needle.split_first() else {
+        return Some(haystack.len());
+    };
+    let mut end = haystack.len().checked_sub(needle.len())? + 1;
+    while end > 0 {
+        let at = rfind_byte(&haystack[0..end], *first)?;
+        if haystack[at + 1..at + needle.len()] == *rest {
+            return Some(at);
+        }
+        end = at;
+    }
+    None
+}
+
 #[cfg(test)]
 mod test {
     use super::*;
@@ -64,4 +666,217 @@ mod test {
         }
         testing_unprint(vec![Vec::from(b"delete(A)")]);
     }
+
+    #[test]
+    fn shared_shtick_copies_on_write() {
+        let shtick = SharedShtickOptimized8::try_from("a long identifier name").expect("ok");
+        let mut clone = shtick.clone();
+        testing_unprint(vec![Vec::from(b"create(A: 22)")]);
+
+        clone[0] = b'A';
+        testing_unprint(vec![Vec::from(b"create(B: 22)")]);
+        assert_eq!(&shtick[..], b"a long identifier name");
+        assert_eq!(&clone[..], b"A long identifier name");
+    }
+
+    #[test]
+    fn find_and_rfind() {
+        let shtick = ShtickOptimized16::try_from("one two three two one").expect("ok");
+        assert_eq!(shtick.find(b't'), Some(4));
+        assert_eq!(shtick.rfind(b't'), Some(14));
+        assert_eq!(shtick.find(b'x'), None);
+        assert_eq!(shtick.find(b"two"), Some(4));
+        assert_eq!(shtick.rfind(b"two"), Some(14));
+        assert_eq!(shtick.find("one"), Some(0));
+        assert_eq!(shtick.rfind("one"), Some(18));
+        assert_eq!(shtick.find(b"twos"), None);
+        assert_eq!(shtick.find(b""), Some(0));
+        assert_eq!(shtick.rfind(b""), Some(21));
+        let vowels = BitArray256::of(b"aeiou");
+        assert_eq!(shtick.find(vowels), Some(0));
+        assert_eq!(shtick.rfind(vowels), Some(20));
+        assert_eq!(shtick.find(BitArray256::of(b"xyz")), None);
+    }
+
+    #[test]
+    fn find_bytes_across_words() {
+        // Check every position so that we cover both the word-at-a-time loop
+        // and the leftover bytes.
+        for length in 0..40 {
+            for at in 0..length {
+                let mut bytes = vec![b'a'; length];
+                bytes[at] = b'b';
+                assert_eq!(find_byte(&bytes, b'b'), Some(at));
+                assert_eq!(rfind_byte(&bytes, b'b'), Some(at));
+                assert_eq!(find_byte(&bytes, b'c'), None);
+                assert_eq!(rfind_byte(&bytes, b'c'), None);
+                assert_eq!(find_bytes(&bytes, b"ab"), at.checked_sub(1));
+                assert_eq!(rfind_bytes(&bytes, b"ba"), (at + 1 < length).then_some(at));
+            }
+        }
+        // Bytes with the high bit set shouldn't give false positives.
+        assert_eq!(
+            find_byte(&[0x80, 0x81, 0xff, 0x00, 0x01, 0x7f, 0x80, 0x01], 0x01),
+            Some(4)
+        );
+        assert_eq!(
+            rfind_byte(&[0x01, 0x81, 0xff, 0x00, 0x01, 0x7f, 0x80, 0x02], 0x81),
+            Some(1)
+        );
+    }
+
+    #[test]
+    fn split_borrows_pieces() {
+        let shtick = ShtickOptimized8::try_from("a,,bc,").expect("ok");
+        assert_eq!(
+            shtick.split(b',').collect::<Vec<_>>(),
+            vec![&b"a"[..], b"", b"bc", b""]
+        );
+        assert_eq!(
+            shtick.split(b",,").collect::<Vec<_>>(),
+            vec![&b"a"[..], b"bc,"]
+        );
+        assert_eq!(
+            shtick.split(BitArray256::of(b"bc")).collect::<Vec<_>>(),
+            vec![&b"a,,"[..], b"", b","]
+        );
+        assert_eq!(
+            ShtickOptimized8::try_from("ab")
+                .expect("ok")
+                .split(b"")
+                .collect::<Vec<_>>(),
+            vec![&b""[..], b"a", b"b", b""]
+        );
+        assert_eq!(
+            ShtickOptimized8::default().split(b"").collect::<Vec<_>>(),
+            vec![&b""[..], b""]
+        );
+        assert_eq!(
+            ShtickOptimized8::default().split(b',').collect::<Vec<_>>(),
+            vec![&b""[..]]
+        );
+        testing_unprint(vec![]);
+    }
+
+    #[test]
+    fn trim_and_affixes() {
+        let shtick = ShtickOptimized32::try_from(" \t abab ok abab\t ").expect("ok");
+        let whitespace = BitArray256::of(b" \t");
+        assert!(shtick.starts_with(b' '));
+        assert!(shtick.starts_with(b" \t"));
+        assert!(shtick.starts_with(whitespace));
+        assert!(!shtick.starts_with(b"\t"));
+        assert!(shtick.ends_with(b"\t "));
+        assert!(!shtick.ends_with(b"ab"));
+        assert_eq!(shtick.trim_start(whitespace), b"abab ok abab\t ");
+        assert_eq!(shtick.trim_end(whitespace), b" \t abab ok abab");
+        assert_eq!(shtick.trim_start(b' '), b"\t abab ok abab\t ");
+        assert_eq!(shtick.trim_start(b""), &shtick[..]);
+
+        let shtick = ShtickOptimized32::try_from("ababxab").expect("ok");
+        assert_eq!(shtick.trim_start(b"ab"), b"xab");
+        assert_eq!(shtick.trim_end(b"ab"), b"ababx");
+    }
+
+    #[test]
+    fn replace_into_new_shtick() {
+        let shtick = ShtickOptimized8::try_from("a-b-c").expect("ok");
+        let replaced = shtick.replace(b'-', b"::").expect("ok");
+        assert_eq!(&replaced[..], b"a::b-c");
+        let replaced = shtick.replace_all(b'-', b"::").expect("ok");
+        assert_eq!(&replaced[..], b"a::b::c");
+        let replaced = shtick.replace_all(b"-b-", b"").expect("ok");
+        assert_eq!(&replaced[..], b"ac");
+        let replaced = shtick
+            .replace_all(BitArray256::of(b"ac"), b"x")
+            .expect("ok");
+        assert_eq!(&replaced[..], b"x-b-x");
+        // Empty patterns match everywhere, like `find(b"")` does.
+        let replaced = shtick.replace_all(b"", b"x").expect("ok");
+        assert_eq!(&replaced[..], b"xax-xbx-xcx");
+        let replaced = shtick.replace(b"", b"x").expect("ok");
+        assert_eq!(&replaced[..], b"xa-b-c");
+        testing_unprint(vec![]);
+
+        let replaced = shtick.replace_all(b'-', b" and also ").expect("ok");
+        assert_eq!(&replaced[..], b"a and also b and also c");
+        testing_unprint(vec![Vec::from(b"create(A: 30)")]);
+    }
+
+    #[test]
+    fn repeat_into_new_shtick() {
+        let shtick = ShtickOptimized64::try_from("ab").expect("ok");
+        assert_eq!(&shtick.repeat(3).expect("ok")[..], b"ababab");
+        assert_eq!(&shtick.repeat(0).expect("ok")[..], b"");
+        testing_unprint(vec![]);
+        assert_eq!(
+            shtick.repeat(usize::MAX).err(),
+            Some(ContainerError::OutOfMemory)
+        );
+
+        let repeated = shtick.repeat(10).expect("ok");
+        assert_eq!(repeated.len(), 20);
+        testing_unprint(vec![Vec::from(b"create(A: 20)")]);
+    }
+
+    #[test]
+    fn format_without_string() {
+        let mut shtick = ShtickOptimized8::format(format_args!("{}+{}", 1, 2)).expect("ok");
+        assert_eq!(shtick, "1+2");
+        shtick
+            .insert_format(format_args!(" = {:>3}", 3))
+            .expect("ok");
+        assert_eq!(shtick, "1+2 =   3");
+        testing_unprint(vec![]);
+
+        use std::fmt::Write;
+        let (yes, half) = (true, 0.5);
+        write!(shtick, "; {} is {}", yes, half).expect("ok");
+        assert_eq!(shtick, "1+2 =   3; true is 0.5");
+        testing_unprint(vec![Vec::from(b"create(A: 30)")]);
+    }
+
+    #[test]
+    fn format_surfaces_errors() {
+        struct Failing;
+        impl std::fmt::Display for Failing {
+            fn fmt(&self, _f: &mut std::fmt::Formatter) -> std::fmt::Result {
+                Err(std::fmt::Error)
+            }
+        }
+        let mut shtick = ShtickOptimized16::default();
+        assert_eq!(
+            shtick.insert_format(format_args!("ok {}", Failing)),
+            Err(ContainerError::Unknown)
+        );
+        assert_eq!(shtick, "ok ");
+    }
+
+    #[test]
+    fn display_and_debug_as_text() {
+        let mut shtick = ShtickOptimized32::try_from("h\"i").expect("ok");
+        assert_eq!(format!("{}", shtick), "h\"i");
+        assert_eq!(
+            format!("{:?}", shtick),
+            "ShtickOptimized32::try_from(\"h\\\"i\")"
+        );
+
+        shtick.insert(OrderedInsert::AtEnd(0xff)).expect("ok");
+        assert_eq!(format!("{}", shtick), "h\"i\u{fffd}");
+        assert_eq!(
+            format!("{:?}", shtick),
+            "MaybeLocalArrayOptimized32::<12, _>::from([104, 34, 105, 255, ])"
+        );
+    }
+
+    #[test]
+    fn compare_with_str() {
+        let shtick = ShtickOptimized64::try_from("oh").expect("ok");
+        assert_eq!(shtick, "oh");
+        assert_eq!("oh", shtick);
+        assert!(shtick != "ohh");
+        assert!(*"oh" == shtick);
+        let shticks = [shtick, ShtickOptimized64::default()];
+        assert_eq!(shticks, ["oh", ""]);
+    }
 }
@@ -0,0 +1,332 @@
+use crate::core::shtick::*;
+
+pub use crate::core::array::*;
+
+/// Runtime helpers for oh's string syntax (see "defining strings" in `wishlist.md`),
+/// so that the interpreter and the C backend agree on the details.
+impl<S: SignedPrimitive, const N_LOCAL: usize> ShtickOptimized<S, N_LOCAL> {
+    /// oh's `left & right`, which strips trailing whitespace from `left` and leading
+    /// whitespace from `right`, then adds a space between them if both are non-empty,
+    /// e.g., `'123\n \n' & '\n456'` is `'123 456'`.
+    pub fn ampersand(left: &[u8], right: &[u8]) -> ContainerResult<Self> {
+        let mut result = Self::default();
+        result.append(left)?;
+        result.insert_ampersand(right)?;
+        Ok(result)
+    }
+
+    /// In-place `self & right`; see `ampersand`.
+    pub fn insert_ampersand(&mut self, right: &[u8]) -> Containered {
+        while self.last().is_some_and(u8::is_ascii_whitespace) {
+            self.remove(OrderedRemove::Last);
+        }
+        let right = right.trim_ascii_start();
+        if !self.is_empty() && !right.is_empty() {
+            self.insert(OrderedInsert::AtEnd(b' '))?;
+        }
+        self.append(right)
+    }
+
+    /// Lines of a `&|` multiline string (without the `&|` prefix), which are
+    /// joined like `&`, e.g., `&|hello` and `&|world` give `"hello world"`.
+    pub fn from_ampersand_lines<'a>(
+        lines: impl IntoIterator<Item = &'a [u8]>,
+    ) -> ContainerResult<Self> {
+        let mut result = Self::default();
+        for line in lines {
+            result.insert_ampersand(line)?;
+        }
+        Ok(result)
+    }
+
+    /// Lines of a `+|` multiline string (without the `+|` prefix), which each
+    /// get a trailing newline, e.g., `+|hello` and `+|world` give `"hello\nworld\n"`.
+    /// Whitespace is kept as is.
+    pub fn from_plus_lines<'a>(lines: impl IntoIterator<Item = &'a [u8]>) -> ContainerResult<Self> {
+        let mut result = Self::default();
+        for line in lines {
+            result.append(line)?;
+            result.insert(OrderedInsert::AtEnd(b'\n'))?;
+        }
+        Ok(result)
+    }
+
+    /// Builds a string from a `template` with `$(...)`, `${...}`, and `$[...]`
+    /// interpolations.  Text outside interpolations is copied as is, except for
+    /// escapes: `\$(` and `$\(` (and similarly for `{` and `[`) both give a
+    /// literal `$(`.  A `$` which isn't followed by an open bracket (e.g., `$a`)
+    /// is never interpolated.  Other backslashes are left for the lexer.
+    ///
+    /// For each interpolation, `evaluate` gets the expression inside the brackets
+    /// and should append its value to the shtick, e.g., for `$(x, y)` it should
+    /// evaluate `x` and `y` but only append `y`.  Nested brackets and quotes in
+    /// the expression are skipped over, so `${if c {a} else {'b$("!")'}}` is one
+    /// interpolation.
+    pub fn interpolate(
+        template: &[u8],
+        mut evaluate: impl FnMut(Interpolation<'_>, &mut Self) -> Containered,
+    ) -> InterpolationResult<Self> {
+        let mut result = Self::default();
+        let mut at = 0;
+        let mut literal_start = 0;
+        while at < template.len() {
+            match (template[at], template.get(at + 1), template.get(at + 2)) {
+                (b'\\', Some(b'$'), Some(open)) | (b'$', Some(b'\\'), Some(open))
+                    if InterpolationKind::of(*open).is_some() =>
+                {
+                    result.append(&template[literal_start..at])?;
+                    result.append(&[b'$', *open])?;
+                    at += 3;
+                    literal_start = at;
+                }
+                (b'$', Some(open), _) if InterpolationKind::of(*open).is_some() => {
+                    let kind = InterpolationKind::of(*open).expect("checked");
+                    let end = Self::interpolation_end(template, at + 2)
+                        .ok_or(InterpolationError::Unterminated(at))?;
+                    result.append(&template[literal_start..at])?;
+                    evaluate(
+                        Interpolation {
+                            kind,
+                            expression: &template[at + 2..end],
+                            at,
+                        },
+                        &mut result,
+                    )?;
+                    at = end + 1;
+                    literal_start = at;
+                }
+                _ => at += 1,
+            }
+        }
+        result.append(&template[literal_start..])?;
+        Ok(result)
+    }
+
+    /// Offset of the bracket which closes an interpolation, given the offset just
+    /// after the opening bracket.  Expects brackets to be balanced, so it doesn't
+    /// check that `(` gets closed by `)` rather than `}`.
+    fn interpolation_end(template: &[u8], mut at: usize) -> Option<usize> {
+        let mut depth = 0usize;
+        while at < template.len() {
+            match template[at] {
+                b'(' | b'{' | b'[' => depth += 1,
+                b')' | b'}' | b']' => {
+                    if depth == 0 {
+                        return Some(at);
+                    }
+                    depth -= 1;
+                }
+                quote @ (b'\'' | b'"' | b'`') => {
+                    at += 1;
+                    while at < template.len() && template[at] != quote {
+                        if template[at] == b'\\' {
+                            at += 1;
+                        }
+                        at += 1;
+                    }
+                }
+                b'\\' => at += 1,
+                _ => {}
+            }
+            at += 1;
+        }
+        None
+    }
+}
+
+#[derive(Eq, PartialEq, Copy, Clone, Debug, Hash)]
+pub enum InterpolationKind {
+    /// `$(...)`
+    Parentheses,
+    /// `${...}`
+    Braces,
+    /// `$[...]`
+    Brackets,
+}
+
+impl InterpolationKind {
+    pub fn of(open: u8) -> Option<Self> {
+        match open {
+            b'(' => Some(InterpolationKind::Parentheses),
+            b'{' => Some(InterpolationKind::Braces),
+            b'[' => Some(InterpolationKind::Brackets),
+            _ => None,
+        }
+    }
+}
+
+/// One `$(...)`, `${...}`, or `$[...]` in a template.
+#[derive(Eq, PartialEq, Copy, Clone, Debug, Hash)]
+pub struct Interpolation<'a> {
+    pub kind: InterpolationKind,
+    /// Everything between the brackets.
+    pub expression: &'a [u8],
+    /// Byte offset of the `$` in the template.
+    pub at: usize,
+}
+
+#[derive(Eq, PartialEq, Copy, Clone, Debug, Hash)]
+pub enum InterpolationError {
+    /// Program ran out of memory.
+    OutOfMemory,
+    /// The interpolation starting at this byte offset was never closed.
+    Unterminated(usize),
+}
+
+pub type InterpolationResult<T> = Result<T, InterpolationError>;
+
+impl From<ContainerError> for InterpolationError {
+    fn from(_error: ContainerError) -> Self {
+        InterpolationError::OutOfMemory
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn evaluate_by_name(
+        interpolation: Interpolation<'_>,
+        shtick: &mut ShtickOptimized32,
+    ) -> Containered {
+        let value: &[u8] = match interpolation.expression {
+            b"name" => b"Barnabus",
+            b"important_items" => b"Fridge\nCheese\n",
+            _ => b"?",
+        };
+        shtick.insert_few(OrderedInsertFew::AtEnd(value, TypeMarker, TypeMarker))
+    }
+
+    fn evaluate_as_unknown(_: Interpolation<'_>, shtick: &mut ShtickOptimized8) -> Containered {
+        shtick.insert(OrderedInsert::AtEnd(b'?'))
+    }
+
+    #[test]
+    fn ampersand_adds_space_if_necessary() {
+        assert_eq!(
+            ShtickOptimized8::ampersand(b"123", b"456").expect("ok"),
+            "123 456"
+        );
+        assert_eq!(
+            ShtickOptimized8::ampersand(b"123\n \n", b"\n456").expect("ok"),
+            "123 456"
+        );
+        assert_eq!(
+            ShtickOptimized8::ampersand(b"123 ", b"").expect("ok"),
+            "123"
+        );
+        assert_eq!(
+            ShtickOptimized8::ampersand(b"", b"\t456").expect("ok"),
+            "456"
+        );
+        assert_eq!(ShtickOptimized8::ampersand(b" ", b" ").expect("ok"), "");
+    }
+
+    #[test]
+    fn ampersand_lines() {
+        let lines: [&[u8]; 3] = [
+            b"This is an example of a long sentence which",
+            b"deserves to be split across \"lines\".  Spaces will be",
+            b"added as necessary between lines.",
+        ];
+        assert_eq!(
+            ShtickOptimized16::from_ampersand_lines(lines).expect("ok"),
+            "This is an example of a long sentence which deserves to be split \
+            across \"lines\".  Spaces will be added as necessary between lines."
+        );
+        let single: [&[u8]; 1] = [b"This is not a 'line' \"you know\""];
+        assert_eq!(
+            ShtickOptimized16::from_ampersand_lines(single).expect("ok"),
+            "This is not a 'line' \"you know\""
+        );
+    }
+
+    #[test]
+    fn plus_lines() {
+        let lines: [&[u8]; 3] = [b"Fridge", b"Pancakes and syrup", b"Cheese"];
+        assert_eq!(
+            ShtickOptimized64::from_plus_lines(lines).expect("ok"),
+            "Fridge\nPancakes and syrup\nCheese\n"
+        );
+        let single: [&[u8]; 1] = [b"This is a 'line' \"you know\" "];
+        assert_eq!(
+            ShtickOptimized64::from_plus_lines(single).expect("ok"),
+            "This is a 'line' \"you know\" \n"
+        );
+    }
+
+    #[test]
+    fn interpolates_each_kind() {
+        let mut seen = Vec::new();
+        let shtick = ShtickOptimized32::interpolate(b"a $(x) b ${y} c $[z]!", |i, s| {
+            seen.push((i.kind, i.expression.to_vec(), i.at));
+            s.insert(OrderedInsert::AtEnd(b'_'))
+        })
+        .expect("ok");
+        assert_eq!(shtick, "a _ b _ c _!");
+        assert_eq!(
+            seen,
+            vec![
+                (InterpolationKind::Parentheses, b"x".to_vec(), 2),
+                (InterpolationKind::Braces, b"y".to_vec(), 9),
+                (InterpolationKind::Brackets, b"z".to_vec(), 16),
+            ]
+        );
+
+        let shtick = ShtickOptimized32::interpolate(
+            b"Special delivery for ${name}:\nYou will receive ${important_items} and more.",
+            evaluate_by_name,
+        )
+        .expect("ok");
+        assert_eq!(
+            shtick,
+            "Special delivery for Barnabus:\nYou will receive Fridge\nCheese\n and more."
+        );
+    }
+
+    #[test]
+    fn interpolation_escapes() {
+        let shtick = ShtickOptimized32::interpolate(
+            b"ok \\${we want this literally as} $\\[whatever] $ok $ \\$a \\n$",
+            evaluate_by_name,
+        )
+        .expect("ok");
+        assert_eq!(
+            shtick,
+            "ok ${we want this literally as} $[whatever] $ok $ \\$a \\n$"
+        );
+    }
+
+    #[test]
+    fn interpolation_skips_nested_brackets_and_quotes() {
+        let mut expressions = Vec::new();
+        let shtick = ShtickOptimized32::interpolate(
+            b"hello, ${if condition {name} else {'World$(\"!\" * 5)'}}! $(f(')', \"}\"))",
+            |i, s| {
+                expressions.push(i.expression.to_vec());
+                evaluate_by_name(i, s)
+            },
+        )
+        .expect("ok");
+        assert_eq!(shtick, "hello, ?! ?");
+        assert_eq!(
+            expressions,
+            vec![
+                b"if condition {name} else {'World$(\"!\" * 5)'}".to_vec(),
+                b"f(')', \"}\")".to_vec(),
+            ]
+        );
+    }
+
+    #[test]
+    fn interpolation_must_be_terminated() {
+        assert_eq!(
+            ShtickOptimized8::interpolate(b"ok $(name", evaluate_as_unknown).err(),
+            Some(InterpolationError::Unterminated(3))
+        );
+        assert_eq!(
+            ShtickOptimized8::interpolate(b"${a} ${{b}", evaluate_as_unknown).err(),
+            Some(InterpolationError::Unterminated(5))
+        );
+    }
+}
@@ -27,6 +27,15 @@ where
     }
 }
 
+impl<T> Default for Signed<T>
+where
+    T: SignedPrimitive,
+{
+    fn default() -> Self {
+        Self(T::ZERO)
+    }
+}
+
 impl<T> Deref for Signed<T>
 where
     T: SignedPrimitive,
@@ -134,6 +143,7 @@ mod test {
         assert_eq!(i8::MAX, 127);
         assert_eq!(Signed8::MIN, Signed8::of(-128));
         assert_eq!(Signed8::MAX, Signed8::of(127));
+        assert_eq!(Signed8::default(), Signed8::of(0));
     }
 
     #[test]
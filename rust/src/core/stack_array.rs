@@ -0,0 +1,398 @@
+use crate::core::number::*;
+
+pub use crate::core::array::*;
+
+use std::mem::MaybeUninit;
+
+pub type StackArray64<const N: usize, T> = StackArray<i64, N, T>;
+pub type StackArray32<const N: usize, T> = StackArray<i32, N, T>;
+pub type StackArray16<const N: usize, T> = StackArray<i16, N, T>;
+pub type StackArray8<const N: usize, T> = StackArray<i8, N, T>;
+
+/// Array that stores up to `N` elements inline and never allocates.
+/// Inserting past `N` elements returns `ContainerError::OutOfMemory`
+/// rather than spilling onto the heap (see `MaybeLocalArrayOptimized` if
+/// you want that).  `N` must be representable as a `Count::<S>`, otherwise
+/// creating one fails to compile:
+///
+/// ```compile_fail
+/// let array = oh::core::StackArray8::<200, u8>::default();
+/// ```
+#[repr(C)]
+pub struct StackArray<S: SignedPrimitive, const N: usize, T> {
+    buffer: [MaybeUninit<T>; N],
+    count: Count<S>,
+}
+
+/// Same layout as `stack_T_t` in `c/stack.h` (i.e., `STACK(T, C)`), so that
+/// C code can read and modify the elements in a `StackArray`.  Get one via
+/// `StackArray::with_c_stack`.  The C side must NOT `realloc` or `free` the
+/// `data` pointer, since it points into the `StackArray` itself, i.e., don't
+/// call `stack_T_p__capacity_t__success_rt_` or `stack_T_p__descope_` on it.
+#[repr(C)]
+pub struct CStack<C: PrimInt, T> {
+    pub data: *mut T,
+    pub capacity: C,
+    pub count: C,
+}
+
+impl<S: SignedPrimitive, const N: usize, T> Default for StackArray<S, N, T> {
+    fn default() -> Self {
+        let () = Self::N_FITS;
+        Self {
+            buffer: [const { MaybeUninit::uninit() }; N],
+            count: Count::<S>::default(),
+        }
+    }
+}
+
+impl<S: SignedPrimitive, const N: usize, T> StackArray<S, N, T> {
+    /// Fails to compile (when a `StackArray` is created) if `N` doesn't fit
+    /// in a `Count::<S>`, so that `capacity` can't fail.
+    const N_FITS: () = assert!(
+        (N as u128) < (1u128 << (S::BITS - 1)),
+        "N should fit in Count::<S>"
+    );
+
+    /// Always `N`.
+    #[inline]
+    pub fn capacity(&self) -> Count<S> {
+        let () = Self::N_FITS;
+        Count::<S>::of(N).expect("checked by N_FITS")
+    }
+
+    /// Looking for `fn pop`? use `remove(OrderedRemove::Last)`
+    pub fn remove(&mut self, remove: OrderedRemove) -> Option<T> {
+        match remove {
+            OrderedRemove::Last => self.remove_last(),
+        }
+    }
+
+    pub fn clear(&mut self) {
+        // We could optimize this but we do need Rust to drop each individual
+        // element (if necessary).
+        while self.remove_last().is_some() {}
+    }
+
+    /// Gives a C-compatible view of this array to `f`, e.g., to pass to generated
+    /// C code as a `stack_T_p`.  Afterwards, we take the `count` from the C stack,
+    /// so `f` can add (initialized) elements up to `capacity` or remove elements.
+    /// Panics if `f` changes `data` or `capacity`, or leaves `count > capacity`.
+    ///
+    /// # Safety
+    ///
+    /// When `f` returns, the first `count` elements of `data` must be initialized
+    /// and any elements past `count` that `f` removed must have been dropped (or
+    /// moved out), since we can't check that here; otherwise `Deref` and `Drop`
+    /// would read uninitialized (or double-drop) `T`s.
+    pub unsafe fn with_c_stack<C: PrimInt, R>(
+        &mut self,
+        f: impl FnOnce(&mut CStack<C, T>) -> R,
+    ) -> R {
+        let data = self.buffer.as_mut_ptr() as *mut T;
+        let mut c_stack = CStack {
+            data,
+            capacity: C::from(N).expect("capacity should fit in C"),
+            count: C::from(self.len()).expect("count should fit in C"),
+        };
+        let result = f(&mut c_stack);
+        assert!(
+            c_stack.data == data,
+            "C code should not reallocate a StackArray"
+        );
+        let count = c_stack
+            .count
+            .to_usize()
+            .expect("count should be non-negative");
+        assert!(
+            c_stack.capacity.to_usize() == Some(N) && count <= N,
+            "C code should not change the capacity of a StackArray"
+        );
+        self.count = Count::<S>::of(count).expect("ok");
+        result
+    }
+
+    pub(crate) fn remove_last(&mut self) -> Option<T> {
+        if !self.count.is_positive() {
+            return None;
+        }
+        let offset = self.count.to_highest_offset().to_inner().as_() as usize;
+        self.count -= S::ONE;
+        // The element at `offset` is no longer considered initialized.
+        Some(unsafe { self.buffer[offset].assume_init_read() })
+    }
+
+    pub(crate) fn insert_at_end(&mut self, value: T) -> Containered {
+        let new_count = self.count + S::ONE;
+        if new_count.is_null() || new_count > self.capacity() {
+            return ContainerError::OutOfMemory.err();
+        }
+        self.buffer[self.count.to_usize()].write(value);
+        self.count = new_count;
+        Ok(())
+    }
+}
+
+impl<S: SignedPrimitive, const N: usize, T: Default + TryClone> Array<T> for StackArray<S, N, T> {
+    fn len(&self) -> usize {
+        self.count.to_usize()
+    }
+
+    /// Looking for `fn add(t)` or `fn append(t)`?  use `insert(OrderedInsert::AtEnd(t))`:
+    fn insert(&mut self, insert: OrderedInsert<T>) -> Containered {
+        match insert {
+            OrderedInsert::AtEnd(t) => self.insert_at_end(t),
+        }
+    }
+
+    /// Looking for `fn add_all(ts)` or `fn append_all(ts)`?
+    /// use `insert_few(OrderedInsertFew::AtEnd(ts), TypeMarker, TypeMarker)`.
+    /// Inserts nothing if all `ts` wouldn't fit.
+    fn insert_few<E, Values: Few<T, Error = E>>(
+        &mut self,
+        insert: OrderedInsertFew<T, E, Values>,
+    ) -> Containered {
+        match insert {
+            OrderedInsertFew::AtEnd(mut values, ..) => {
+                let new_count = self.count
+                    + Count::of(values.size()).map_err(|_| ContainerError::OutOfMemory)?;
+                if new_count.is_null() || new_count > self.capacity() {
+                    return ContainerError::OutOfMemory.err();
+                }
+                for i in 0..values.size() {
+                    let value = values.nab(i).map_err(|_| ContainerError::Unknown)?;
+                    self.insert_at_end(value).expect("already checked capacity");
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+impl<S: SignedPrimitive, const N: usize, T> GetCount<S> for StackArray<S, N, T> {
+    fn count(&self) -> Count<S> {
+        self.count
+    }
+}
+
+impl<S: SignedPrimitive, const N: usize, T: Default> SetCount<S> for StackArray<S, N, T> {
+    type Error = ContainerError;
+
+    fn set_count(&mut self, new_count: Count<S>) -> Containered {
+        if new_count.is_null() || new_count > self.capacity() {
+            return ContainerError::OutOfMemory.err();
+        }
+        while self.count > new_count {
+            _ = self.remove_last();
+        }
+        while self.count < new_count {
+            self.insert_at_end(Default::default())
+                .expect("already checked capacity");
+        }
+        Ok(())
+    }
+}
+
+impl<S: SignedPrimitive, const N: usize, T> std::ops::Deref for StackArray<S, N, T> {
+    type Target = [T];
+
+    fn deref(&self) -> &[T] {
+        let count = self.count.to_usize();
+        // Elements are initialized up to `count`.
+        unsafe { std::slice::from_raw_parts(self.buffer.as_ptr() as *const T, count) }
+    }
+}
+
+impl<S: SignedPrimitive, const N: usize, T> std::ops::DerefMut for StackArray<S, N, T> {
+    fn deref_mut(&mut self) -> &mut [T] {
+        let count = self.count.to_usize();
+        // Elements are initialized up to `count`.
+        unsafe { std::slice::from_raw_parts_mut(self.buffer.as_mut_ptr() as *mut T, count) }
+    }
+}
+
+impl<S: SignedPrimitive, const N: usize, T: std::cmp::PartialEq> PartialEq<Self>
+    for StackArray<S, N, T>
+{
+    fn eq(&self, other: &Self) -> bool {
+        self[..] == other[..]
+    }
+}
+
+impl<S: SignedPrimitive, const N: usize, T: std::cmp::Eq> Eq for StackArray<S, N, T> {}
+
+impl<S: SignedPrimitive, const N: usize, T: TryClone> TryClone for StackArray<S, N, T> {
+    // TODO: this should probably be one_of(ContainerError, <T as TryClone>::Error)
+    type Error = ContainerError;
+
+    fn try_clone(&self) -> Result<Self, ContainerError> {
+        let mut result = Self::default();
+        for value in self.iter() {
+            let clone = value.try_clone().map_err(|_| ContainerError::Unknown)?;
+            result
+                .insert_at_end(clone)
+                .expect("already at necessary capacity");
+        }
+        Ok(result)
+    }
+}
+
+impl<S: SignedPrimitive, const N: usize, T: std::fmt::Debug> std::fmt::Debug
+    for StackArray<S, N, T>
+{
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "StackArray{}::<{}, _>::from([", S::BITS, N)?;
+        for value in self.iter() {
+            write!(f, "{:?}, ", value)?;
+        }
+        write!(f, "])")
+    }
+}
+
+impl<S: SignedPrimitive, const N: usize, T> Drop for StackArray<S, N, T> {
+    fn drop(&mut self) {
+        self.clear();
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::core::testing::*;
+
+    #[test]
+    fn stack_array_is_inline() {
+        assert_eq!(std::mem::size_of::<StackArray8<15, u8>>(), 16);
+        assert_eq!(std::mem::size_of::<StackArray32<3, u32>>(), 16);
+        assert_eq!(std::mem::size_of::<StackArray64<2, u64>>(), 24);
+    }
+
+    #[test]
+    fn insert_until_full() {
+        let mut array = StackArray16::<3, TestingNoisy>::default();
+        assert_eq!(array.capacity(), Count::of(3).expect("ok"));
+        for i in 1..=3 {
+            array
+                .insert(OrderedInsert::AtEnd(TestingNoisy::new(i)))
+                .expect("has capacity");
+        }
+        assert_eq!(
+            array.insert(OrderedInsert::AtEnd(TestingNoisy::new(4))),
+            Err(ContainerError::OutOfMemory)
+        );
+        assert_eq!(array.count(), Count::of(3).expect("ok"));
+        // No allocations, and the rejected element gets dropped.
+        testing_unprint(vec![
+            Vec::from(b"noisy_new(1)"),
+            Vec::from(b"noisy_new(2)"),
+            Vec::from(b"noisy_new(3)"),
+            Vec::from(b"noisy_new(4)"),
+            Vec::from(b"noisy_drop(4)"),
+        ]);
+
+        assert_eq!(
+            array.remove(OrderedRemove::Last).map(|n| n.value()),
+            Some(3)
+        );
+        testing_unprint(vec![Vec::from(b"noisy_drop(3)")]);
+    }
+
+    #[test]
+    fn insert_few_is_all_or_nothing() {
+        let mut array = StackArray8::<4, u8>::default();
+        array
+            .insert_few(OrderedInsertFew::AtEnd(
+                &[1, 2, 3][..],
+                TypeMarker,
+                TypeMarker,
+            ))
+            .expect("ok");
+        assert_eq!(
+            array.insert_few(OrderedInsertFew::AtEnd(&[4, 5][..], TypeMarker, TypeMarker)),
+            Err(ContainerError::OutOfMemory)
+        );
+        assert_eq!(&array[..], &[1, 2, 3]);
+        array[0] = 10;
+        assert_eq!(&array[..], &[10, 2, 3]);
+    }
+
+    #[test]
+    fn set_count_respects_capacity() {
+        let mut array = StackArray32::<5, TestingNoisy>::default();
+        array.set_count(Count::of(2).expect("ok")).expect("ok");
+        assert_eq!(
+            array.set_count(Count::of(6).expect("ok")),
+            Err(ContainerError::OutOfMemory)
+        );
+        array.set_count(Count::of(1).expect("ok")).expect("ok");
+        testing_unprint(vec![
+            Vec::from(b"noisy_new(256)"),
+            Vec::from(b"noisy_new(256)"),
+            Vec::from(b"noisy_drop(256)"),
+        ]);
+    }
+
+    #[test]
+    fn drop_drops_elements() {
+        {
+            let mut array = StackArray64::<4, TestingNoisy>::default();
+            array
+                .insert(OrderedInsert::AtEnd(TestingNoisy::new(7)))
+                .expect("ok");
+            array
+                .insert(OrderedInsert::AtEnd(TestingNoisy::new(8)))
+                .expect("ok");
+            let clone = array.try_clone().expect("ok");
+            assert_eq!(clone, array);
+            testing_unprint(vec![
+                Vec::from(b"noisy_new(7)"),
+                Vec::from(b"noisy_new(8)"),
+                Vec::from(b"noisy_clone(7)"),
+                Vec::from(b"noisy_clone(8)"),
+            ]);
+        }
+        testing_unprint(vec![
+            Vec::from(b"noisy_drop(8)"),
+            Vec::from(b"noisy_drop(7)"),
+            Vec::from(b"noisy_drop(8)"),
+            Vec::from(b"noisy_drop(7)"),
+        ]);
+    }
+
+    #[test]
+    fn c_stack_has_stack_h_layout() {
+        assert_eq!(std::mem::size_of::<CStack<u32, u8>>(), 16);
+        assert_eq!(std::mem::offset_of!(CStack<u32, u8>, data), 0);
+        assert_eq!(std::mem::offset_of!(CStack<u32, u8>, capacity), 8);
+        assert_eq!(std::mem::offset_of!(CStack<u32, u8>, count), 12);
+        assert_eq!(std::mem::offset_of!(CStack<u64, u8>, count), 16);
+    }
+
+    #[test]
+    fn c_stack_shares_elements() {
+        let mut array = StackArray8::<4, u32>::default();
+        array.insert(OrderedInsert::AtEnd(5)).expect("ok");
+        // Safety: we initialize the element we add.
+        let capacity = unsafe {
+            array.with_c_stack(|c_stack: &mut CStack<u32, u32>| {
+                // Emulate `stack_u32_p__append_default_` followed by an assignment.
+                *c_stack.data.add(c_stack.count as usize) = 6;
+                c_stack.count += 1;
+                c_stack.capacity
+            })
+        };
+        assert_eq!(capacity, 4);
+        assert_eq!(&array[..], &[5, 6]);
+    }
+
+    #[test]
+    #[should_panic]
+    fn c_stack_cannot_change_capacity() {
+        let mut array = StackArray8::<4, u32>::default();
+        unsafe {
+            array.with_c_stack(|c_stack: &mut CStack<u64, u32>| {
+                c_stack.capacity = 8;
+            });
+        }
+    }
+}
@@ -0,0 +1,349 @@
+/// Declares a struct-of-arrays table, which stores each field of a row in its
+/// own column (an `AllocationCount<S, T>`), e.g., for cache-friendly AST/token
+/// tables where most passes only look at one or two fields.  All columns share
+/// one `Count<S>`, so they're always in lock-step and indices stay compact.
+///
+/// ```
+/// oh::struct_of_arrays! {
+///     /// Tokens in a file.
+///     pub struct TokenTable(TokenRow, TokenRowRef, TokenColumnsMut) {
+///         kind: u8,
+///         start: u32,
+///     }
+/// }
+///
+/// use oh::core::*;
+/// let mut tokens = TokenTable::<i32>::default();
+/// tokens
+///     .insert(OrderedInsert::AtEnd(TokenRow { kind: 3, start: 100 }))
+///     .expect("ok");
+/// assert_eq!(tokens.start(), &[100]);
+/// assert_eq!(*tokens.get(0).expect("ok").kind, 3);
+/// tokens.columns_mut().kind[0] = 4;
+/// assert_eq!(tokens.kind(), &[4]);
+/// ```
+///
+/// This declares `TokenRow` with owned fields (for inserting and removing rows),
+/// `TokenRowRef<'a>` with references to each field (for `get`), and
+/// `TokenColumnsMut<'a>` with mutable slices for each column (for `columns_mut`).
+/// Each column also gets a getter for its slice, so avoid field names which
+/// conflict with the table's methods (e.g., `count` or `len`).
+#[macro_export]
+macro_rules! struct_of_arrays {
+    (
+        $(#[$meta:meta])*
+        $vis:vis struct $table:ident($row:ident, $row_ref:ident, $columns_mut:ident) {
+            $($(#[$field_meta:meta])* $field:ident: $type:ty),+ $(,)?
+        }
+    ) => {
+        #[doc = concat!("One row of a `", stringify!($table), "`.")]
+        $vis struct $row {
+            $($(#[$field_meta])* pub $field: $type,)+
+        }
+
+        #[doc = concat!("References to one row of a `", stringify!($table), "`.")]
+        #[allow(dead_code)]
+        $vis struct $row_ref<'a> {
+            $($(#[$field_meta])* pub $field: &'a $type,)+
+        }
+
+        #[doc = concat!("Mutable slices of each column of a `", stringify!($table), "`.")]
+        #[allow(dead_code)]
+        $vis struct $columns_mut<'a> {
+            $($(#[$field_meta])* pub $field: &'a mut [$type],)+
+        }
+
+        $(#[$meta])*
+        #[repr(C, align(8))]
+        $vis struct $table<S: $crate::core::SignedPrimitive> {
+            $($field: $crate::core::AllocationCount<S, $type>,)+
+            count: $crate::core::Count<S>,
+        }
+
+        impl<S: $crate::core::SignedPrimitive> Default for $table<S> {
+            fn default() -> Self {
+                Self {
+                    $($field: Default::default(),)+
+                    count: Default::default(),
+                }
+            }
+        }
+
+        #[allow(dead_code)]
+        impl<S: $crate::core::SignedPrimitive> $table<S> {
+            pub fn len(&self) -> usize {
+                self.count.to_usize()
+            }
+
+            pub fn is_empty(&self) -> bool {
+                !self.count.is_positive()
+            }
+
+            /// Number of rows we can hold before reallocating.
+            pub fn capacity(&self) -> $crate::core::Count<S> {
+                // All columns have the same capacity; see `set_capacity`.
+                let capacities = [$(self.$field.capacity()),+];
+                debug_assert!(capacities.iter().all(|capacity| *capacity == capacities[0]));
+                capacities[0]
+            }
+
+            /// Will reallocate every column to exactly this capacity.
+            /// Will delete rows if `new_capacity < self.count()`.
+            /// If we run out of memory, every column keeps its old capacity.
+            pub fn set_capacity(
+                &mut self,
+                new_capacity: $crate::core::Count<S>,
+            ) -> $crate::core::Containered {
+                while self.count > new_capacity {
+                    // Could happen if new_capacity < 0
+                    if self.remove_last().is_none() {
+                        break;
+                    }
+                }
+                if !new_capacity.is_positive() {
+                    $(
+                        self.$field
+                            .set_capacity($crate::core::Count::<S>::default())
+                            .expect("freeing should not fail");
+                    )+
+                    return Ok(());
+                } else if new_capacity == self.capacity() {
+                    return Ok(());
+                }
+                // Allocate every column before moving any rows over, so that
+                // we can back out without touching the existing columns.
+                $(let mut $field = $crate::core::AllocationCount::<S, $type>::default();)+
+                let allocated = (|| -> $crate::core::Containered {
+                    $($field.set_capacity(new_capacity)?;)+
+                    Ok(())
+                })();
+                if let Err(error) = allocated {
+                    $(
+                        $field
+                            .set_capacity($crate::core::Count::<S>::default())
+                            .expect("freeing should not fail");
+                    )+
+                    return Err(error);
+                }
+                $(self.$field.replace_with($field, self.count);)+
+                Ok(())
+            }
+
+            /// Looking for `fn add(row)` or `fn append(row)`?  use `insert(OrderedInsert::AtEnd(row))`:
+            pub fn insert(
+                &mut self,
+                insert: $crate::core::OrderedInsert<$row>,
+            ) -> $crate::core::Containered {
+                match insert {
+                    $crate::core::OrderedInsert::AtEnd(row) => self.insert_at_end(row),
+                }
+            }
+
+            /// Looking for `fn pop`? use `remove(OrderedRemove::Last)`
+            pub fn remove(&mut self, remove: $crate::core::OrderedRemove) -> Option<$row> {
+                match remove {
+                    $crate::core::OrderedRemove::Last => self.remove_last(),
+                }
+            }
+
+            pub fn get(&self, at: usize) -> Option<$row_ref<'_>> {
+                if at >= self.len() {
+                    return None;
+                }
+                Some($row_ref {
+                    $($field: &self.$field[at],)+
+                })
+            }
+
+            pub fn columns_mut(&mut self) -> $columns_mut<'_> {
+                let count = self.count.to_usize();
+                $columns_mut {
+                    $($field: &mut self.$field[0..count],)+
+                }
+            }
+
+            $(
+                pub fn $field(&self) -> &[$type] {
+                    &self.$field[0..self.count.to_usize()]
+                }
+            )+
+
+            pub fn clear(&mut self, options: $crate::core::Clear) {
+                match options {
+                    $crate::core::Clear::KeepingCapacity => {
+                        // We need Rust to drop each individual element (if necessary).
+                        while self.remove_last().is_some() {}
+                    }
+                    $crate::core::Clear::DroppingCapacity => self
+                        .set_capacity($crate::core::Count::<S>::default())
+                        .expect("clearing should not alloc"),
+                }
+            }
+
+            fn insert_at_end(&mut self, row: $row) -> $crate::core::Containered {
+                let new_count = self.count + <S as $crate::core::SignedPrimitive>::ONE;
+                if new_count.is_null() {
+                    return $crate::core::ContainerError::OutOfMemory.err();
+                }
+                if new_count > self.capacity() {
+                    let desired_capacity = self
+                        .capacity()
+                        .double_or_at_least(new_count)
+                        .map_err(|_| $crate::core::ContainerError::OutOfMemory)?;
+                    self.set_capacity(desired_capacity)?;
+                }
+                let offset = new_count.to_highest_offset();
+                let $row { $($field),+ } = row;
+                $(
+                    self.$field
+                        .write_initializing(offset, $field)
+                        .expect("should be in bounds");
+                )+
+                self.count = new_count;
+                Ok(())
+            }
+
+            fn remove_last(&mut self) -> Option<$row> {
+                if !self.count.is_positive() {
+                    return None;
+                }
+                let offset = self.count.to_highest_offset();
+                let row = $row {
+                    $(
+                        $field: self
+                            .$field
+                            .read_destructively(offset)
+                            .expect("should be in bounds"),
+                    )+
+                };
+                self.count -= <S as $crate::core::SignedPrimitive>::ONE;
+                Some(row)
+            }
+        }
+
+        impl<S: $crate::core::SignedPrimitive> $crate::core::GetCount<S> for $table<S> {
+            fn count(&self) -> $crate::core::Count<S> {
+                self.count
+            }
+        }
+
+        impl<S: $crate::core::SignedPrimitive> Drop for $table<S> {
+            fn drop(&mut self) {
+                self.clear($crate::core::Clear::DroppingCapacity);
+            }
+        }
+    };
+}
+
+#[cfg(test)]
+mod test {
+    use crate::core::*;
+
+    crate::struct_of_arrays! {
+        /// Something like a parser node.
+        struct NoisyTable(NoisyRow, NoisyRowRef, NoisyColumnsMut) {
+            kind: u8,
+            /// Noisy so we can check drops.
+            value: TestingNoisy,
+        }
+    }
+
+    fn row(kind: u8, value: i32) -> NoisyRow {
+        NoisyRow {
+            kind,
+            value: TestingNoisy::new(value),
+        }
+    }
+
+    #[test]
+    fn table_is_compact() {
+        assert_eq!(std::mem::size_of::<NoisyTable<i8>>(), 24);
+        assert_eq!(std::mem::size_of::<NoisyTable<i32>>(), 32);
+    }
+
+    #[test]
+    fn columns_grow_in_lock_step() {
+        let mut table = NoisyTable::<i16>::default();
+        table.insert(OrderedInsert::AtEnd(row(1, 10))).expect("ok");
+        table.insert(OrderedInsert::AtEnd(row(2, 20))).expect("ok");
+        testing_unprint(vec![
+            Vec::from(b"noisy_new(10)"),
+            Vec::from(b"create(A: 1)"),
+            Vec::from(b"create(B: 1)"),
+            Vec::from(b"noisy_new(20)"),
+            // All columns are allocated before any are moved over.
+            Vec::from(b"create(C: 2)"),
+            Vec::from(b"create(D: 2)"),
+            Vec::from(b"delete(A)"),
+            Vec::from(b"delete(B)"),
+        ]);
+        assert_eq!(table.count(), Count::of(2).expect("ok"));
+        assert_eq!(table.capacity(), Count::of(2).expect("ok"));
+        assert_eq!(table.kind(), &[1, 2]);
+        assert_eq!(
+            table.value().iter().map(|n| n.value()).collect::<Vec<_>>(),
+            vec![10, 20]
+        );
+    }
+
+    #[test]
+    fn set_capacity_is_all_or_nothing() {
+        let mut table = NoisyTable::<i32>::default();
+        table.insert(OrderedInsert::AtEnd(row(3, 30))).expect("ok");
+        _ = testing_prints();
+
+        // Allocating the `kind` column works but the `value` column doesn't.
+        testing_fail_allocation_after(1);
+        assert_eq!(
+            table.set_capacity(Count::of(5).expect("ok")),
+            Err(ContainerError::OutOfMemory)
+        );
+        crate::testing_unprint!["create(C: 5)", "delete(C)"];
+        assert_eq!(table.capacity(), Count::of(1).expect("ok"));
+        assert_eq!(table.kind(), &[3]);
+        assert_eq!(table.value()[0].value(), 30);
+
+        table.set_capacity(Count::of(5).expect("ok")).expect("ok");
+        assert_eq!(table.capacity(), Count::of(5).expect("ok"));
+        assert_eq!(table.kind(), &[3]);
+    }
+
+    #[test]
+    fn get_and_columns_mut() {
+        let mut table = NoisyTable::<i32>::default();
+        table.insert(OrderedInsert::AtEnd(row(5, 50))).expect("ok");
+        table.insert(OrderedInsert::AtEnd(row(6, 60))).expect("ok");
+
+        let second = table.get(1).expect("ok");
+        assert_eq!(*second.kind, 6);
+        assert_eq!(second.value.value(), 60);
+        assert!(table.get(2).is_none());
+
+        let columns = table.columns_mut();
+        columns.kind[0] = 7;
+        columns.kind[1] = columns.value[0].value() as u8;
+        assert_eq!(table.kind(), &[7, 50]);
+    }
+
+    #[test]
+    fn remove_and_drop_rows() {
+        {
+            let mut table = NoisyTable::<i64>::default();
+            table.set_capacity(Count::of(3).expect("ok")).expect("ok");
+            table.insert(OrderedInsert::AtEnd(row(1, 1))).expect("ok");
+            table.insert(OrderedInsert::AtEnd(row(2, 2))).expect("ok");
+            table.insert(OrderedInsert::AtEnd(row(3, 3))).expect("ok");
+            let removed = table.remove(OrderedRemove::Last).expect("ok");
+            assert_eq!(removed.kind, 3);
+            assert_eq!(removed.value.value(), 3);
+            _ = testing_prints();
+        }
+        testing_unprint(vec![
+            Vec::from(b"noisy_drop(3)"), // `removed`
+            Vec::from(b"noisy_drop(2)"),
+            Vec::from(b"noisy_drop(1)"),
+            Vec::from(b"delete(A)"),
+            Vec::from(b"delete(B)"),
+        ]);
+    }
+}
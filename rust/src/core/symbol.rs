@@ -0,0 +1,373 @@
+use crate::core::non_local_array::*;
+
+pub use crate::core::array::*;
+
+use std::cell::RefCell;
+
+/// Compact id for an interned identifier; comparing two symbols is a single
+/// integer comparison rather than comparing bytes.  Symbols are only meaningful
+/// for the `SymbolTable` which created them, and they're stable for the life of
+/// that table (e.g., a compilation), since we never remove symbols.
+#[derive(Eq, PartialEq, Ord, PartialOrd, Copy, Clone, Debug, Hash)]
+pub struct Symbol(i32);
+
+impl Symbol {
+    pub fn to_usize(self) -> usize {
+        self.0 as usize
+    }
+
+    /// Interns `bytes` in this thread's `SymbolTable`; see `SymbolTable::with_thread_local`.
+    pub fn intern(bytes: &[u8]) -> ContainerResult<Self> {
+        SymbolTable::with_thread_local(|table| table.intern(bytes))
+    }
+
+    /// Resolves this symbol in this thread's `SymbolTable`.  Panics if this
+    /// symbol didn't come from the thread-local table.
+    pub fn with_bytes<U>(self, f: impl FnOnce(&[u8]) -> U) -> U {
+        SymbolTable::with_thread_local(|table| {
+            f(table
+                .resolve(self)
+                .expect("should be a thread-local symbol"))
+        })
+    }
+}
+
+impl From<Keyword> for Symbol {
+    /// Keywords are pre-seeded in order, so their symbols are known ahead of time.
+    fn from(keyword: Keyword) -> Self {
+        Symbol(keyword as i32)
+    }
+}
+
+/// Reserved keywords in oh, see `wishlist.md`.
+#[derive(Eq, PartialEq, Copy, Clone, Debug, Hash)]
+pub enum Keyword {
+    Also,
+    If,
+    Elif,
+    Else,
+    With,
+    Return,
+    Break,
+    Continue,
+    What,
+    In,
+    Each,
+    For,
+    While,
+    Pass,
+    Where,
+    When,
+    Is,
+    Has,
+}
+
+impl Keyword {
+    pub const ALL: [Keyword; 18] = [
+        Keyword::Also,
+        Keyword::If,
+        Keyword::Elif,
+        Keyword::Else,
+        Keyword::With,
+        Keyword::Return,
+        Keyword::Break,
+        Keyword::Continue,
+        Keyword::What,
+        Keyword::In,
+        Keyword::Each,
+        Keyword::For,
+        Keyword::While,
+        Keyword::Pass,
+        Keyword::Where,
+        Keyword::When,
+        Keyword::Is,
+        Keyword::Has,
+    ];
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Keyword::Also => "also",
+            Keyword::If => "if",
+            Keyword::Elif => "elif",
+            Keyword::Else => "else",
+            Keyword::With => "with",
+            Keyword::Return => "return",
+            Keyword::Break => "break",
+            Keyword::Continue => "continue",
+            Keyword::What => "what",
+            Keyword::In => "in",
+            Keyword::Each => "each",
+            Keyword::For => "for",
+            Keyword::While => "while",
+            Keyword::Pass => "pass",
+            Keyword::Where => "where",
+            Keyword::When => "when",
+            Keyword::Is => "is",
+            Keyword::Has => "has",
+        }
+    }
+}
+
+crate::struct_of_arrays! {
+    /// Per-symbol data, indexed by `Symbol`.
+    struct SymbolColumns(SymbolRow, SymbolRowRef, SymbolColumnsMut) {
+        /// Offset just past this symbol's bytes in `SymbolTable::bytes`;
+        /// the symbol starts at the previous symbol's end.
+        end: i32,
+        /// So we don't need to rehash bytes when growing `slots`.
+        hash: u32,
+    }
+}
+
+/// Interns identifiers as `Symbol`s.  Use a table directly (explicit-context mode),
+/// or use `Symbol::intern` and `Symbol::with_bytes` for a table per thread
+/// (thread-local mode), which is pre-seeded with keywords.
+#[derive(Default)]
+pub struct SymbolTable {
+    /// Bytes of every symbol, back to back.
+    bytes: NonLocalArrayCount32<u8>,
+    symbols: SymbolColumns<i32>,
+    /// Open-addressed hash table (with linear probing) into `symbols`.
+    /// Its count is zero or a power of two, and it's at most half full.
+    slots: NonLocalArrayCount32<Option<Symbol>>,
+}
+
+thread_local! {
+    static THREAD_LOCAL_SYMBOL_TABLE: RefCell<SymbolTable> =
+        RefCell::new(SymbolTable::with_keywords().expect("should have memory for keywords"));
+}
+
+impl SymbolTable {
+    /// Returns a table where `Symbol::from(keyword)` matches `intern(keyword.as_str())`.
+    pub fn with_keywords() -> ContainerResult<Self> {
+        let mut table = Self::default();
+        for keyword in Keyword::ALL {
+            let symbol = table.intern(keyword.as_str().as_bytes())?;
+            debug_assert_eq!(symbol, Symbol::from(keyword));
+        }
+        Ok(table)
+    }
+
+    /// Runs `f` with this thread's table, which starts out `with_keywords`.
+    /// Don't nest calls (e.g., via `Symbol::intern` inside `f`), since the
+    /// table is already borrowed.
+    pub fn with_thread_local<U>(f: impl FnOnce(&mut SymbolTable) -> U) -> U {
+        THREAD_LOCAL_SYMBOL_TABLE.with(|table| f(&mut table.borrow_mut()))
+    }
+
+    pub fn len(&self) -> usize {
+        self.symbols.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.symbols.is_empty()
+    }
+
+    /// Returns the existing symbol for `bytes`, or adds a new one.
+    pub fn intern(&mut self, bytes: &[u8]) -> ContainerResult<Symbol> {
+        let hash = Self::hash(bytes);
+        if let Some(symbol) = self.find(bytes, hash) {
+            return Ok(symbol);
+        }
+        if (self.len() + 1) * 2 > self.slots.len() {
+            self.grow_slots()?;
+        }
+        let end = self
+            .bytes
+            .len()
+            .checked_add(bytes.len())
+            .and_then(|end| i32::try_from(end).ok())
+            .ok_or(ContainerError::OutOfMemory)?;
+        let symbol = Symbol(i32::try_from(self.len()).map_err(|_| ContainerError::OutOfMemory)?);
+        self.bytes
+            .insert_few(OrderedInsertFew::AtEnd(bytes, TypeMarker, TypeMarker))?;
+        if let Err(error) = self
+            .symbols
+            .insert(OrderedInsert::AtEnd(SymbolRow { end, hash }))
+        {
+            // Keep `bytes` in sync with `symbols`.
+            for _ in 0..bytes.len() {
+                self.bytes.remove(OrderedRemove::Last);
+            }
+            return Err(error);
+        }
+        let slot = self.empty_slot(hash);
+        self.slots[slot] = Some(symbol);
+        Ok(symbol)
+    }
+
+    /// Returns the keyword that `symbol` stands for, if any.  Only tables made
+    /// `with_keywords` put keywords at the symbols `Symbol::from(keyword)`,
+    /// so we check the bytes rather than trusting the index.
+    pub fn keyword(&self, symbol: Symbol) -> Option<Keyword> {
+        let keyword = *Keyword::ALL.get(symbol.to_usize())?;
+        if self.resolve(symbol)? == keyword.as_str().as_bytes() {
+            Some(keyword)
+        } else {
+            None
+        }
+    }
+
+    /// Returns the symbol for `bytes` if it has been interned.
+    pub fn lookup(&self, bytes: &[u8]) -> Option<Symbol> {
+        self.find(bytes, Self::hash(bytes))
+    }
+
+    /// Returns the bytes for `symbol`, or `None` if it's not from this table.
+    pub fn resolve(&self, symbol: Symbol) -> Option<&[u8]> {
+        let index = symbol.to_usize();
+        let ends = self.symbols.end();
+        let end = *ends.get(index)? as usize;
+        let start = if index == 0 {
+            0
+        } else {
+            ends[index - 1] as usize
+        };
+        Some(&self.bytes[start..end])
+    }
+
+    fn find(&self, bytes: &[u8], hash: u32) -> Option<Symbol> {
+        if self.slots.is_empty() {
+            return None;
+        }
+        let mask = self.slots.len() - 1;
+        let mut slot = hash as usize & mask;
+        // We're never full, so we'll always hit an empty slot eventually.
+        while let Some(symbol) = self.slots[slot] {
+            if self.symbols.hash()[symbol.to_usize()] == hash && self.resolve(symbol) == Some(bytes)
+            {
+                return Some(symbol);
+            }
+            slot = (slot + 1) & mask;
+        }
+        None
+    }
+
+    fn empty_slot(&self, hash: u32) -> usize {
+        let mask = self.slots.len() - 1;
+        let mut slot = hash as usize & mask;
+        while self.slots[slot].is_some() {
+            slot = (slot + 1) & mask;
+        }
+        slot
+    }
+
+    fn grow_slots(&mut self) -> Containered {
+        let new_count = (self.slots.len() * 2).max(16);
+        let mut slots = NonLocalArrayCount32::<Option<Symbol>>::default();
+        slots.set_count(Count::of(new_count).map_err(|_| ContainerError::OutOfMemory)?)?;
+        self.slots = slots;
+        for (index, hash) in self.symbols.hash().iter().enumerate() {
+            let slot = self.empty_slot(*hash);
+            self.slots[slot] = Some(Symbol(index as i32));
+        }
+        Ok(())
+    }
+
+    /// FNV-1a, which is simple and good enough for short identifiers.
+    fn hash(bytes: &[u8]) -> u32 {
+        let mut hash: u32 = 0x811c_9dc5;
+        for byte in bytes {
+            hash ^= *byte as u32;
+            hash = hash.wrapping_mul(0x0100_0193);
+        }
+        hash
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::core::shtick::*;
+
+    #[test]
+    fn intern_lookup_and_resolve() {
+        let mut table = SymbolTable::default();
+        assert!(table.is_empty());
+        assert_eq!(table.lookup(b"x"), None);
+
+        let x = table.intern(b"x").expect("ok");
+        let long = table.intern(b"some_long_identifier").expect("ok");
+        let empty = table.intern(b"").expect("ok");
+        assert_eq!(table.intern(b"x").expect("ok"), x);
+        assert_eq!(table.lookup(b"some_long_identifier"), Some(long));
+        assert_eq!(table.len(), 3);
+        assert_ne!(x, long);
+
+        assert_eq!(table.resolve(x), Some(&b"x"[..]));
+        assert_eq!(table.resolve(long), Some(&b"some_long_identifier"[..]));
+        assert_eq!(table.resolve(empty), Some(&b""[..]));
+        assert_eq!(table.resolve(Symbol(3)), None);
+    }
+
+    #[test]
+    fn interns_shticks() {
+        let mut table = SymbolTable::default();
+        let shtick = ShtickOptimized8::try_from("name").expect("ok");
+        let symbol = table.intern(&shtick).expect("ok");
+        assert_eq!(table.lookup(b"name"), Some(symbol));
+        assert_eq!(table.resolve(symbol), Some(&shtick[..]));
+    }
+
+    #[test]
+    fn symbols_are_stable_while_growing() {
+        let mut table = SymbolTable::default();
+        let names: Vec<Vec<u8>> = (0..1000)
+            .map(|i| format!("name_{}", i).into_bytes())
+            .collect();
+        let symbols: Vec<Symbol> = names
+            .iter()
+            .map(|name| table.intern(name).expect("ok"))
+            .collect();
+        for (i, symbol) in symbols.iter().enumerate() {
+            assert_eq!(symbol.to_usize(), i);
+            assert_eq!(table.lookup(&names[i]), Some(*symbol));
+            assert_eq!(table.resolve(*symbol), Some(&names[i][..]));
+        }
+        assert_eq!(table.len(), 1000);
+    }
+
+    #[test]
+    fn keywords_are_pre_seeded() {
+        let mut table = SymbolTable::with_keywords().expect("ok");
+        assert_eq!(table.len(), Keyword::ALL.len());
+        for keyword in Keyword::ALL {
+            let symbol = Symbol::from(keyword);
+            assert_eq!(table.lookup(keyword.as_str().as_bytes()), Some(symbol));
+            assert_eq!(table.keyword(symbol), Some(keyword));
+        }
+        assert_eq!(table.intern(b"while").expect("ok"), Keyword::While.into());
+        let not_keyword = table.intern(b"whilst").expect("ok");
+        assert_eq!(table.keyword(not_keyword), None);
+    }
+
+    #[test]
+    fn keywords_need_a_seeded_table() {
+        let mut table = SymbolTable::default();
+        let symbol = table.intern(b"x").expect("ok");
+        assert_eq!(symbol, Symbol::from(Keyword::Also));
+        assert_eq!(table.keyword(symbol), None);
+        // Even if the keyword gets interned later, it's not at the keyword's symbol.
+        let also = table.intern(b"also").expect("ok");
+        assert_eq!(table.keyword(also), None);
+        assert_eq!(table.keyword(Symbol::from(Keyword::If)), None);
+    }
+
+    #[test]
+    fn thread_local_symbols() {
+        let symbol = Symbol::intern(b"thread_local_name").expect("ok");
+        assert_eq!(Symbol::intern(b"thread_local_name").expect("ok"), symbol);
+        assert_eq!(
+            symbol.with_bytes(|bytes| bytes.to_vec()),
+            b"thread_local_name"
+        );
+        assert_eq!(Symbol::intern(b"elif").expect("ok"), Keyword::Elif.into());
+
+        let other_thread = std::thread::spawn(|| {
+            SymbolTable::with_thread_local(|table| table.lookup(b"thread_local_name"))
+        })
+        .join()
+        .expect("ok");
+        assert_eq!(other_thread, None);
+    }
+}
@@ -1,4 +1,13 @@
 #![allow(dead_code)]
+//! Hooks for observing allocations, drops, etc. in tests, e.g., `create(A: 39)`
+//! and `delete(A)`.  These are on for unit tests, and for anything else (like
+//! the integration tests in `tests/`) with the `testing` feature; otherwise
+//! the hooks are no-ops, and the functions that read prints don't exist.
+//! Pointer names are per-thread, so that tests running in parallel each start
+//! at `A`.  A pointer freed on a different thread than the one which named it
+//! prints `delete(?)`; only unit tests panic on that (or on naming a pointer
+//! twice), since with the feature it may happen while dropping, in any code.
+//! Prints are kept until read, up to `TESTING_PRINTS_LIMIT` per thread.
 use crate::core::count::*;
 use crate::core::moot::*;
 use crate::core::signed::*;
@@ -57,6 +66,30 @@ struct TestingData {
     prints: Vec<Vec<u8>>,
     pointer_names: HashMap<usize, u64>,
     next_pointer_name_index: u64,
+    allocations_until_failure: Option<u64>,
+}
+
+/// Past this many unread prints, the oldest half are dropped, so that code that
+/// never reads its prints (e.g., with the `testing` feature on) doesn't grow forever.
+pub const TESTING_PRINTS_LIMIT: usize = 1 << 16;
+
+impl TestingData {
+    fn push_print(&mut self, print: Vec<u8>) {
+        if self.prints.len() >= TESTING_PRINTS_LIMIT {
+            self.prints.drain(..TESTING_PRINTS_LIMIT / 2);
+        }
+        self.prints.push(print);
+    }
+}
+
+/// Unit tests should never misuse pointer names, but with the `testing` feature
+/// a pointer can e.g. be freed on a different thread than the one which named it.
+/// Don't panic for that, since we may be inside a `Drop` (and would abort).
+#[cfg(any(test, feature = "testing"))]
+fn testing_pointer_name_mismatch(message: std::fmt::Arguments<'_>) {
+    if cfg!(test) {
+        panic!("{}", message);
+    }
 }
 
 impl Default for TestingData {
@@ -65,33 +98,66 @@ impl Default for TestingData {
             prints: Default::default(),
             pointer_names: Default::default(),
             next_pointer_name_index: 0,
+            allocations_until_failure: None,
         }
     }
 }
 
-#[cfg(test)]
+#[cfg(any(test, feature = "testing"))]
 #[inline]
 pub fn testing_print(bytes: &[u8]) {
     TESTING_DATA.with_borrow_mut(|t| {
-        t.prints.push(Vec::from(bytes));
+        t.push_print(Vec::from(bytes));
     });
 }
 
-#[cfg(not(test))]
+#[cfg(not(any(test, feature = "testing")))]
 #[inline]
 pub fn testing_print(_bytes: &[u8]) {}
 
-#[cfg(test)]
+#[cfg(any(test, feature = "testing"))]
 #[inline]
 pub fn testing_print_string(string: String) {
     testing_print(<String as AsRef<[u8]>>::as_ref(&string));
 }
 
-#[cfg(not(test))]
+#[cfg(not(any(test, feature = "testing")))]
 #[inline]
 pub fn testing_print_string(_string: String) {}
 
-#[cfg(test)]
+/// Makes an allocation on this thread fail after `count` more allocations succeed,
+/// e.g., to check what containers do when they run out of memory partway through.
+#[cfg(any(test, feature = "testing"))]
+pub fn testing_fail_allocation_after(count: u64) {
+    TESTING_DATA.with_borrow_mut(|t| {
+        t.allocations_until_failure = Some(count);
+    });
+}
+
+/// Whether the allocation about to happen should fail; see `testing_fail_allocation_after`.
+#[cfg(any(test, feature = "testing"))]
+#[inline]
+pub fn testing_allocation_fails() -> bool {
+    TESTING_DATA.with_borrow_mut(|t| match t.allocations_until_failure {
+        None => false,
+        Some(0) => {
+            t.allocations_until_failure = None;
+            true
+        }
+        Some(count) => {
+            t.allocations_until_failure = Some(count - 1);
+            false
+        }
+    })
+}
+
+#[cfg(not(any(test, feature = "testing")))]
+#[inline]
+pub fn testing_allocation_fails() -> bool {
+    false
+}
+
+#[cfg(any(test, feature = "testing"))]
 pub fn testing_prints() -> Vec<Vec<u8>> {
     TESTING_DATA.with_borrow_mut(|t| {
         let mut result = vec![];
@@ -100,56 +166,320 @@ pub fn testing_prints() -> Vec<Vec<u8>> {
     })
 }
 
-#[cfg(test)]
-pub fn testing_unprint(mut expected_values: Vec<Vec<u8>>) {
-    let mut actual_values = testing_prints();
-    let same_len = actual_values.len() == expected_values.len();
-    let mut first_bad_i: i64 = -1;
-    for i in 0..actual_values.len().min(expected_values.len()) {
-        if actual_values[i] != expected_values[i] {
-            first_bad_i = i as i64;
-            break;
+#[cfg(any(test, feature = "testing"))]
+pub fn testing_unprint(expected_values: Vec<Vec<u8>>) {
+    let actual_values = testing_prints();
+    let first_bad_i = (0..actual_values.len().min(expected_values.len()))
+        .find(|&i| actual_values[i] != expected_values[i]);
+    if actual_values.len() != expected_values.len() || first_bad_i.is_some() {
+        testing_eprint_diff(&actual_values, &expected_values, first_bad_i);
+        panic!("left (actual) was not equal to right (expected)");
+    }
+}
+
+/// Prints `actual` and `expected` side by side, for when they don't match.
+#[cfg(any(test, feature = "testing"))]
+fn testing_eprint_diff(actual: &[Vec<u8>], expected: &[Vec<u8>], first_bad_i: Option<usize>) {
+    eprintln!("left (actual) was not equal to right (expected)");
+    let missing = Vec::from(b"{missing}");
+    for i in 0..actual.len().max(expected.len()) {
+        eprint!("{:02} ", i);
+        let left = actual.get(i).unwrap_or(&missing);
+        let right = expected.get(i).unwrap_or(&missing);
+        if let Ok(left) = std::str::from_utf8(left) {
+            eprint!("[actual]: {}    <->", left);
+        } else {
+            eprint!("[actual]: {:?}    <->", left);
+        }
+        if let Ok(right) = std::str::from_utf8(right) {
+            eprintln!("    [expected]: {}", right);
+        } else {
+            eprintln!("    [expected]: {:?}", right);
         }
     }
-    if !same_len || first_bad_i >= 0 {
-        eprintln!("left (actual) was not equal to right (expected)");
-        for i in 0..actual_values.len().max(expected_values.len()) {
-            eprint!("{:02} ", i);
-            let left = if i < actual_values.len() {
-                moot(&mut actual_values[i])
-            } else {
-                Vec::from(b"{missing}")
-            };
-            let right = if i < expected_values.len() {
-                moot(&mut expected_values[i])
-            } else {
-                Vec::from(b"{missing}")
-            };
-            if let Ok(left) = std::str::from_utf8(&left) {
-                eprint!("[actual]: {}    <->", left);
-            } else {
-                eprint!("[actual]: {:?}    <->", left);
+    if let Some(first_bad_i) = first_bad_i {
+        eprintln!("first different at index {}", first_bad_i);
+    }
+    if actual.len() != expected.len() {
+        eprintln!(
+            "left (actual) had count {} and right (expected) had count {}",
+            actual.len(),
+            expected.len()
+        );
+    }
+}
+
+/// Checks the prints since the last unprint, e.g.,
+/// `testing_unprint!["create(A: 39)", "noisy_new(1)"]`.  Each string can use `*`
+/// to match any bytes (including none), e.g., `"create(*: 39)"`, and a braced group
+/// like `{"noisy_drop(1)", "noisy_drop(2)"}` matches those prints in any order,
+/// e.g., for dropping things in hash order.  Starting with `contains` only checks
+/// that the expected prints show up in order, with anything else in between, e.g.,
+/// `testing_unprint![contains "delete(A)"]`.  On a mismatch, this prints actual and
+/// expected side by side (like `testing_unprint`) and panics.
+#[cfg(any(test, feature = "testing"))]
+#[macro_export]
+macro_rules! testing_unprint {
+    (contains $($expectation:tt),* $(,)?) => {
+        $crate::core::testing_unprint_matching(
+            $crate::core::TestingUnprintMode::ContainsInOrder,
+            vec![$($crate::testing_expectation!($expectation)),*],
+        )
+    };
+    ($($expectation:tt),* $(,)?) => {
+        $crate::core::testing_unprint_matching(
+            $crate::core::TestingUnprintMode::Exactly,
+            vec![$($crate::testing_expectation!($expectation)),*],
+        )
+    };
+}
+
+/// Helper for `testing_unprint!`.
+#[cfg(any(test, feature = "testing"))]
+#[doc(hidden)]
+#[macro_export]
+macro_rules! testing_expectation {
+    ({ $($print:expr),* $(,)? }) => {
+        $crate::core::TestingExpectation::Unordered(vec![
+            $(Vec::from(AsRef::<[u8]>::as_ref(&$print))),*
+        ])
+    };
+    ($print:expr) => {
+        $crate::core::TestingExpectation::Print(Vec::from(AsRef::<[u8]>::as_ref(&$print)))
+    };
+}
+
+#[cfg(any(test, feature = "testing"))]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum TestingExpectation {
+    /// One print, where `*` matches any bytes (including none).
+    Print(Vec<u8>),
+    /// Prints which can come in any order, each of which can use `*`.
+    Unordered(Vec<Vec<u8>>),
+}
+
+#[cfg(any(test, feature = "testing"))]
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum TestingUnprintMode {
+    /// All prints need to match, in order (except within unordered groups).
+    Exactly,
+    /// Expected prints need to show up in order, but other prints can come
+    /// before, after, or in between.
+    ContainsInOrder,
+}
+
+#[cfg(any(test, feature = "testing"))]
+impl TestingExpectation {
+    fn patterns(&self) -> &[Vec<u8>] {
+        match self {
+            TestingExpectation::Print(pattern) => std::slice::from_ref(pattern),
+            TestingExpectation::Unordered(patterns) => patterns,
+        }
+    }
+
+    /// How to show `pattern` (from `self`) in the diff.
+    fn label(&self, pattern: &[u8]) -> Vec<u8> {
+        let mut label = Vec::from(pattern);
+        if let TestingExpectation::Unordered(_) = self {
+            label.extend_from_slice(b"    {any order}");
+        }
+        label
+    }
+
+    /// Tries to match all of `self` against `actual`, returning which print
+    /// each pattern matched.
+    fn match_prints(&self, actual: &[Vec<u8>]) -> Option<Vec<usize>> {
+        let patterns = self.patterns();
+        // For each print, which pattern it was matched with.
+        let mut matched_pattern: Vec<Option<usize>> = vec![None; actual.len()];
+        for pattern_index in 0..patterns.len() {
+            let mut visited = vec![false; actual.len()];
+            if !Self::augment(
+                patterns,
+                actual,
+                pattern_index,
+                &mut visited,
+                &mut matched_pattern,
+            ) {
+                return None;
             }
-            if let Ok(right) = std::str::from_utf8(&right) {
-                eprintln!("    [expected]: {}", right);
-            } else {
-                eprintln!("    [expected]: {:?}", right);
+        }
+        let mut matched_print = vec![0; patterns.len()];
+        for (print_index, pattern_index) in matched_pattern.iter().enumerate() {
+            if let Some(pattern_index) = pattern_index {
+                matched_print[*pattern_index] = print_index;
             }
         }
-        if first_bad_i >= 0 {
-            eprintln!("first different at index {}", first_bad_i);
+        Some(matched_print)
+    }
+
+    /// Finds a print for `pattern_index`, possibly moving other patterns to
+    /// different prints, since a wildcard pattern can match more than one print.
+    fn augment(
+        patterns: &[Vec<u8>],
+        actual: &[Vec<u8>],
+        pattern_index: usize,
+        visited: &mut Vec<bool>,
+        matched_pattern: &mut Vec<Option<usize>>,
+    ) -> bool {
+        for print_index in 0..actual.len() {
+            if visited[print_index]
+                || !testing_pattern_matches(&patterns[pattern_index], &actual[print_index])
+            {
+                continue;
+            }
+            visited[print_index] = true;
+            let available = match matched_pattern[print_index] {
+                None => true,
+                Some(other) => Self::augment(patterns, actual, other, visited, matched_pattern),
+            };
+            if available {
+                matched_pattern[print_index] = Some(pattern_index);
+                return true;
+            }
         }
-        if !same_len {
-            eprintln!(
-                "left (actual) had count {} and right (expected) had count {}",
-                actual_values.len(),
-                expected_values.len()
-            );
+        false
+    }
+}
+
+/// Whether `print` matches `pattern`, where `*` matches any bytes (including none).
+#[cfg(any(test, feature = "testing"))]
+pub fn testing_pattern_matches(pattern: &[u8], print: &[u8]) -> bool {
+    let (mut p, mut q) = (0, 0);
+    // Where to resume after the last `*` if the rest doesn't match.
+    let mut star: Option<(usize, usize)> = None;
+    while q < print.len() {
+        if p < pattern.len() && pattern[p] == b'*' {
+            star = Some((p, q));
+            p += 1;
+        } else if p < pattern.len() && pattern[p] == print[q] {
+            p += 1;
+            q += 1;
+        } else if let Some((star_p, star_q)) = star {
+            p = star_p + 1;
+            q = star_q + 1;
+            star = Some((star_p, q));
+        } else {
+            return false;
+        }
+    }
+    pattern[p..].iter().all(|byte| *byte == b'*')
+}
+
+/// Checks the prints since the last unprint against `expected`; see `testing_unprint!`.
+#[cfg(any(test, feature = "testing"))]
+pub fn testing_unprint_matching(mode: TestingUnprintMode, expected: Vec<TestingExpectation>) {
+    let actual = testing_prints();
+    // What to show next to each actual print, if anything fails.
+    let mut expected_column: Vec<Vec<u8>> = vec![];
+    let mut start = 0;
+    for (index, expectation) in expected.iter().enumerate() {
+        let count = expectation.patterns().len();
+        let found = match mode {
+            TestingUnprintMode::Exactly => (start + count <= actual.len())
+                .then(|| expectation.match_prints(&actual[start..start + count]))
+                .flatten()
+                .map(|matched| (start, start + count, matched)),
+            TestingUnprintMode::ContainsInOrder => (start + count..=actual.len())
+                .find_map(|end| {
+                    expectation
+                        .match_prints(&actual[start..end])
+                        .map(|matched| (end, matched))
+                })
+                .map(|(end, matched)| (start, end, matched)),
+        };
+        let Some((window_start, window_end, matched)) = found else {
+            for expectation in &expected[index..] {
+                for pattern in expectation.patterns() {
+                    expected_column.push(expectation.label(pattern));
+                }
+            }
+            let first_bad_i = match mode {
+                TestingUnprintMode::Exactly => Some(start),
+                TestingUnprintMode::ContainsInOrder => None,
+            };
+            testing_eprint_diff(&actual, &expected_column, first_bad_i);
+            match mode {
+                TestingUnprintMode::Exactly => {
+                    panic!("left (actual) was not equal to right (expected)")
+                }
+                TestingUnprintMode::ContainsInOrder => {
+                    panic!("left (actual) did not contain right (expected) in order")
+                }
+            }
+        };
+        // Line up each pattern next to its print.
+        let mut window_column = vec![Vec::new(); window_end - window_start];
+        for (pattern, print_index) in expectation.patterns().iter().zip(matched) {
+            window_column[print_index] = expectation.label(pattern);
         }
+        expected_column.append(&mut window_column);
+        start = window_end;
+    }
+    if mode == TestingUnprintMode::Exactly && start < actual.len() {
+        testing_eprint_diff(&actual, &expected_column, Some(start));
         panic!("left (actual) was not equal to right (expected)");
     }
 }
 
+/// A fresh directory for a test, removed (with everything in it) when dropped.
+/// E.g., `TestingDirectory::new("file-watcher-renames")` for a test in `file_watcher.rs`,
+/// since names need to be unique across tests running in parallel.  The process
+/// ID is included too, since the unit tests run in two binaries at once.
+#[cfg(any(test, feature = "testing"))]
+pub struct TestingDirectory {
+    path: std::path::PathBuf,
+}
+
+#[cfg(any(test, feature = "testing"))]
+impl TestingDirectory {
+    pub fn new(name: &str) -> Self {
+        let path = std::env::temp_dir().join(format!("oh-{}-{}", name, std::process::id()));
+        // In case a crashed run left it behind.
+        let _ = std::fs::remove_dir_all(&path);
+        std::fs::create_dir_all(&path).expect("ok");
+        Self { path }
+    }
+
+    /// Creates empty files (and their directories) inside, where a trailing
+    /// separator makes just a directory, e.g., `["a/b.oh", "c/"]`.
+    pub fn create_files(&self, files: &[&str]) {
+        for file in files {
+            let path = self.path.join(file);
+            if file.ends_with('/') {
+                std::fs::create_dir_all(&path).expect("ok");
+            } else {
+                std::fs::create_dir_all(path.parent().expect("ok")).expect("ok");
+                std::fs::write(&path, b"").expect("ok");
+            }
+        }
+    }
+}
+
+#[cfg(any(test, feature = "testing"))]
+impl std::ops::Deref for TestingDirectory {
+    type Target = std::path::Path;
+
+    fn deref(&self) -> &std::path::Path {
+        &self.path
+    }
+}
+
+#[cfg(any(test, feature = "testing"))]
+impl AsRef<std::path::Path> for TestingDirectory {
+    fn as_ref(&self) -> &std::path::Path {
+        &self.path
+    }
+}
+
+#[cfg(any(test, feature = "testing"))]
+impl Drop for TestingDirectory {
+    fn drop(&mut self) {
+        // Best effort, e.g., the test might have removed it already.
+        let _ = std::fs::remove_dir_all(&self.path);
+    }
+}
+
 pub enum TestingPointer<S: SignedPrimitive, T> {
     One(*const T),
     Count(*const T, Count<S>),
@@ -183,13 +513,17 @@ impl<S: SignedPrimitive, T> TestingPointer<S, T> {
     }
 }
 
-#[cfg(test)]
+#[cfg(any(test, feature = "testing"))]
 #[inline]
 pub fn testing_name_pointer<S: SignedPrimitive, T>(what: TestingPointer<S, T>) {
     let pointer = what.pointer() as usize;
     TESTING_DATA.with_borrow_mut(|t| {
         if t.pointer_names.contains_key(&pointer) {
-            panic!("already created a name for pointer {}", pointer);
+            // E.g., the pointer was freed on another thread, and then reused.
+            testing_pointer_name_mismatch(format_args!(
+                "already created a name for pointer {}",
+                pointer
+            ));
         }
         let name_index = t.next_pointer_name_index;
         t.pointer_names.insert(pointer, name_index);
@@ -197,38 +531,39 @@ pub fn testing_name_pointer<S: SignedPrimitive, T>(what: TestingPointer<S, T>) {
         let mut print = Vec::from(b"create(");
         print.append(&mut what.name_label(u64_name(name_index)));
         print.push(b')');
-        t.prints.push(print);
+        t.push_print(print);
     });
 }
 
-#[cfg(not(test))]
+#[cfg(not(any(test, feature = "testing")))]
 #[inline]
 pub fn testing_name_pointer<S: SignedPrimitive, T>(_what: TestingPointer<S, T>) {}
 
-#[cfg(test)]
+#[cfg(any(test, feature = "testing"))]
 #[inline]
 pub fn testing_unname_pointer<T>(pointer: *const T) {
     let pointer = pointer as usize;
     TESTING_DATA.with_borrow_mut(|t| {
+        let mut print = Vec::from(b"delete(");
         if let Some(removed_index) = t.pointer_names.remove(&pointer) {
-            let mut print = Vec::from(b"delete(");
             print.append(&mut u64_name(removed_index));
-            print.push(b')');
-            t.prints.push(print);
         } else {
-            panic!(
+            testing_pointer_name_mismatch(format_args!(
                 "didn't start with a name for pointer {}, call `name_pointer` first",
                 pointer
-            );
+            ));
+            print.push(b'?');
         }
+        print.push(b')');
+        t.push_print(print);
     });
 }
 
-#[cfg(not(test))]
+#[cfg(not(any(test, feature = "testing")))]
 #[inline]
 pub fn testing_unname_pointer<T>(_pointer: *const T) {}
 
-#[cfg(test)]
+#[cfg(any(test, feature = "testing"))]
 #[inline]
 pub fn testing_pointer_name<T>(pointer: *const T) -> Vec<u8> {
     let pointer = pointer as usize;
@@ -254,6 +589,17 @@ fn u64_name(name_index: u64) -> Vec<u8> {
 mod test {
     use super::*;
 
+    #[test]
+    fn testing_directories_are_removed_when_dropped() {
+        let path = {
+            let directory = TestingDirectory::new("testing-directory");
+            std::fs::create_dir(directory.join("nested")).expect("ok");
+            std::fs::write(directory.join("nested/file.txt"), b"hi").expect("ok");
+            directory.to_path_buf()
+        };
+        assert!(!path.exists());
+    }
+
     #[test]
     fn noisy_makes_noise() {
         {
@@ -321,6 +667,76 @@ mod test {
         testing_unprint(vec![Vec::from(b"delete(A)")]);
     }
 
+    #[test]
+    fn unprint_macro_matches_literals_and_wildcards() {
+        let x: u8 = 13;
+        testing_name_pointer(TestingPointer::Count(&x, Count8::of(39).expect("ok")));
+        testing_print(b"noisy_new(1)");
+        crate::testing_unprint!["create(A: 39)", b"noisy_new(1)"];
+
+        testing_print(b"create(AB: 39)");
+        testing_print(b"anything at all");
+        testing_print(b"");
+        crate::testing_unprint!["create(*: 39)", "*", "*"];
+        crate::testing_unprint![];
+    }
+
+    #[test]
+    fn unprint_macro_allows_unordered_groups() {
+        for print in ["first", "drop(2)", "drop(1)", "drop(3)", "last"] {
+            testing_print(print.as_bytes());
+        }
+        crate::testing_unprint!["first", {"drop(1)", "drop(*)", "drop(2)"}, "last"];
+    }
+
+    #[test]
+    fn unprint_macro_can_check_for_contains_in_order() {
+        for print in ["a", "b", "c", "d", "e"] {
+            testing_print(print.as_bytes());
+        }
+        crate::testing_unprint![contains "b", {"e", "d"}];
+        testing_print(b"f");
+        crate::testing_unprint![contains];
+    }
+
+    #[test]
+    #[should_panic(expected = "did not contain")]
+    fn unprint_macro_contains_fails_out_of_order() {
+        testing_print(b"a");
+        testing_print(b"b");
+        crate::testing_unprint![contains "b", "a"];
+    }
+
+    #[test]
+    #[should_panic(expected = "not equal")]
+    fn unprint_macro_fails_with_extra_prints() {
+        testing_print(b"a");
+        testing_print(b"b");
+        crate::testing_unprint!["a"];
+    }
+
+    #[test]
+    #[should_panic(expected = "not equal")]
+    fn unprint_macro_fails_with_mismatched_group() {
+        testing_print(b"drop(1)");
+        testing_print(b"drop(2)");
+        crate::testing_unprint![{"drop(2)", "drop(3)"}];
+    }
+
+    #[test]
+    fn patterns_match_with_wildcards() {
+        assert!(testing_pattern_matches(b"create(*: 39)", b"create(A: 39)"));
+        assert!(testing_pattern_matches(b"create(*: 39)", b"create(: 39)"));
+        assert!(testing_pattern_matches(b"*a*b", b"xxaxxab"));
+        assert!(testing_pattern_matches(b"**", b""));
+        assert!(!testing_pattern_matches(
+            b"create(*: 39)",
+            b"create(A: 390)"
+        ));
+        assert!(!testing_pattern_matches(b"a*b", b"abc"));
+        assert!(!testing_pattern_matches(b"", b"a"));
+    }
+
     #[test]
     #[should_panic]
     fn getting_pointer_names_fails_without_first_naming() {
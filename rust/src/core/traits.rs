@@ -21,6 +21,14 @@ impl<T: Clone> TryClone for T {
     }
 }
 
+/// For containers which can copy themselves into a bigger buffer, e.g., so that a
+/// copy-on-write array which is about to grow doesn't copy and then reallocate.
+pub trait TryCloneWithCapacity: TryClone + Sized {
+    /// Clones into a buffer with room for at least `capacity` elements
+    /// (and at least the current count).
+    fn try_clone_with_capacity(&self, capacity: usize) -> Result<Self, Self::Error>;
+}
+
 pub trait GetCount<S: SignedPrimitive> {
     fn count(&self) -> Count<S>;
 }
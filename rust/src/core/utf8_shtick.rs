@@ -0,0 +1,312 @@
+use crate::core::shtick::*;
+
+pub use crate::core::array::*;
+
+pub type Utf8ShtickOptimized8 = Utf8ShtickOptimized<i8, 15>;
+pub type Utf8ShtickOptimized16 = Utf8ShtickOptimized<i16, 14>;
+pub type Utf8ShtickOptimized32 = Utf8ShtickOptimized<i32, 12>;
+pub type Utf8ShtickOptimized64 = Utf8ShtickOptimized<i64, 16>;
+
+#[derive(Eq, PartialEq, Copy, Clone, Debug, Hash)]
+pub enum Utf8Error {
+    /// Program ran out of memory.
+    OutOfMemory,
+    /// Bytes are not valid UTF-8, starting at this byte offset.
+    InvalidAt(usize),
+}
+
+pub type Utf8Result<T> = Result<T, Utf8Error>;
+
+impl From<ContainerError> for Utf8Error {
+    fn from(_error: ContainerError) -> Self {
+        Utf8Error::OutOfMemory
+    }
+}
+
+/// A `ShtickOptimized` which is guaranteed to hold valid UTF-8, so that we can
+/// work with runes (i.e., code points, or `char`s in Rust).  Use the plain
+/// `ShtickOptimized` for bytes which might not be valid UTF-8; converting from
+/// bytes requires validation (`from_utf8`) or an explicit `unsafe` opt-out
+/// (`from_utf8_unchecked`).
+#[derive(Eq, PartialEq)]
+pub struct Utf8ShtickOptimized<S: SignedPrimitive, const N_LOCAL: usize> {
+    bytes: ShtickOptimized<S, N_LOCAL>,
+}
+
+impl<S: SignedPrimitive, const N_LOCAL: usize> Default for Utf8ShtickOptimized<S, N_LOCAL> {
+    fn default() -> Self {
+        Self {
+            bytes: ShtickOptimized::default(),
+        }
+    }
+}
+
+impl<S: SignedPrimitive, const N_LOCAL: usize> Utf8ShtickOptimized<S, N_LOCAL> {
+    /// Returns the byte offset of the first invalid sequence in an error
+    /// if `bytes` isn't valid UTF-8.
+    pub fn from_utf8(bytes: ShtickOptimized<S, N_LOCAL>) -> Utf8Result<Self> {
+        match std::str::from_utf8(&bytes) {
+            Ok(_) => Ok(Self { bytes }),
+            Err(error) => Err(Utf8Error::InvalidAt(error.valid_up_to())),
+        }
+    }
+
+    /// # Safety
+    /// `bytes` must be valid UTF-8.
+    pub unsafe fn from_utf8_unchecked(bytes: ShtickOptimized<S, N_LOCAL>) -> Self {
+        debug_assert!(std::str::from_utf8(&bytes).is_ok());
+        Self { bytes }
+    }
+
+    pub fn as_str(&self) -> &str {
+        // We only construct `Self` from valid UTF-8 and only modify with valid UTF-8.
+        unsafe { std::str::from_utf8_unchecked(&self.bytes) }
+    }
+
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.bytes
+    }
+
+    /// # Safety
+    /// The caller must leave the bytes as valid UTF-8 once the borrow ends.
+    pub unsafe fn as_bytes_mut(&mut self) -> &mut ShtickOptimized<S, N_LOCAL> {
+        &mut self.bytes
+    }
+
+    pub fn into_bytes(self) -> ShtickOptimized<S, N_LOCAL> {
+        self.bytes
+    }
+
+    /// Iterates over runes; use `.rev()` to go backwards.
+    pub fn runes(&self) -> std::str::Chars<'_> {
+        self.as_str().chars()
+    }
+
+    /// Iterates over runes along with their starting byte offset.
+    pub fn rune_offsets(&self) -> std::str::CharIndices<'_> {
+        self.as_str().char_indices()
+    }
+
+    /// Number of runes, which is at most the number of bytes (`count()`).
+    pub fn rune_count(&self) -> usize {
+        // Every rune has exactly one byte that isn't a continuation byte (0b10xx_xxxx).
+        self.bytes
+            .iter()
+            .filter(|byte| (**byte as i8) >= -0x40)
+            .count()
+    }
+
+    pub fn rune_at(&self, rune_index: usize) -> Option<char> {
+        self.runes().nth(rune_index)
+    }
+
+    /// Slices by rune index rather than by byte offset, e.g., `1..3` are the second and
+    /// third runes.  Returns `None` if the range goes past the end of the runes.
+    pub fn rune_slice(&self, rune_range: std::ops::Range<usize>) -> Option<&str> {
+        if rune_range.start > rune_range.end {
+            return None;
+        }
+        let start = self.rune_to_byte_offset(rune_range.start)?;
+        let end = start
+            + Self::nth_rune_byte_offset(
+                &self.as_str()[start..],
+                rune_range.end - rune_range.start,
+            )?;
+        Some(&self.as_str()[start..end])
+    }
+
+    /// Byte offset of the rune at `rune_index`; `rune_count()` gives `count()`.
+    pub fn rune_to_byte_offset(&self, rune_index: usize) -> Option<usize> {
+        Self::nth_rune_byte_offset(self.as_str(), rune_index)
+    }
+
+    pub fn count(&self) -> CountMax {
+        self.bytes.count()
+    }
+
+    /// Looking for `fn push(rune)`?  use `insert(OrderedInsert::AtEnd(rune))`.
+    pub fn insert(&mut self, insert: OrderedInsert<char>) -> Containered {
+        match insert {
+            OrderedInsert::AtEnd(rune) => {
+                let mut buffer = [0u8; 4];
+                self.insert_str(rune.encode_utf8(&mut buffer))
+            }
+        }
+    }
+
+    /// Looking for `fn push_str(string)`?
+    pub fn insert_str(&mut self, string: &str) -> Containered {
+        self.bytes.insert_few(OrderedInsertFew::AtEnd(
+            string.as_bytes(),
+            TypeMarker,
+            TypeMarker,
+        ))
+    }
+
+    /// Looking for `fn pop`? use `remove(OrderedRemove::Last)`.  Removes a whole rune.
+    pub fn remove(&mut self, remove: OrderedRemove) -> Option<char> {
+        match remove {
+            OrderedRemove::Last => {
+                let rune = self.runes().next_back()?;
+                for _ in 0..rune.len_utf8() {
+                    self.bytes.remove(OrderedRemove::Last);
+                }
+                Some(rune)
+            }
+        }
+    }
+
+    fn nth_rune_byte_offset(string: &str, rune_index: usize) -> Option<usize> {
+        if rune_index == 0 {
+            return Some(0);
+        }
+        match string.char_indices().nth(rune_index) {
+            Some((offset, _)) => Some(offset),
+            None => {
+                // Allow slicing up to the end.
+                if string.chars().count() == rune_index {
+                    Some(string.len())
+                } else {
+                    None
+                }
+            }
+        }
+    }
+}
+
+impl<S: SignedPrimitive, const N_LOCAL: usize> std::ops::Deref for Utf8ShtickOptimized<S, N_LOCAL> {
+    type Target = str;
+
+    fn deref(&self) -> &str {
+        self.as_str()
+    }
+}
+
+impl<S: SignedPrimitive, const N_LOCAL: usize> TryFrom<&str> for Utf8ShtickOptimized<S, N_LOCAL> {
+    type Error = ContainerError;
+
+    fn try_from(value: &str) -> ContainerResult<Self> {
+        Ok(Self {
+            bytes: ShtickOptimized::try_from(value)?,
+        })
+    }
+}
+
+impl<S: SignedPrimitive, const N_LOCAL: usize> TryClone for Utf8ShtickOptimized<S, N_LOCAL> {
+    type Error = ContainerError;
+
+    fn try_clone(&self) -> ContainerResult<Self> {
+        Ok(Self {
+            bytes: self.bytes.try_clone()?,
+        })
+    }
+}
+
+impl<S: SignedPrimitive, const N_LOCAL: usize> std::fmt::Debug for Utf8ShtickOptimized<S, N_LOCAL> {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(
+            f,
+            "Utf8ShtickOptimized{}::try_from({:?})",
+            S::BITS,
+            self.as_str()
+        )
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn from_utf8_reports_first_bad_offset() {
+        let valid = ShtickOptimized8::try_from("héllo").expect("ok");
+        let valid = Utf8ShtickOptimized8::from_utf8(valid).expect("ok");
+        assert_eq!(valid.as_str(), "héllo");
+
+        let mut invalid = ShtickOptimized8::try_from("ab").expect("ok");
+        invalid
+            .insert_few(OrderedInsertFew::AtEnd(
+                &[0xe2, 0x82, b'c'][..],
+                TypeMarker,
+                TypeMarker,
+            ))
+            .expect("ok");
+        assert_eq!(
+            Utf8ShtickOptimized8::from_utf8(invalid),
+            Err(Utf8Error::InvalidAt(2))
+        );
+
+        let lone_continuation = ShtickOptimized16::try_from("\u{80}")
+            .map(|mut bytes| {
+                bytes.remove(OrderedRemove::Last);
+                bytes
+            })
+            .expect("ok");
+        assert_eq!(
+            Utf8ShtickOptimized16::from_utf8(lone_continuation),
+            Err(Utf8Error::InvalidAt(0))
+        );
+    }
+
+    #[test]
+    fn runes_go_forwards_and_backwards() {
+        let shtick = Utf8ShtickOptimized32::try_from("añ€𝄞").expect("ok");
+        assert_eq!(shtick.count(), Count::of(10).expect("ok"));
+        assert_eq!(shtick.rune_count(), 4);
+        assert_eq!(shtick.runes().collect::<Vec<_>>(), vec!['a', 'ñ', '€', '𝄞']);
+        assert_eq!(
+            shtick.runes().rev().collect::<Vec<_>>(),
+            vec!['𝄞', '€', 'ñ', 'a']
+        );
+        assert_eq!(
+            shtick
+                .rune_offsets()
+                .map(|(offset, _)| offset)
+                .collect::<Vec<_>>(),
+            vec![0, 1, 3, 6]
+        );
+        assert_eq!(shtick.rune_at(2), Some('€'));
+        assert_eq!(shtick.rune_at(4), None);
+    }
+
+    #[test]
+    fn rune_slicing() {
+        let shtick = Utf8ShtickOptimized8::try_from("añ€𝄞").expect("ok");
+        assert_eq!(shtick.rune_slice(1..3), Some("ñ€"));
+        assert_eq!(shtick.rune_slice(0..4), Some("añ€𝄞"));
+        assert_eq!(shtick.rune_slice(4..4), Some(""));
+        assert_eq!(shtick.rune_slice(3..5), None);
+        assert_eq!(shtick.rune_slice(5..5), None);
+        assert_eq!(shtick.rune_to_byte_offset(3), Some(6));
+        assert_eq!(shtick.rune_to_byte_offset(4), Some(10));
+    }
+
+    #[test]
+    fn insert_and_remove_whole_runes() {
+        let mut shtick = Utf8ShtickOptimized64::default();
+        shtick.insert(OrderedInsert::AtEnd('o')).expect("ok");
+        shtick.insert(OrderedInsert::AtEnd('ħ')).expect("ok");
+        shtick.insert_str(" 🙂").expect("ok");
+        assert_eq!(&*shtick, "oħ 🙂");
+        assert_eq!(shtick.remove(OrderedRemove::Last), Some('🙂'));
+        assert_eq!(shtick.remove(OrderedRemove::Last), Some(' '));
+        assert_eq!(shtick.remove(OrderedRemove::Last), Some('ħ'));
+        assert_eq!(shtick.as_bytes(), b"o");
+        assert_eq!(
+            format!("{:?}", shtick),
+            "Utf8ShtickOptimized64::try_from(\"o\")"
+        );
+    }
+
+    #[test]
+    fn unchecked_bytes() {
+        let mut shtick = Utf8ShtickOptimized8::try_from("abc").expect("ok");
+        unsafe {
+            shtick.as_bytes_mut()[1] = b'B';
+        }
+        assert_eq!(shtick.as_str(), "aBc");
+        let bytes = shtick.into_bytes();
+        let shtick = unsafe { Utf8ShtickOptimized8::from_utf8_unchecked(bytes) };
+        assert_eq!(shtick.try_clone().expect("ok"), shtick);
+    }
+}
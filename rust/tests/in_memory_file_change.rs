@@ -0,0 +1,126 @@
+use oh::core::*;
+
+fn open(path: &std::path::Path) -> InMemoryFile {
+    InMemoryFile::open(FilePath::try_from(path).expect("ok")).expect("ok")
+}
+
+/// Moves the modification time so changes are visible even on file systems
+/// with coarse timestamps.
+fn set_modified(path: &std::path::Path, seconds_ago: u64) {
+    let time = std::time::SystemTime::now() - std::time::Duration::from_secs(seconds_ago);
+    std::fs::File::options()
+        .write(true)
+        .open(path)
+        .expect("ok")
+        .set_modified(time)
+        .expect("ok");
+}
+
+#[test]
+fn test_unchanged_file() {
+    let directory = TestingDirectory::new("in-memory-file-change-unchanged");
+    let path = directory.join("file.txt");
+    std::fs::write(&path, b"hello\n").expect("ok");
+    let mut file = open(&path);
+    assert_eq!(file.has_changed(), Ok(false));
+    assert_eq!(file.reload_if_changed(), Ok(false));
+
+    // Touching without editing doesn't count as a change.
+    set_modified(&path, 100);
+    assert_eq!(file.has_changed(), Ok(false));
+}
+
+#[test]
+fn test_changed_file() {
+    let directory = TestingDirectory::new("in-memory-file-change-changed");
+    let path = directory.join("file.txt");
+    std::fs::write(&path, b"hello\n").expect("ok");
+    set_modified(&path, 100);
+    let mut file = open(&path);
+
+    // Same size, different contents.
+    std::fs::write(&path, b"howdy\n").expect("ok");
+    assert_eq!(file.has_changed(), Ok(true));
+    assert_eq!(file.reload_if_changed(), Ok(true));
+    assert_eq!(&file.lines[..], ["howdy"]);
+    assert_eq!(file.has_changed(), Ok(false));
+
+    std::fs::write(&path, b"howdy\nthere\n").expect("ok");
+    assert_eq!(file.has_changed(), Ok(true));
+
+    std::fs::remove_file(&path).expect("ok");
+    assert_eq!(file.has_changed(), Ok(true));
+    assert!(matches!(
+        file.reload_if_changed(),
+        Err(FileError::Missing(_))
+    ));
+    file.missing_file = MissingFile::Empty;
+    assert_eq!(file.reload_if_changed(), Ok(true));
+    assert_eq!(&file.lines[..], [""; 0]);
+    assert_eq!(file.has_changed(), Ok(false));
+
+    std::fs::write(&path, b"").expect("ok");
+    assert_eq!(file.has_changed(), Ok(true));
+}
+
+#[test]
+fn test_write_conflict() {
+    let directory = TestingDirectory::new("in-memory-file-change-conflict");
+    let path = directory.join("file.txt");
+    std::fs::write(&path, b"original\n").expect("ok");
+    set_modified(&path, 100);
+    let mut file = open(&path);
+    file.lines[0] = FileLine::try_from("ours").expect("ok");
+
+    std::fs::write(&path, b"theirs\n").expect("ok");
+    match file.write() {
+        Err(FileError::Conflict(details)) => {
+            assert_eq!(details.operation, FileOperation::Write);
+            assert_eq!(details.path, file.path);
+            assert_eq!(details.os_error, None);
+        }
+        result => panic!("expected a conflict, got {:?}", result),
+    }
+    assert_eq!(std::fs::read(&path).expect("ok"), b"theirs\n");
+
+    assert_eq!(file.reload_if_changed(), Ok(true));
+    file.lines[0] = FileLine::try_from("ours").expect("ok");
+    file.write().expect("ok");
+    assert_eq!(file.has_changed(), Ok(false));
+    assert_eq!(std::fs::read(&path).expect("ok"), b"ours\n");
+
+    // Writing again after our own write isn't a conflict.
+    file.ends_with_newline = false;
+    file.write().expect("ok");
+    assert_eq!(std::fs::read(&path).expect("ok"), b"ours");
+}
+
+#[test]
+fn test_missing_file_created_elsewhere() {
+    let directory = TestingDirectory::new("in-memory-file-change-created");
+    let path = directory.join("file.txt");
+    let mut file =
+        InMemoryFile::open_or_empty(FilePath::try_from(path.as_path()).expect("ok")).expect("ok");
+    assert_eq!(file.has_changed(), Ok(false));
+    std::fs::write(&path, b"surprise\n").expect("ok");
+    assert!(matches!(file.write(), Err(FileError::Conflict(_))));
+}
+
+#[test]
+fn test_changed_directory() {
+    let directory = TestingDirectory::new("in-memory-file-change-directory");
+    std::fs::create_dir(directory.join("sub")).expect("ok");
+    std::fs::write(directory.join("b.oh"), b"").expect("ok");
+    let path = format!("{}/", directory.to_str().expect("ok"));
+    let mut file = InMemoryFile::open(FilePath::try_from(path.as_str()).expect("ok")).expect("ok");
+    assert_eq!(&file.lines[..], ["b.oh", "sub/"]);
+    assert_eq!(file.has_changed(), Ok(false));
+
+    // Editing a file doesn't change the directory's entries.
+    std::fs::write(directory.join("b.oh"), b"edited").expect("ok");
+    assert_eq!(file.has_changed(), Ok(false));
+
+    std::fs::write(directory.join("a.oh"), b"").expect("ok");
+    assert_eq!(file.reload_if_changed(), Ok(true));
+    assert_eq!(&file.lines[..], ["a.oh", "b.oh", "sub/"]);
+}
@@ -21,7 +21,7 @@ fn test_read_file() {
 fn test_read_empty_file() {
     let path = FilePath::try_from("tests/empty-file.txt").expect("ok");
     let file = InMemoryFile::open(path).expect("ok");
-    assert_eq!(&file.lines[..], []);
+    assert_eq!(&file.lines[..], [""; 0]);
 }
 
 #[test]
@@ -38,3 +38,144 @@ fn test_read_file_no_empty_lines() {
         ]
     );
 }
+
+#[test]
+fn test_read_directory() {
+    let path = FilePath::try_from("tests/").expect("ok");
+    let file = InMemoryFile::open(path).expect("ok");
+    assert!(file.lines.len() >= 4);
+    assert!(file.lines.iter().any(|line| line == "sample-file.txt"));
+    assert!(file.lines.is_sorted_by(|a, b| a[..] <= b[..]));
+
+    let path = FilePath::try_from("tests/missing-directory/").expect("ok");
+    let file = InMemoryFile::open_or_empty(path).expect("ok");
+    assert_eq!(&file.lines[..], [""; 0]);
+}
+
+#[test]
+fn test_read_missing_file() {
+    let path = FilePath::try_from("tests/missing-file.txt").expect("ok");
+    let error = InMemoryFile::open(path.try_clone().expect("ok"))
+        .err()
+        .expect("should be missing");
+    let details = error.details().expect("has details");
+    assert!(matches!(error, FileError::Missing(_)));
+    assert_eq!(details.operation, FileOperation::Open);
+    assert_eq!(details.path, path);
+    assert_eq!(
+        details.os_error.map(|os_error| os_error.kind),
+        Some(std::io::ErrorKind::NotFound)
+    );
+    assert!(
+        error
+            .to_string()
+            .starts_with("couldn't open `tests/missing-file.txt`: ")
+    );
+
+    let mut file = InMemoryFile::open_or_empty(path).expect("ok");
+    assert_eq!(&file.lines[..], [""; 0]);
+    assert!(file.ends_with_newline);
+    assert_eq!(file.missing_file, MissingFile::Empty);
+    // Re-reading resets everything back to a new file.
+    file.lines
+        .insert(OrderedInsert::AtEnd(
+            FileLine::try_from("edit").expect("ok"),
+        ))
+        .expect("ok");
+    file.ends_with_newline = false;
+    file.read().expect("ok");
+    assert_eq!(&file.lines[..], [""; 0]);
+    assert!(file.ends_with_newline);
+
+    let path = FilePath::try_from("tests/missing-directory/").expect("ok");
+    assert!(matches!(
+        InMemoryFile::open(path),
+        Err(FileError::Missing(_))
+    ));
+}
+
+#[test]
+fn test_read_line_endings() {
+    for (fixture, line_ending, ends_with_newline, lines) in [
+        (
+            "tests/sample-file.txt",
+            LineEnding::Lf,
+            true,
+            &[
+                "",
+                "This is a sample test file",
+                "",
+                "It will be used to verify that file-reading works.",
+                "That is pretty much it.",
+                "",
+            ][..],
+        ),
+        (
+            "tests/crlf-file.txt",
+            LineEnding::CrLf,
+            true,
+            &["first line", "second line", "", "last line"][..],
+        ),
+        (
+            "tests/mixed-line-endings-file.txt",
+            LineEnding::Mixed,
+            false,
+            &[
+                "lf line",
+                "crlf line",
+                "",
+                "another crlf",
+                "no final newline",
+            ][..],
+        ),
+        (
+            "tests/no-final-newline-file.txt",
+            LineEnding::Lf,
+            false,
+            &["just one line", "and another"][..],
+        ),
+        (
+            "tests/lone-carriage-return-file.txt",
+            LineEnding::CrLf,
+            false,
+            &["carriage\rreturn", "alone\r"][..],
+        ),
+        ("tests/empty-file.txt", LineEnding::Lf, false, &[][..]),
+    ] {
+        let file = InMemoryFile::open(FilePath::try_from(fixture).expect("ok")).expect("ok");
+        assert_eq!(&file.lines[..], lines, "{}", fixture);
+        assert_eq!(file.line_ending, line_ending, "{}", fixture);
+        assert_eq!(file.ends_with_newline, ends_with_newline, "{}", fixture);
+    }
+}
+
+#[test]
+fn test_read_encoding() {
+    let path = FilePath::try_from("tests/byte-order-mark-file.txt").expect("ok");
+    let file = InMemoryFile::open(path).expect("ok");
+    assert!(file.byte_order_mark);
+    assert_eq!(&file.lines[..], ["main(): null", "\tindented\u{a0}"]);
+    let issues: Vec<String> = file
+        .encoding_issues()
+        .expect("ok")
+        .iter()
+        .map(|issue| issue.to_string())
+        .collect();
+    assert_eq!(issues, ["2:1: tab", "2:10: non-standard whitespace U+00A0"]);
+
+    let path = FilePath::try_from("tests/sample-file.txt").expect("ok");
+    let file = InMemoryFile::open(path).expect("ok");
+    assert!(!file.byte_order_mark);
+    assert!(file.encoding_issues().expect("ok").is_empty());
+
+    let path = FilePath::try_from("tests/utf16-file.txt").expect("ok");
+    let error = InMemoryFile::open(path.try_clone().expect("ok"))
+        .err()
+        .expect("should be rejected");
+    assert!(matches!(error, FileError::Encoding(_)));
+    assert_eq!(error.details().expect("has details").path, path);
+    assert_eq!(
+        error.to_string(),
+        "couldn't read `tests/utf16-file.txt`: it isn't UTF-8"
+    );
+}
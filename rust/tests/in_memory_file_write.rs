@@ -0,0 +1,135 @@
+use oh::core::*;
+
+use std::sync::Arc;
+
+fn open(path: &std::path::Path) -> InMemoryFile {
+    InMemoryFile::open(FilePath::try_from(path).expect("ok")).expect("ok")
+}
+
+fn open_new(path: &std::path::Path) -> InMemoryFile {
+    InMemoryFile::open_or_empty(FilePath::try_from(path).expect("ok")).expect("ok")
+}
+
+/// `file.txt` with `contents`, in memory.
+fn memory_file(contents: &[u8]) -> (Arc<MemoryFilesystem>, InMemoryFile) {
+    let filesystem = Arc::new(MemoryFilesystem::default());
+    filesystem.insert_file("file.txt", contents).expect("ok");
+    let file = InMemoryFile::open_in(
+        filesystem.clone(),
+        FilePath::try_from("file.txt").expect("ok"),
+        MissingFile::Error,
+    )
+    .expect("ok");
+    (filesystem, file)
+}
+
+fn written(filesystem: &MemoryFilesystem) -> Vec<u8> {
+    let path = FilePath::try_from("file.txt").expect("ok");
+    filesystem.read(&path).expect("ok").to_vec()
+}
+
+fn round_trip(contents: &[u8]) {
+    let (filesystem, mut file) = memory_file(contents);
+    file.write().expect("ok");
+    assert_eq!(written(&filesystem), contents);
+}
+
+#[test]
+fn test_write_new_file() {
+    let directory = TestingDirectory::new("in-memory-file-write-new");
+    let path = directory.join("new.txt");
+    let mut file = open_new(&path);
+    assert_eq!(&file.lines[..], [""; 0]);
+    for line in ["hello", "", "world"] {
+        file.lines
+            .insert(OrderedInsert::AtEnd(FileLine::try_from(line).expect("ok")))
+            .expect("ok");
+    }
+    file.write().expect("ok");
+    assert_eq!(std::fs::read(&path).expect("ok"), b"hello\n\nworld\n");
+    assert_eq!(&open(&path).lines[..], ["hello", "", "world"]);
+
+    // The temporary file was renamed, so nothing else is left behind.
+    assert_eq!(std::fs::read_dir(&directory).expect("ok").count(), 1);
+}
+
+#[test]
+fn test_write_keeps_line_endings() {
+    round_trip(b"one\r\ntwo\r\n\r\n");
+    round_trip(b"one\r\ntwo\nthree");
+    round_trip(b"one\ntwo");
+    round_trip(b"\n");
+    round_trip(b"");
+    round_trip(b"\xef\xbb\xbf");
+}
+
+#[test]
+fn test_write_fixtures_exactly() {
+    for fixture in [
+        "sample-file.txt",
+        "empty-file.txt",
+        "non-empty-file.txt",
+        "crlf-file.txt",
+        "mixed-line-endings-file.txt",
+        "no-final-newline-file.txt",
+        "lone-carriage-return-file.txt",
+        "byte-order-mark-file.txt",
+    ] {
+        round_trip(&std::fs::read(format!("tests/{}", fixture)).expect("ok"));
+    }
+}
+
+#[test]
+fn test_write_with_line_ending() {
+    let (filesystem, mut file) = memory_file(b"a\r\nb\nc\r\n");
+    assert_eq!(file.line_ending, LineEnding::Mixed);
+    file.lines
+        .insert(OrderedInsert::AtEnd(FileLine::try_from("d").expect("ok")))
+        .expect("ok");
+    file.write().expect("ok");
+    assert_eq!(written(&filesystem), b"a\r\nb\nc\r\nd\n");
+
+    file.line_ending = LineEnding::CrLf;
+    file.write().expect("ok");
+    assert_eq!(written(&filesystem), b"a\r\nb\r\nc\r\nd\r\n");
+}
+
+#[test]
+fn test_write_replaces_contents() {
+    let (filesystem, mut file) = memory_file(b"old\ncontents\n");
+    file.lines[1] = FileLine::try_from("new contents").expect("ok");
+    file.ends_with_newline = false;
+    file.write().expect("ok");
+    assert_eq!(written(&filesystem), b"old\nnew contents");
+}
+
+#[test]
+fn test_write_into_missing_directory() {
+    let directory = TestingDirectory::new("in-memory-file-write-missing");
+    let mut file = open_new(&directory.join("missing/file.txt"));
+    match file.write() {
+        Err(FileError::Missing(details)) => {
+            assert_eq!(details.operation, FileOperation::Write);
+            assert!(details.os_error.is_some());
+        }
+        result => panic!("expected a missing directory, got {:?}", result),
+    }
+}
+
+#[test]
+fn test_write_directory() {
+    let directory = TestingDirectory::new("in-memory-file-write-directory");
+    let path = FilePath::try_from(format!("{}/", directory.display()).as_str()).expect("ok");
+    let mut file = InMemoryFile::open(path).expect("ok");
+    match file.write() {
+        Err(FileError::Write(details)) => {
+            assert_eq!(details.operation, FileOperation::Write);
+            assert_eq!(details.path, file.path);
+            assert_eq!(
+                details.os_error.map(|os_error| os_error.kind),
+                Some(std::io::ErrorKind::IsADirectory)
+            );
+        }
+        result => panic!("expected a directory error, got {:?}", result),
+    }
+}
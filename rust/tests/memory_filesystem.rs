@@ -0,0 +1,113 @@
+use oh::core::*;
+
+use std::sync::Arc;
+
+fn path(path: &str) -> FilePath {
+    FilePath::try_from(path).expect("ok")
+}
+
+/// A small project, entirely in memory.
+fn project() -> Arc<MemoryFilesystem> {
+    let filesystem = MemoryFilesystem::default();
+    for (file, contents) in [
+        ("project/main.oh", "main(): null\n"),
+        (
+            "project/lib/math.oh",
+            "add(X: int, Y: int): int\r\n    X + Y\r\n",
+        ),
+        ("project/lib/readme.md", "# math\n"),
+        ("project/.generated/main.oh", "generated\n"),
+    ] {
+        filesystem
+            .insert_file(file, contents.as_bytes())
+            .expect("ok");
+    }
+    Arc::new(filesystem)
+}
+
+#[test]
+fn test_finds_oh_files_in_memory() {
+    let filesystem = project();
+    let directory = DirectoryPath::try_from(path("project")).expect("ok");
+    let mut found = Vec::new();
+    let count = directory
+        .find_oh_files_in(&*filesystem, &DirectoryWalk::default(), |file| {
+            found.push(file.to_str().expect("ok").to_string());
+            Ok(())
+        })
+        .expect("ok");
+    assert_eq!(count, 2);
+    assert_eq!(found, vec!["project/main.oh", "project/lib/math.oh"]);
+}
+
+#[test]
+fn test_in_memory_file_round_trips() {
+    let filesystem = project();
+    let mut file = InMemoryFile::open_in(
+        filesystem.clone(),
+        path("project/lib/math.oh"),
+        MissingFile::Error,
+    )
+    .expect("ok");
+    assert_eq!(&file.lines[..], ["add(X: int, Y: int): int", "    X + Y"]);
+    assert_eq!(file.line_ending, LineEnding::CrLf);
+
+    file.lines
+        .insert(OrderedInsert::AtEnd(
+            FileLine::try_from("# done").expect("ok"),
+        ))
+        .expect("ok");
+    file.write().expect("ok");
+    assert_eq!(
+        &filesystem.read(&path("project/lib/math.oh")).expect("ok")[..],
+        b"add(X: int, Y: int): int\r\n    X + Y\r\n# done\r\n"
+    );
+    assert_eq!(file.has_changed(), Ok(false));
+
+    // Someone else edits the file, so we shouldn't clobber it.
+    filesystem
+        .insert_file("project/lib/math.oh", b"edited\n")
+        .expect("ok");
+    assert_eq!(file.has_changed(), Ok(true));
+    assert!(matches!(file.write(), Err(FileError::Conflict(_))));
+    assert_eq!(file.reload_if_changed(), Ok(true));
+    assert_eq!(&file.lines[..], ["edited"]);
+
+    let lines =
+        FileLines::read_in(&*filesystem, &path("project/main.oh"), MissingFile::Error).expect("ok");
+    assert_eq!(lines.iter().collect::<Vec<_>>(), vec![&b"main(): null"[..]]);
+}
+
+#[test]
+fn test_in_memory_directories_and_missing_files() {
+    let filesystem = project();
+    let mut directory =
+        InMemoryFile::open_in(filesystem.clone(), path("project/"), MissingFile::Error)
+            .expect("ok");
+    assert_eq!(&directory.lines[..], [".generated/", "lib/", "main.oh"]);
+
+    let mut file = InMemoryFile::open_in(
+        filesystem.clone(),
+        path("project/new.oh"),
+        MissingFile::Empty,
+    )
+    .expect("ok");
+    assert!(file.lines.is_empty());
+    file.lines
+        .insert(OrderedInsert::AtEnd(FileLine::try_from("new").expect("ok")))
+        .expect("ok");
+    file.write().expect("ok");
+    assert_eq!(directory.reload_if_changed(), Ok(true));
+    assert_eq!(
+        &directory.lines[..],
+        [".generated/", "lib/", "main.oh", "new.oh"]
+    );
+
+    match InMemoryFile::open_in(filesystem, path("project/missing.oh"), MissingFile::Error) {
+        Err(FileError::Missing(details)) => {
+            assert_eq!(details.path, path("project/missing.oh"));
+            assert_eq!(details.operation, FileOperation::Open);
+        }
+        _ => panic!("should be missing"),
+    }
+}
@@ -0,0 +1,55 @@
+use oh::core::*;
+
+#[test]
+fn test_allocations_are_traced() {
+    {
+        let mut array = NonLocalArrayCount32::<TestingNoisy>::default();
+        array
+            .set_capacity(Count::of(39).expect("ok"))
+            .expect("small alloc");
+        oh::testing_unprint!["create(A: 39)"];
+        array
+            .insert(OrderedInsert::AtEnd(TestingNoisy::new(5)))
+            .expect("already allocked");
+        oh::testing_unprint!["noisy_new(5)"];
+    }
+    oh::testing_unprint!["noisy_drop(5)", "delete(A)"];
+}
+
+#[test]
+fn test_pointers_are_named_per_thread() {
+    let mut array = NonLocalArrayCount32::<u8>::default();
+    array
+        .insert_few(OrderedInsertFew::AtEnd(&b"hi"[..], TypeMarker, TypeMarker))
+        .expect("small alloc");
+    assert_eq!(testing_pointer_name(array.as_ptr()), Vec::from("A"));
+    oh::testing_unprint!["create(*: 2)"];
+}
+
+#[test]
+fn test_pointers_freed_on_other_threads_are_unnamed() {
+    let value = 5u8;
+    testing_name_pointer(testing_pointer_one(&value as *const u8));
+    oh::testing_unprint!["create(A)"];
+    let address = &value as *const u8 as usize;
+    std::thread::spawn(move || {
+        // Doesn't panic, since this thread never named the pointer.
+        testing_unname_pointer(address as *const u8);
+        oh::testing_unprint!["delete(?)"];
+    })
+    .join()
+    .expect("ok");
+}
+
+#[test]
+fn test_unread_prints_are_limited() {
+    for i in 0..TESTING_PRINTS_LIMIT + 10 {
+        testing_print_string(format!("print {}", i));
+    }
+    let prints = testing_prints();
+    assert_eq!(prints.len(), TESTING_PRINTS_LIMIT / 2 + 10);
+    assert_eq!(
+        prints.last(),
+        Some(&Vec::from(format!("print {}", TESTING_PRINTS_LIMIT + 9)))
+    );
+}